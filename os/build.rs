@@ -104,6 +104,18 @@ app_{0}_end:"#,
                          idx, app, TARGET_PATH
                 ).unwrap();
         }
+
+        // names in the order of the apps, e.g. for per app permissions
+        writeln!(
+                 f,
+                 r#"
+    .section .rodata
+    .global _app_names
+_app_names:"#
+        ).unwrap();
+        for app in apps.iter() {
+                writeln!(f, r#"    .string "{}""#, app).unwrap();
+        }
         f
 }
 
@@ -134,6 +146,12 @@ SECTIONS
         *(.rodata .rodata.*)
         *(.srodata .srodata.*)
     }
+    . = ALIGN(8);
+    .ksyms : {
+        sksyms = .;
+        KEEP(*(.ksyms))
+        eksyms = .;
+    }
     . = ALIGN(4K);
     erodata = .;
 
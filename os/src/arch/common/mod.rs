@@ -1,4 +1,5 @@
 use crate::arch::riscv::*;
+use crate::harts::HartState;
 use crate::trap::fast::{ FastContext, FastResult };
 
 // kernel entry
@@ -11,6 +12,8 @@ pub use crate::arch::riscv::entry;
 pub trait ArchMem {
 	unsafe fn fencei(&self);
 	fn unwind(&self);
+	/// flush the TLB entries of `vaddr` in every address space, or all entries, on this hart
+	fn flush_tlb(&self, vaddr: Option<usize>);
 }
 
 pub trait ArchPower {
@@ -25,12 +28,37 @@ pub trait ArchTime {
 	fn time_ms(&self) -> u64;
 	fn time_s(&self) -> u64;
 	fn set_next_timer_intr(&self, dur_ms: usize);
+	/// a timer interrupt is pending, i.e. the tick of the running task is over
+	fn timer_pending(&self) -> bool;
 }
 
 pub trait ArchHarts {
 	fn exchange_scratch(&self, val: usize) -> usize;
 	fn get_scratch(&self) -> usize;
-	fn hart_start(&self, hartid: usize, start_addr: usize, opaque: usize); //TODO: may be error
+	/// start a stopped hart at `start_addr`, return false if the SBI refused
+	fn hart_start(&self, hartid: usize, start_addr: usize, opaque: usize) -> bool;
+	/// stop this hart, it only returns if the SBI refused
+	fn hart_stop(&self);
+	fn hart_status(&self, hartid: usize) -> HartState;
+	/// per hart state every task relies on, set before the hart runs any task
+	fn hart_init(&self);
+	/// raise a software interrupt on `hartid`
+	fn send_ipi(&self, hartid: usize);
+	/// take the software interrupt of this hart
+	fn clear_ipi(&self);
+	/// sleep until an interrupt is pending, it is not taken with interrupts off
+	fn wait_for_interrupt(&self);
+	/// stop this hart for good, with interrupts off
+	fn park(&self) -> !;
+	/// turn interrupts off on this hart, return whether they were on
+	fn irq_save(&self) -> bool;
+	/// turn interrupts back on if `irq_save` found them on
+	fn irq_restore(&self, was_on: bool);
+}
+
+pub trait ArchSwitch {
+	/// save the running kernel flow into `cur` and resume `next`
+	unsafe fn switch(&self, cur: *mut KernelContext, next: *const KernelContext);
 }
 
 pub trait ArchTrap {
@@ -56,13 +84,20 @@ pub trait ArchTrap {
 		a7: usize,
 	) -> FastResult;
 	// app boot entry
-	unsafe extern "C" fn boot_entry(a0: usize, a1: usize) -> !;
+	unsafe extern "C" fn boot_entry(a0: usize, a1: usize, a2: usize) -> !;
 	// app boot prepare
 	extern "C" fn boot_handler(entry: usize, trampoline: usize, utraph: usize);
+	// ra and sp of the caller, read before it calls anything else
+	fn caller_regs(&self) -> [usize; 2];
+	// sepc, scause and stval of the last trap on this hart
+	fn trap_regs(&self) -> [usize; 3];
 }
 
 #[cfg(target_arch = "riscv64")]
 pub type Arch = Riscv64<RiscvCommon>;
 
 #[cfg(target_arch = "riscv64")]
-pub use crate::arch::riscv::trap::FlowContext as FlowContext;
\ No newline at end of file
+pub use crate::arch::riscv::trap::FlowContext as FlowContext;
+
+#[cfg(target_arch = "riscv64")]
+pub use crate::arch::riscv::switch::KernelContext as KernelContext;
\ No newline at end of file
@@ -0,0 +1,50 @@
+//! CSRs of the advanced interrupt architecture, which the riscv crate lacks.
+//!
+//! They go by number, 0x150 siselect, 0x151 sireg and 0x15c stopei, so the
+//! kernel needs no assembler support for Ssaia.
+use core::arch::asm;
+
+/// registers of the S mode interrupt file behind `siselect`
+pub const EIDELIVERY: usize = 0x70;
+pub const EITHRESHOLD: usize = 0x72;
+/// eie0, on RV64 only the even ones exist and hold 64 ids each
+pub const EIE0: usize = 0xc0;
+
+/// Write `val` to the indirect register `reg` of this hart's interrupt file.
+pub fn ireg_write(reg: usize, val: usize) {
+	unsafe {
+		asm!(
+			"csrw 0x150, {reg}",
+			"csrw 0x151, {val}",
+			reg = in(reg) reg,
+			val = in(reg) val,
+		);
+	}
+}
+
+pub fn ireg_read(reg: usize) -> usize {
+	let val;
+	unsafe {
+		asm!(
+			"csrw 0x150, {reg}",
+			"csrr {val}, 0x151",
+			reg = in(reg) reg,
+			val = out(reg) val,
+		);
+	}
+	val
+}
+
+/// Take the highest pending interrupt identity of this hart, clearing its
+/// pending bit, None if there is none.
+pub fn claim_ei() -> Option<usize> {
+	let topei: usize;
+	unsafe {
+		// a write of any value claims what was read
+		asm!("csrrw {}, 0x15c, zero", out(reg) topei);
+	}
+	match topei >> 16 {
+		0 => None,
+		id => Some(id),
+	}
+}
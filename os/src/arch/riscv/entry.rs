@@ -1,5 +1,5 @@
 #![cfg(target_arch = "riscv64")]
-use crate::{config::KERNEL_STACK_SIZE, global::KERNEL_STACK, trap::TrapHandler};
+use crate::{config::KERNEL_STACK_SIZE, global::BOOT_STACK, trap::TrapHandler};
 use core::arch::naked_asm;
 
 /// Move stack to keep a space for TrapHandler
@@ -15,24 +15,21 @@ pub unsafe extern "C" fn reuse_stack_for_trap() {
     )
 }
 
-/// Locates and initializes stack for each hart.
+/// Locates and initializes stack for the boot hart.
 ///
-/// This is a naked function that sets up the stack pointer based on hart ID.
+/// This is a naked function that sets up the stack pointer at the top of
+/// `BOOT_STACK`, the other harts get their stack from `hart_start`.
 #[unsafe(naked)]
 pub(crate) unsafe extern "C" fn locate() {
     core::arch::naked_asm!(
 	"   la   sp, {stack}            // Load stack base address
-	    li   t0, {per_hart_stack_size} // Load stack size per hart
-	    mv t1, a0                   // Get current hart ID
-	    addi t1, t1,  1             // Add 1 to hart ID
-	 1: add  sp, sp, t0             // Calculate stack pointer
-	    addi t1, t1, -1             // Decrement counter
-	    bnez t1, 1b                 // Loop if not zero
+	    li   t0, {stack_size}       // Load stack size
+	    add  sp, sp, t0             // Stack grows down from the top
 	    call t1, {move_stack}       // Call stack reuse function
 	    ret                         // Return
 	",
-	per_hart_stack_size = const KERNEL_STACK_SIZE,
-	stack               =   sym KERNEL_STACK,
+	stack_size          = const KERNEL_STACK_SIZE,
+	stack               =   sym BOOT_STACK,
 	move_stack          =   sym reuse_stack_for_trap,
     )
 }
@@ -63,14 +60,17 @@ unsafe extern "C" fn start() -> ! {
 	)
 }
 
+/// Entry of the other harts, `a1` is the top of the stack given by
+/// `mm::stack::alloc_hart_stack`.
 #[unsafe(naked)]
 #[unsafe(export_name = "hart_start")]
 pub unsafe extern "C" fn hart_start() -> ! {
 	naked_asm!(
 	"hart_real_start:
-		call {locate}
+		mv   sp, a1
+		call {move_stack}
 		call hart_main
 		",
-		locate = sym locate,
+		move_stack = sym reuse_stack_for_trap,
 	)
 }
@@ -1,6 +1,8 @@
-use riscv::register::sscratch;
+use riscv::register::{sie, sip, sscratch, sstatus};
+use sbi_rt::HartMask;
 
-use crate::arch::{common::ArchHarts, riscv::Riscv64};
+use crate::arch::{common::ArchHarts, riscv::{Riscv64, time::init_stimecmp, trap::reset_ext}};
+use crate::harts::HartState;
 use core::arch::asm;
 
 impl<C> ArchHarts for Riscv64<C> {
@@ -15,7 +17,71 @@ impl<C> ArchHarts for Riscv64<C> {
 		sscratch::read()
 	}
 
-	fn hart_start(&self, hartid: usize, start_addr: usize, opaque: usize) {
-		sbi_rt::hart_start(hartid, start_addr, opaque);
+	fn hart_start(&self, hartid: usize, start_addr: usize, opaque: usize) -> bool {
+		sbi_rt::hart_start(hartid, start_addr, opaque).error == 0
+	}
+
+	fn hart_stop(&self) {
+		sbi_rt::hart_stop();
+	}
+
+	fn hart_status(&self, hartid: usize) -> HartState {
+		let ret = sbi_rt::hart_get_status(hartid);
+		if ret.error != 0 {
+			return HartState::Invalid;
+		}
+		match ret.value {
+			0 => HartState::Started,
+			1 => HartState::Stoped,
+			2 => HartState::StartPeding,
+			3 => HartState::StopPeding,
+			// suspended, suspend pending and resume pending
+			4..=6 => HartState::Suspended,
+			_ => HartState::Invalid,
+		}
+	}
+
+	fn hart_init(&self) {
+		unsafe {
+			// the FP and vector registers belong to no task yet, see FlowContext::claim_ext
+			reset_ext();
+			sie::set_stimer();
+			sie::set_ssoft();
+			sie::set_sext();
+		}
+		// before the first set_next_timer_intr of the hart
+		init_stimecmp();
+	}
+
+	fn send_ipi(&self, hartid: usize) {
+		sbi_rt::send_ipi(HartMask::from_mask_base(1, hartid));
+	}
+
+	fn clear_ipi(&self) {
+		unsafe { sip::clear_ssoft() };
+	}
+
+	fn wait_for_interrupt(&self) {
+		riscv::asm::wfi();
+	}
+
+	fn park(&self) -> ! {
+		unsafe { sstatus::clear_sie() };
+		loop {
+			riscv::asm::wfi();
+		}
+	}
+
+	fn irq_save(&self) -> bool {
+		let sstatus: usize;
+		// SIE is bit 1
+		unsafe { asm!("csrrci {}, sstatus, 2", out(reg) sstatus) };
+		sstatus & 2 != 0
+	}
+
+	fn irq_restore(&self, was_on: bool) {
+		if was_on {
+			unsafe { sstatus::set_sie() };
+		}
 	}
 }
\ No newline at end of file
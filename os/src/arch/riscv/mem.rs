@@ -1,6 +1,7 @@
-use riscv::asm::fence_i;
+use riscv::asm::{fence_i, sfence_vma_all};
 
 use crate::arch::{common::ArchMem, riscv::Riscv64};
+use crate::ksyms::Symbolized;
 use log::error;
 use core::arch::asm;
 
@@ -9,6 +10,13 @@ impl<C> ArchMem for Riscv64<C> {
 		fence_i();
 	}
 
+	fn flush_tlb(&self, vaddr: Option<usize>) {
+		match vaddr {
+			Some(vaddr) => unsafe { asm!("sfence.vma {}, zero", in(reg) vaddr) },
+			None => sfence_vma_all(),
+		}
+	}
+
 	#[inline(never)]
 	fn unwind(&self) {
 		const MAX_DEPTH: usize = 128;
@@ -40,7 +48,7 @@ impl<C> ArchMem for Riscv64<C> {
 					options(readonly)
 				);
 			}
-			error!("[{:<10x}, {:<10x}] ra = {:<10x}", prev_fp, fp, ra);
+			error!("[{:<10x}, {:<10x}] ra = {:<10x} {}", prev_fp, fp, ra, Symbolized(ra));
 			depth += 1;
 		}
 	}
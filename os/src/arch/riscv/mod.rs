@@ -1,9 +1,12 @@
+pub mod aia;
 pub mod entry;
 pub mod mem;
 pub mod power;
 pub mod time;
 pub mod trap;
+pub mod vector;
 pub mod harts;
+pub mod switch;
 
 use core::marker::PhantomData;
 
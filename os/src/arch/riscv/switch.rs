@@ -0,0 +1,78 @@
+use core::arch::naked_asm;
+
+use crate::arch::{common::ArchSwitch, riscv::Riscv64};
+
+/// Registers a kernel control flow has to keep across a switch.
+///
+/// Unlike `FlowContext`, which holds the whole user register file and is
+/// restored by the trampoline, a kernel switch is an ordinary function call,
+/// so only `ra`, `sp` and the callee saved registers are recorded.
+#[repr(C)]
+pub struct KernelContext {
+	pub ra: usize,      // 0
+	pub sp: usize,      // 1
+	pub s: [usize; 12], // 2..
+}
+
+impl KernelContext {
+	pub const ZERO: Self = Self {
+		ra: 0,
+		sp: 0,
+		s: [0; 12],
+	};
+
+	/// A context which starts at `entry` on the stack whose top is `sp`.
+	pub fn new(entry: usize, sp: usize) -> Self {
+		Self {
+			ra: entry,
+			sp,
+			s: [0; 12],
+		}
+	}
+}
+
+impl<C> ArchSwitch for Riscv64<C> {
+	#[inline]
+	unsafe fn switch(&self, cur: *mut KernelContext, next: *const KernelContext) {
+		unsafe { kernel_switch(cur, next) }
+	}
+}
+
+/// Save the current kernel flow into `cur` and resume the one in `next`.
+///
+/// `ret` jumps to `next.ra`, i.e. right after the `kernel_switch` which saved
+/// `next`, or to the entry of a flow which never ran before.
+#[unsafe(naked)]
+unsafe extern "C" fn kernel_switch(cur: *mut KernelContext, next: *const KernelContext) {
+	naked_asm!(
+		"sd ra,  0*8(a0)",
+		"sd sp,  1*8(a0)",
+		"sd s0,  2*8(a0)",
+		"sd s1,  3*8(a0)",
+		"sd s2,  4*8(a0)",
+		"sd s3,  5*8(a0)",
+		"sd s4,  6*8(a0)",
+		"sd s5,  7*8(a0)",
+		"sd s6,  8*8(a0)",
+		"sd s7,  9*8(a0)",
+		"sd s8,  10*8(a0)",
+		"sd s9,  11*8(a0)",
+		"sd s10, 12*8(a0)",
+		"sd s11, 13*8(a0)",
+		"ld ra,  0*8(a1)",
+		"ld sp,  1*8(a1)",
+		"ld s0,  2*8(a1)",
+		"ld s1,  3*8(a1)",
+		"ld s2,  4*8(a1)",
+		"ld s3,  5*8(a1)",
+		"ld s4,  6*8(a1)",
+		"ld s5,  7*8(a1)",
+		"ld s6,  8*8(a1)",
+		"ld s7,  9*8(a1)",
+		"ld s8,  10*8(a1)",
+		"ld s9,  11*8(a1)",
+		"ld s10, 12*8(a1)",
+		"ld s11, 13*8(a1)",
+		"ret",
+	)
+}
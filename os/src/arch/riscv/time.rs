@@ -1,8 +1,87 @@
+//! Timer of the RISC-V harts.
+//!
+//! With Sstc the next timer interrupt is written to stimecmp (CSR 0x14d)
+//! directly, without a round trip into the SBI, which stays the fallback.
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use crate::arch::riscv::Riscv64;
 use crate::arch::{common::ArchTime, riscv::RiscvVirt};
 use crate::config::VIRT_FREQUNCY;
+use crate::cpu::{self, CpuFeatures, PerCpu};
 use crate::global::PLATFORM;
-use riscv::register::{sie, time};
+use crate::harts::{hart_id_if_running, hart_id_in_trap_stage};
+use log::warn;
+use riscv::register::{sie, sip, time};
+
+/// the hart may write stimecmp, see `init_stimecmp`
+static STIMECMP: PerCpu<AtomicBool> = PerCpu::new(|| AtomicBool::new(false));
+
+// Trap handler of the probe in `stimecmp_works`, it skips the csrr and
+// clears a0. stvec needs it 4 byte aligned, which a function need not be.
+global_asm!(
+	".pushsection .text",
+	".balign 4",
+	"stimecmp_probe_trap:",
+	"csrr t0, sepc",
+	"addi t0, t0, 4",
+	"csrw sepc, t0",
+	"li a0, 0",
+	"sret",
+	".popsection",
+);
+
+unsafe extern "C" {
+	fn stimecmp_probe_trap();
+}
+
+/// Read stimecmp once, which traps unless the SBI set menvcfg.STCE.
+///
+/// Interrupts are off meanwhile, sstatus and stvec are restored afterwards.
+fn stimecmp_works() -> bool {
+	let works: usize;
+	unsafe {
+		asm!(
+			"csrrci {sstatus}, sstatus, 2",
+			"csrrw {stvec}, stvec, {probe}",
+			"li a0, 1",
+			"csrr t0, 0x14d",
+			"csrw stvec, {stvec}",
+			"csrw sstatus, {sstatus}",
+			sstatus = out(reg) _,
+			stvec = out(reg) _,
+			probe = in(reg) stimecmp_probe_trap as *const () as usize,
+			out("a0") works,
+			out("t0") _,
+		);
+	}
+	works != 0
+}
+
+/// Use stimecmp on this hart if the devicetree lists Sstc for every hart
+/// and the SBI lets S mode access it, called at hart init.
+pub(crate) fn init_stimecmp() {
+	let hartid = hart_id_in_trap_stage();
+	let sstc = cpu::features().contains(CpuFeatures::SSTC);
+	let works = sstc && stimecmp_works();
+	if sstc && !works {
+		warn!("hart {} has Sstc but the SBI does not enable stimecmp, using the SBI timer", hartid);
+	}
+	STIMECMP[hartid].store(works, Ordering::Relaxed);
+}
+
+/// Raise the timer interrupt of this hart once `time` reaches `stime`.
+fn set_timer(stime: u64) {
+	// before the hart runs tasks it has not probed yet
+	let stimecmp = hart_id_if_running()
+		.and_then(|hartid| STIMECMP.get(hartid))
+		.is_some_and(|works| works.load(Ordering::Relaxed));
+	if stimecmp {
+		unsafe { asm!("csrw 0x14d, {}", in(reg) stime) };
+	} else {
+		sbi_rt::set_timer(stime);
+	}
+}
 
 impl<C> ArchTime for Riscv64<C> {
 	default fn sleep(&self, sec: usize) {
@@ -12,7 +91,7 @@ impl<C> ArchTime for Riscv64<C> {
 		unsafe {
 			sie::set_stimer();
 		}
-		sbi_rt::set_timer(time_end as u64);
+		set_timer(time_end as u64);
 		riscv::asm::wfi();
 	}
 
@@ -22,6 +101,10 @@ impl<C> ArchTime for Riscv64<C> {
 		}
 	}
 
+	fn timer_pending(&self) -> bool {
+		sip::read().stimer()
+	}
+
 	default fn time_ns(&self) -> u64 {
 		let time_freq = PLATFORM.get().unwrap().board_info.cpu_freq.unwrap();
 		let time = time::read64();
@@ -50,7 +133,7 @@ impl<C> ArchTime for Riscv64<C> {
 		let time_freq = PLATFORM.get().unwrap().board_info.cpu_freq.unwrap();
 		let now = time::read64();
 		let total_clock_dur = (time_freq as u64 / 1_000) * dur_ms as u64;
-		sbi_rt::set_timer(now + total_clock_dur);
+		set_timer(now + total_clock_dur);
 	}
 }
 
@@ -61,7 +144,7 @@ impl ArchTime for Riscv64<RiscvVirt> {
 		unsafe {
 			sie::set_stimer();
 		}
-		sbi_rt::set_timer(time_end as u64);
+		set_timer(time_end as u64);
 		riscv::asm::wfi();
 	}
 
@@ -88,6 +171,6 @@ impl ArchTime for Riscv64<RiscvVirt> {
 	fn set_next_timer_intr(&self, dur_ms: usize) {
 		let now = time::read64();
 		let total_clock_dur = (VIRT_FREQUNCY as u64 / 1_000) * dur_ms as u64;
-		sbi_rt::set_timer(now + total_clock_dur);
+		set_timer(now + total_clock_dur);
 	}
 }
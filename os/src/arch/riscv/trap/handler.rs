@@ -1,9 +1,20 @@
 use crate::TASK_MANAGER;
+use crate::task::processor::current_processor;
 use crate::config::TICK_MS;
+use crate::crash;
+use crate::driver::irq;
+use crate::ipi;
+use crate::harts::hart_id_in_trap_stage;
 use crate::global::ARCH;
-use crate::arch::common::ArchTime;
+use crate::arch::common::{ArchHarts, ArchTime};
+use crate::error::Errno;
 use crate::syscall::syscall;
 use crate::syscall::syscallid::SyscallID;
+use crate::syscall::signal::sys_sigreturn;
+use crate::harts::task_context_in_trap_stage;
+use crate::task::block::TaskControlBlock;
+use crate::task::fault::Fault;
+use crate::task::signal::{self, deliver_signals, force_signal, SIGBUS, SIGILL, SIGKILL, SIGSEGV, SIGTRAP};
 use crate::trap::entire::EntireContext;
 use crate::trap::entire::EntireResult;
 use crate::trap::fast::FastResult;
@@ -48,10 +59,21 @@ pub extern "C" fn fast_handler_user(
 	ctx.set_trans_context(trans_ctx);
 	ctx.tasks().app_info().user_time.end();
 	ctx.tasks().app_info().kernel_time.start();
+	// the main thread exited while this one was running
+	if ctx.tasks().process.is_exiting() {
+		TASK_MANAGER.get().unwrap().exit_cur_and_run_next();
+	}
 
 	let scause = scause::read();
 	let stval = stval::read();
 	let sepc = sepc::read();
+	// the signal is delivered on the entire path, which saves the other registers
+	let raise_fault = |mut ctx: FastContext, signo: usize, cause: Exception| {
+		save_regs(&mut ctx);
+		let fault = Fault { signo, cause, addr: stval, pc: sepc };
+		signal::raise_fault(ctx.tasks(), fault);
+		ctx.continue_with(signal_handler, ())
+	};
 
 	#[cfg(feature = "nested_trap")]
 	unsafe {
@@ -67,52 +89,83 @@ pub extern "C" fn fast_handler_user(
 			stack_drop));
 		sstatus::set_sie();
 	}
-	match scause.cause()
-		.try_into::<riscv::interrupt::Interrupt, riscv::interrupt::supervisor::Exception>()
-		.unwrap() {
-
+	let Ok(trap) = scause.cause()
+		.try_into::<riscv::interrupt::Interrupt, riscv::interrupt::supervisor::Exception>() else {
+		return kill_on_unknown_trap(scause.bits(), stval, sepc);
+	};
+	match trap {
 		Trap::Interrupt(Interrupt::SupervisorTimer) => {
 			save_regs(&mut ctx);
 			ctx.continue_with(timer_handler, ())
 		}
+		Trap::Interrupt(Interrupt::SupervisorSoft) => {
+			ARCH.clear_ipi();
+			save_regs(&mut ctx);
+			ctx.continue_with(ipi_handler, ())
+		}
+		Trap::Interrupt(Interrupt::SupervisorExternal) => {
+			save_regs(&mut ctx);
+			irq::handle_irq(hart_id_in_trap_stage());
+			return_to_user(ctx)
+		}
 
 		Trap::Exception(Exception::UserEnvCall) => {
 			save_regs(&mut ctx);
 			syscall_handler(ctx, a1, a2, a3, a4, a5, a6, a7)
 		}
-		Trap::Exception(Exception::StoreFault) |
-		Trap::Exception(Exception::StorePageFault) |
-		Trap::Exception(Exception::LoadFault) |
-		Trap::Exception(Exception::LoadMisaligned) => {
-			warn!("PageFault in application, kernel killed it.");
-			warn!("Illegal addr: 0x{:x}", stval);
-			warn!("excption pc: 0x{:x}", sepc);
-			TASK_MANAGER.get().unwrap().exit_cur_and_run_next();
-			ctx.switch_to()
+		Trap::Exception(cause @ (
+			Exception::StoreFault |
+			Exception::StorePageFault |
+			Exception::LoadFault |
+			Exception::LoadPageFault
+		)) => {
+			info!("PageFault in application at pc {:#x}, addr {:#x}, raise SIGSEGV.", sepc, stval);
+			raise_fault(ctx, SIGSEGV, cause)
 		}
-		Trap::Exception(Exception::IllegalInstruction) => {
-			warn!("IllegalInstruction in application, kernel killed it.");
-			warn!("excption pc: 0x{:x}", sepc);
-			TASK_MANAGER.get().unwrap().exit_cur_and_run_next();
-			ctx.switch_to()
+		Trap::Exception(cause @ (
+			Exception::LoadMisaligned |
+			Exception::StoreMisaligned |
+			Exception::InstructionMisaligned
+		)) => {
+			info!("Misaligned access in application at pc {:#x}, addr {:#x}, raise SIGBUS.", sepc, stval);
+			raise_fault(ctx, SIGBUS, cause)
 		}
-		Trap::Exception(Exception::InstructionFault) |
-		Trap::Exception(Exception::InstructionMisaligned) |
-		Trap::Exception(Exception::InstructionPageFault) => {
-			warn!("Instruction PageFault in application, kernel killed it.");
-			warn!("Illegal addr: 0x{:x}", stval);
-			warn!("excption pc: 0x{:x}", sepc);
-			ctx.tasks().app_info().end();
-			TASK_MANAGER.get().unwrap().exit_cur_and_run_next();
-			ctx.switch_to()
+		Trap::Exception(cause @ Exception::IllegalInstruction) => {
+			// the first FP or vector instruction since the task came to this hart, run it again
+			if unsafe { ctx.regs().claim_ext(stval) } {
+				save_regs(&mut ctx);
+				return ctx.restore();
+			}
+			info!("IllegalInstruction in application at pc {:#x}, raise SIGILL.", sepc);
+			raise_fault(ctx, SIGILL, cause)
+		}
+		Trap::Exception(cause @ (
+			Exception::InstructionFault |
+			Exception::InstructionPageFault
+		)) => {
+			info!("Instruction PageFault in application at pc {:#x}, addr {:#x}, raise SIGSEGV.", sepc, stval);
+			raise_fault(ctx, SIGSEGV, cause)
 		}
 
-		_ => {
-			panic!("Unsupported trap {:?}, stval = {:#x}!", scause.cause(), stval);
+		Trap::Exception(cause @ Exception::Breakpoint) => {
+			info!("Breakpoint in application at pc {:#x}, raise SIGTRAP.", sepc);
+			raise_fault(ctx, SIGTRAP, cause)
 		}
+
+		_ => kill_on_unknown_trap(scause.bits(), stval, sepc),
 	}
 }
 
+/// Kill the app on a trap the kernel can not handle instead of panicking.
+fn kill_on_unknown_trap(scause: usize, stval: usize, sepc: usize) -> ! {
+	let tcb = task_context_in_trap_stage();
+	warn!(
+		"Unsupported trap in task {} (app {}), scause = {:#x}, stval = {:#x}, sepc = {:#x}, kill it.",
+		tcb.id(), tcb.process.app_id, scause, stval, sepc
+	);
+	TASK_MANAGER.get().unwrap().exit_cur_process_and_run_next(-(SIGKILL as i32))
+}
+
 pub extern "C" fn fast_handler_kernel(
 	mut ctx: FastContext,
 	a1: usize,
@@ -134,12 +187,23 @@ pub extern "C" fn fast_handler_kernel(
 		.unwrap() {
 
 		Trap::Interrupt(Interrupt::SupervisorTimer) => {
-			//TODO: do something useful
 			save_regs(&mut ctx);
-			//println!("Kernel recieve timer intrrupt");
+			// kernel code is only switched at safe points, see preempt_point
+			current_processor().set_need_resched();
 			ARCH.set_next_timer_intr(TICK_MS);
 			ctx.nested_restore()
 		}
+		Trap::Interrupt(Interrupt::SupervisorSoft) => {
+			ARCH.clear_ipi();
+			crash::poll_stop();
+			// a reschedule waits for preempt_point, like the timer
+			ipi::handle_messages(hart_id_in_trap_stage());
+			ctx.nested_restore()
+		}
+		Trap::Interrupt(Interrupt::SupervisorExternal) => {
+			irq::handle_irq(hart_id_in_trap_stage());
+			ctx.nested_restore()
+		}
 
 		Trap::Exception(Exception::StoreFault) |
 		Trap::Exception(Exception::StorePageFault) |
@@ -180,12 +244,24 @@ pub extern "C" fn syscall_handler(
 	a6: usize,
 	a7: usize,
 ) -> FastResult {
-	let syscall_id: SyscallID = a7.try_into().unwrap();
+	let syscall_id: SyscallID = match a7.try_into() {
+		Ok(syscall_id) => syscall_id,
+		Err(e) => {
+			info!("Unknown syscall {} from task {}.", a7, ctx.tasks().id());
+			ctx.regs().a[0] = Errno::from(e).to_ret() as usize;
+			skip_ecall(&mut ctx);
+			return return_to_user(ctx);
+		}
+	};
 	let tasks = ctx.tasks();
 	let app_info = tasks.app_info();
 
 	*app_info.syscall_record.get_mut(&syscall_id).unwrap() += 1;
 
+	if syscall_id == SyscallID::SigReturn {
+		// every register comes back from the signal frame
+		return ctx.continue_with(sigreturn_handler, ());
+	}
 	ctx.regs().a[0] = syscall(syscall_id, [ctx.a0(), a1, a2]) as usize;
 
 	match syscall_id {
@@ -193,22 +269,93 @@ pub extern "C" fn syscall_handler(
 			ctx.continue_with(yield_handler, ())
 		},
 		SyscallID::Exit => {
-			TASK_MANAGER.get().unwrap().exit_cur_and_run_next();
-			ctx.switch_to()
+			TASK_MANAGER.get().unwrap().exit_cur_and_run_next()
 		}
 		_ => {
-			unsafe {
-				if cfg!(feature = "nested_trap") {
-					ctx.regs().pc = ctx.regs().pc + 4;
-				} else {
-					sepc::write(sepc::read() + 4);
-				}
-			}
-			ctx.restore()
+			skip_ecall(&mut ctx);
+			return_to_user(ctx)
+		}
+	}
+}
+
+/// Step over the ecall before going back to user mode.
+fn skip_ecall(ctx: &mut FastContext) {
+	unsafe {
+		if cfg!(feature = "nested_trap") {
+			ctx.regs().pc = ctx.regs().pc + 4;
+		} else {
+			sepc::write(sepc::read() + 4);
 		}
 	}
 }
 
+/// Return to user mode, through the entire path if a signal is pending.
+fn return_to_user(mut ctx: FastContext) -> FastResult {
+	if ctx.tasks().signals.has_deliverable() {
+		ctx.continue_with(signal_handler, ())
+	} else {
+		ctx.restore()
+	}
+}
+
+/// Deliver pending signals and load the user sp and pc they may change.
+///
+/// The context must hold every user register.
+fn deliver_and_load(tcb: &TaskControlBlock) {
+	deliver_signals(tcb);
+	unsafe { tcb.flow_context().load_others() };
+}
+
+/// Save the user sp and pc of the trap, a nested trap saved them on entry.
+fn save_user_sp_pc(tcb: &TaskControlBlock) {
+	#[cfg(not(feature = "nested_trap"))]
+	unsafe {
+		tcb.flow_context().save_others()
+	};
+	#[cfg(feature = "nested_trap")]
+	let _ = tcb;
+}
+
+pub extern "C" fn signal_handler(ctx: EntireContext) -> EntireResult {
+	let split_ctx = ctx.split().0;
+	let tcb = task_context_in_trap_stage();
+	save_user_sp_pc(tcb);
+	deliver_and_load(tcb);
+	split_ctx.restore()
+}
+
+pub extern "C" fn sigreturn_handler(ctx: EntireContext) -> EntireResult {
+	let split_ctx = ctx.split().0;
+	let tcb = task_context_in_trap_stage();
+	save_user_sp_pc(tcb);
+	if sys_sigreturn().is_err() {
+		warn!("Bad signal frame at 0x{:x}, raise SIGSEGV.", tcb.flow_context().sp);
+		force_signal(tcb, SIGSEGV);
+	}
+	deliver_and_load(tcb);
+	split_ctx.restore()
+}
+
+/// Stop for a crash dump with every user register saved, otherwise handle
+/// the messages to this hart and give it up if a reschedule was asked for.
+pub extern "C" fn ipi_handler(ctx: EntireContext) -> EntireResult {
+	let split_ctx = ctx.split().0;
+	let tcb = task_context_in_trap_stage();
+	save_user_sp_pc(tcb);
+	if crash::stop_requested() {
+		crash::stop_in_user(tcb);
+	}
+	ipi::handle_messages(hart_id_in_trap_stage());
+	if current_processor().take_need_resched() {
+		// save_user_sp_pc already saved sp and pc
+		TASK_MANAGER.get().unwrap().suspend_cur_and_run_next(None, None);
+		deliver_and_load(task_context_in_trap_stage());
+		return split_ctx.switch();
+	}
+	deliver_and_load(tcb);
+	split_ctx.restore()
+}
+
 pub extern "C" fn yield_handler(ctx: EntireContext) -> EntireResult {
 	let mut split_ctx = ctx.split().0;
 	let (pc, sp) = {
@@ -222,6 +369,7 @@ pub extern "C" fn yield_handler(ctx: EntireContext) -> EntireResult {
 		}
     	};
 	TASK_MANAGER.get().unwrap().suspend_cur_and_run_next(sp, pc);
+	deliver_and_load(task_context_in_trap_stage());
 	split_ctx.switch()
 }
 
@@ -234,10 +382,13 @@ pub extern "C" fn timer_handler(ctx: EntireContext) -> EntireResult {
 		}
 		#[cfg(not(feature = "nested_trap"))]
 		{
-			(Some(sepc::read() + 4), Some(sscratch::read()))
+			// the interrupted instruction has not run yet
+			(Some(sepc::read()), Some(sscratch::read()))
 		}
     	};
 	ARCH.set_next_timer_intr(TICK_MS);
+	current_processor().take_need_resched();
 	TASK_MANAGER.get().unwrap().suspend_cur_and_run_next(sp, pc);
+	deliver_and_load(task_context_in_trap_stage());
 	split_ctx.switch()
 }
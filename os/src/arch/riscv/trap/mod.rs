@@ -1,11 +1,15 @@
 use core::intrinsics::unreachable;
 use core::{arch::naked_asm, usize};
 use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use riscv::register::sie;
 use riscv::register::sstatus::FS;
-use riscv::register::{sepc, sscratch, sstatus::{self, SPP}, stvec::{self, Stvec}};
+use riscv::register::{scause, sepc, sscratch, stval, sstatus::{self, SPP}, stvec::{self, Stvec}};
 use log::{info, warn};
 use crate::config::{TRAMPOLINE_VADDR, USER_STACK_SIZE};
+use crate::arch::riscv::vector::{self, VectorState, VS};
+use crate::cpu::{self, CpuFeatures, PerCpu};
+use crate::harts::hart_id_in_trap_stage;
 use crate::harts::task_context_in_trap_stage;
 use crate::{arch::{common::ArchTrap, riscv::Riscv64}};
 use crate::{USER_STACK, println};
@@ -51,8 +55,8 @@ impl<C> ArchTrap for Riscv64<C> {
 		result
 	}
 
-	unsafe extern "C" fn boot_entry(a0: usize, a1: usize) -> ! {
-		//a0: user sp, a1: user addr space
+	unsafe extern "C" fn boot_entry(a0: usize, a1: usize, a2: usize) -> ! {
+		//a0: user sp, a1: user addr space, a2: a0 of the user
 		unsafe {
 			unsafe extern "C" {
 				fn _boot_entry();
@@ -62,11 +66,10 @@ impl<C> ArchTrap for Riscv64<C> {
 			println!("b: 0x{:x}, t: 0x{:x}, tram: 0x{:x}, r: 0x{:x}", _boot_entry as usize, _trap_enrty as usize, TRAMPOLINE_VADDR, real_boot_enrty_trampoline);
 			asm!(
 				"fence.i",
-				"mv a0, {user_sp}",
-				"mv a1, {user_addr_space}",
 				"jr {real_entry}",
-				user_sp = in(reg) a0,
-				user_addr_space = in(reg) a1,
+				in("a0") a0,
+				in("a1") a1,
+				in("a2") a2,
 				real_entry = in(reg) real_boot_enrty_trampoline
 			);
 			unreachable();
@@ -77,13 +80,85 @@ impl<C> ArchTrap for Riscv64<C> {
 	extern "C" fn boot_handler(entry: usize, trampoline: usize, utraph: usize) {
 		unsafe {
 			sstatus::set_spp(SPP::User);
-			sstatus::set_fs(FS::Initial);
+			// 新任务第一次使用浮点或向量指令时才加载相应状态
+			sstatus::set_fs(FS::Off);
+			vector::set_vs(VS::Off);
 			sie::set_stimer();
+			sie::set_ssoft();
+			sie::set_sext();
 			sepc::write(entry);
 			stvec::write(Stvec::new(trampoline, stvec::TrapMode::Direct));
 			sscratch::write(utraph);
 		}
 	}
+
+	#[inline(always)]
+	fn caller_regs(&self) -> [usize; 2] {
+		let (ra, sp): (usize, usize);
+		unsafe {
+			asm!("mv {}, ra", "mv {}, sp", out(reg) ra, out(reg) sp, options(nomem, nostack));
+		}
+		[ra, sp]
+	}
+
+	#[inline]
+	fn trap_regs(&self) -> [usize; 3] {
+		[sepc::read(), scause::read().bits(), stval::read()]
+	}
+}
+
+/// 每个 hart 的浮点寄存器中是哪个上下文的值，见 `FlowContext::claim_ext`
+static FP_OWNER: PerCpu<AtomicUsize> = PerCpu::new(|| AtomicUsize::new(0));
+/// 每个 hart 的向量寄存器中是哪个上下文的值
+static V_OWNER: PerCpu<AtomicUsize> = PerCpu::new(|| AtomicUsize::new(0));
+/// 浮点或向量状态不在任何 hart 上
+const NO_HART: usize = usize::MAX;
+
+/// hart 刚启动时浮点和向量寄存器不属于任何任务，停止前的值也已不在。
+pub(crate) unsafe fn reset_ext() {
+	let hartid = hart_id_in_trap_stage();
+	FP_OWNER[hartid].store(0, Ordering::Relaxed);
+	V_OWNER[hartid].store(0, Ordering::Relaxed);
+	unsafe {
+		sstatus::set_fs(FS::Off);
+		vector::set_vs(VS::Off);
+	}
+}
+
+/// 非法指令 `insn` 可能用到的扩展，只看操作码和 CSR 编号。
+///
+/// stval 为 0 时硬件没有给出指令，两者都有可能。
+fn ext_of(insn: u32) -> CpuFeatures {
+	if insn == 0 {
+		return CpuFeatures::FP | CpuFeatures::V;
+	}
+	if insn & 0b11 != 0b11 {
+		// 压缩指令 c.fld、c.fsd、c.fldsp 和 c.fsdsp
+		let quadrant = insn & 0b11;
+		let funct3 = (insn >> 13) & 0b111;
+		return match (quadrant, funct3) {
+			(0b00 | 0b10, 0b001 | 0b101) => CpuFeatures::FP,
+			_ => CpuFeatures::empty(),
+		};
+	}
+	let funct3 = (insn >> 12) & 0b111;
+	match insn & 0x7f {
+		// LOAD-FP 和 STORE-FP，其余宽度编码是向量访存
+		0x07 | 0x27 if (1..=4).contains(&funct3) => CpuFeatures::FP,
+		0x07 | 0x27 => CpuFeatures::V,
+		// fmadd、fmsub、fnmsub、fnmadd 和 OP-FP
+		0x43 | 0x47 | 0x4b | 0x4f | 0x53 => CpuFeatures::FP,
+		// OP-V，OPFVV 和 OPFVF 是向量浮点指令，也要 FS 不为 Off
+		0x57 if funct3 == 0b001 || funct3 == 0b101 => CpuFeatures::FP | CpuFeatures::V,
+		0x57 => CpuFeatures::V,
+		// 访问 fflags、frm、fcsr 或向量 CSR 的 csr 指令
+		0x73 if funct3 != 0 => match insn >> 20 {
+			0x001..=0x003 => CpuFeatures::FP,
+			0x008..=0x00f | 0xc20..=0xc22 => CpuFeatures::V,
+			_ => CpuFeatures::empty(),
+		},
+		_ => CpuFeatures::empty(),
+	}
 }
 
 macro_rules! exchange {
@@ -126,7 +201,6 @@ macro_rules! load {
 	};
 }
 
-#[cfg(feature = "float")]
 macro_rules! fsave {
 	($reg:ident => $ptr:ident[$pos:expr]) => {
 		concat!("fsd ",
@@ -138,14 +212,6 @@ macro_rules! fsave {
 			')')
 	};
 }
-#[cfg(not(feature = "float"))]
-macro_rules! fsave {
-	($reg:ident => $ptr:ident[$pos:expr]) => {
-		""
-	};
-}
-
-#[cfg(feature = "float")]
 macro_rules! fload {
 	($ptr:ident[$pos:expr] => $reg:ident) => {
 		concat!("fld ",
@@ -157,14 +223,6 @@ macro_rules! fload {
 			')')
 	};
 }
-#[cfg(not(feature = "float"))]
-macro_rules! fload {
-	($ptr:ident[$pos:expr] => $reg:ident) => {
-		""
-	};
-
-}
-
 //TODO:其实不能将他和nested trap feature绑定
 #[cfg(feature = "nested_trap")]
 macro_rules! csr_save_n {
@@ -230,8 +288,12 @@ pub struct FlowContext {
 	pub uaddr_space: usize, // 32
 	pub utrap_handler: usize, // 33
 	pub id: usize,      // 34..
-	#[cfg(feature = "float")]
 	pub f:  [usize; 32], // 35..
+	pub fcsr: usize,    // 67
+	/// `f` 和 `fcsr` 最后加载到的 hart，见 `owns_fp`
+	fp_hart: usize,     // 68
+	/// 任务第一次使用向量指令时创建，大小取决于 vlenb
+	vector: Option<VectorState>,
 }
 
 impl FlowContext {
@@ -247,8 +309,10 @@ impl FlowContext {
 		uaddr_space: 0,
 		utrap_handler: 0,
 		id: 0,
-		#[cfg(feature = "float")]
 		f: [0; 32],
+		fcsr: 0,
+		fp_hart: NO_HART,
+		vector: None,
 	};
 
 	pub fn new(sp: usize, entry: usize, id: usize, uaddr_space: usize, utraph: usize) -> Self {
@@ -264,9 +328,10 @@ impl FlowContext {
 			uaddr_space,
 			utrap_handler: utraph,
 			id,
-
-			#[cfg(feature = "float")]
 			f: [0; 32],
+			fcsr: 0,
+			fp_hart: NO_HART,
+			vector: None,
 		}
 	}
 
@@ -287,6 +352,224 @@ impl FlowContext {
 		}
 	}
 
+	/// 从硬件保存非调用规范约定的寄存器，与 `load_others` 相对。
+	#[inline]
+	pub(crate) unsafe fn save_others(&mut self) {
+		self.sp = sscratch::read();
+		self.pc = sepc::read();
+	}
+
+	/// 任务在陷入中途离开 hart 前，保存快速路径不保存的用户寄存器（gp 和被改写的浮点、向量寄存器）。
+	///
+	/// 离开期间其他任务会在这个 hart 上改写它们。浮点和向量寄存器只在 FS 或
+	/// VS 为 Dirty 时保存，不为 Off 时这个 hart 上的值一定属于当前任务。
+	#[inline]
+	pub(crate) unsafe fn save_live(&mut self) {
+		unsafe {
+			asm!("mv {}, gp", out(reg) self.gp);
+		}
+		if sstatus::read().fs() == FS::Dirty {
+			unsafe {
+				self.save_fp();
+				sstatus::set_fs(FS::Clean);
+			}
+		}
+		if vector::vs() == VS::Dirty {
+			unsafe {
+				if let Some(state) = self.vector.as_mut() {
+					state.save();
+				}
+				vector::set_vs(VS::Clean);
+			}
+		}
+	}
+
+	/// 任务回到 hart 后恢复 `save_live` 保存的寄存器。
+	///
+	/// 浮点和向量寄存器不在这里加载：若离开期间没有别的任务用过这个 hart 的
+	/// 这些寄存器，它们仍是这个任务的；否则置 FS 或 VS 为 Off，等任务下次
+	/// 使用相应指令时由 `claim_ext` 加载。
+	#[inline]
+	pub(crate) unsafe fn load_live(&self) {
+		let hartid = hart_id_in_trap_stage();
+		unsafe {
+			asm!("mv gp, {}", in(reg) self.gp);
+			if self.owns_fp(hartid) {
+				sstatus::set_fs(FS::Clean);
+			} else {
+				sstatus::set_fs(FS::Off);
+			}
+			if self.owns_vector(hartid) {
+				vector::set_vs(VS::Clean);
+			} else {
+				vector::set_vs(VS::Off);
+			}
+		}
+	}
+
+	/// `hartid` 的浮点寄存器中是这个上下文的值。
+	///
+	/// 上下文加载到别的 hart 后，原 hart 上的值可能已经过时，所以还要看 `fp_hart`。
+	fn owns_fp(&self, hartid: usize) -> bool {
+		self.fp_hart == hartid
+			&& FP_OWNER[hartid].load(Ordering::Relaxed) == self as *const Self as usize
+	}
+
+	/// `hartid` 的向量寄存器中是这个上下文的值，与 `owns_fp` 相同。
+	fn owns_vector(&self, hartid: usize) -> bool {
+		self.vector.as_ref().is_some_and(|state| state.hart == hartid)
+			&& V_OWNER[hartid].load(Ordering::Relaxed) == self as *const Self as usize
+	}
+
+	/// 任务的浮点或向量指令在 FS 或 VS 为 Off 时触发非法指令异常，此时把
+	/// 上下文中的相应状态加载到当前 hart，重新执行这条指令即可。`insn` 是
+	/// stval 给出的指令。
+	///
+	/// 返回 false 表示这条指令用不到关着的扩展，或者不是每个 hart 都有这个扩展，
+	/// 这确实是一条非法指令。上一个使用者离开 hart 时已经保存了它的状态，这里不需要再保存。
+	pub(crate) unsafe fn claim_ext(&mut self, insn: usize) -> bool {
+		let needs = ext_of(insn as u32) & cpu::features();
+		let hartid = hart_id_in_trap_stage();
+		let mut claimed = false;
+		if needs.contains(CpuFeatures::FP) && sstatus::read().fs() == FS::Off {
+			unsafe {
+				sstatus::set_fs(FS::Initial);
+				self.load_fp();
+				sstatus::set_fs(FS::Clean);
+			}
+			self.fp_hart = hartid;
+			FP_OWNER[hartid].store(self as *mut Self as usize, Ordering::Relaxed);
+			claimed = true;
+		}
+		if needs.contains(CpuFeatures::V) && vector::vs() == VS::Off {
+			unsafe {
+				vector::set_vs(VS::Initial);
+				let state = self.vector.get_or_insert_with(VectorState::new);
+				state.load();
+				state.hart = hartid;
+				vector::set_vs(VS::Clean);
+			}
+			V_OWNER[hartid].store(self as *mut Self as usize, Ordering::Relaxed);
+			claimed = true;
+		}
+		claimed
+	}
+
+	/// 内核读写 `f` 和 `fcsr` 之前调用，例如投递信号时。
+	///
+	/// 先写回这个 hart 上属于该上下文的浮点寄存器，再让任务下次使用浮点
+	/// 指令时重新加载，这样内核对上下文的修改也会生效。
+	pub(crate) unsafe fn flush_fp(&mut self) {
+		let hartid = hart_id_in_trap_stage();
+		if !self.owns_fp(hartid) {
+			return;
+		}
+		unsafe {
+			if sstatus::read().fs() == FS::Dirty {
+				self.save_fp();
+			}
+			sstatus::set_fs(FS::Off);
+		}
+		self.fp_hart = NO_HART;
+	}
+
+	/// 保存浮点寄存器和 fcsr，FS 不能为 Off。
+	#[inline]
+	unsafe fn save_fp(&mut self) {
+		unsafe {
+			asm!(
+				fsave!(f0 => t0[0]),
+				fsave!(f1 => t0[1]),
+				fsave!(f2 => t0[2]),
+				fsave!(f3 => t0[3]),
+				fsave!(f4 => t0[4]),
+				fsave!(f5 => t0[5]),
+				fsave!(f6 => t0[6]),
+				fsave!(f7 => t0[7]),
+				fsave!(f8 => t0[8]),
+				fsave!(f9 => t0[9]),
+				fsave!(f10 => t0[10]),
+				fsave!(f11 => t0[11]),
+				fsave!(f12 => t0[12]),
+				fsave!(f13 => t0[13]),
+				fsave!(f14 => t0[14]),
+				fsave!(f15 => t0[15]),
+				fsave!(f16 => t0[16]),
+				fsave!(f17 => t0[17]),
+				fsave!(f18 => t0[18]),
+				fsave!(f19 => t0[19]),
+				fsave!(f20 => t0[20]),
+				fsave!(f21 => t0[21]),
+				fsave!(f22 => t0[22]),
+				fsave!(f23 => t0[23]),
+				fsave!(f24 => t0[24]),
+				fsave!(f25 => t0[25]),
+				fsave!(f26 => t0[26]),
+				fsave!(f27 => t0[27]),
+				fsave!(f28 => t0[28]),
+				fsave!(f29 => t0[29]),
+				fsave!(f30 => t0[30]),
+				fsave!(f31 => t0[31]),
+				in("t0") self.f.as_mut_ptr(),
+			);
+			asm!("csrr {}, fcsr", out(reg) self.fcsr);
+		}
+	}
+
+	/// 加载浮点寄存器和 fcsr，FS 不能为 Off。
+	#[inline]
+	unsafe fn load_fp(&self) {
+		unsafe {
+			asm!(
+				fload!(t0[0] => f0),
+				fload!(t0[1] => f1),
+				fload!(t0[2] => f2),
+				fload!(t0[3] => f3),
+				fload!(t0[4] => f4),
+				fload!(t0[5] => f5),
+				fload!(t0[6] => f6),
+				fload!(t0[7] => f7),
+				fload!(t0[8] => f8),
+				fload!(t0[9] => f9),
+				fload!(t0[10] => f10),
+				fload!(t0[11] => f11),
+				fload!(t0[12] => f12),
+				fload!(t0[13] => f13),
+				fload!(t0[14] => f14),
+				fload!(t0[15] => f15),
+				fload!(t0[16] => f16),
+				fload!(t0[17] => f17),
+				fload!(t0[18] => f18),
+				fload!(t0[19] => f19),
+				fload!(t0[20] => f20),
+				fload!(t0[21] => f21),
+				fload!(t0[22] => f22),
+				fload!(t0[23] => f23),
+				fload!(t0[24] => f24),
+				fload!(t0[25] => f25),
+				fload!(t0[26] => f26),
+				fload!(t0[27] => f27),
+				fload!(t0[28] => f28),
+				fload!(t0[29] => f29),
+				fload!(t0[30] => f30),
+				fload!(t0[31] => f31),
+				in("t0") self.f.as_ptr(),
+			);
+			asm!("csrw fcsr, {}", in(reg) self.fcsr);
+		}
+	}
+
+	/// ra 到 pc 这 32 个用户整数寄存器，按上下文中的顺序。
+	pub fn user_regs(&self) -> [usize; 32] {
+		// SAFETY: repr(C)，ra 到 pc 恰好是开头连续的 32 个 usize
+		unsafe { *(self as *const Self as *const [usize; 32]) }
+	}
+
+	/// 用 `user_regs` 的格式整体设置用户整数寄存器。
+	pub fn set_user_regs(&mut self, regs: &[usize; 32]) {
+		unsafe { *(self as *mut Self as *mut [usize; 32]) = *regs }
+	}
+
 	pub fn set_sp(&mut self, sp: usize) {
 		self.sp = sp;
 	}
@@ -299,12 +582,13 @@ impl FlowContext {
 #[unsafe(naked)]
 #[unsafe(export_name = "_boot_entry")]
 #[unsafe(link_section = ".text.trampoline.boot")]
-pub unsafe extern "C" fn boot_entry(a0: usize, a1: usize) -> ! {
+pub unsafe extern "C" fn boot_entry(a0: usize, a1: usize, a2: usize) -> ! {
 	naked_asm!(
 		".align 2",
 		"mv sp, a0",
 		"csrw satp, a1",
 		"sfence.vma",
+		"mv a0, a2",
 		"sret",
 	)
 }
@@ -397,45 +681,8 @@ pub unsafe extern "C" fn trap_entry() {
 		save!(s10 => a1[26]),
 		save!(s11 => a1[27]),
 		save!(gp  => a1[28]),
+		// 浮点寄存器不在陷入路径保存，内核不使用它们，见 `FlowContext::save_live`
 
-		"csrr t0, sstatus",
-		"srli t0, t0, 13",
-		"andi t0, t0, 0b11", //FS
-		"li t1, 3",
-		"bne t0, t1, fs_not_dirty1", //if FS != Dirty, skip f reg save
-		fsave!(f0 =>  a1[35]),
-		fsave!(f1 =>  a1[36]),
-		fsave!(f2 =>  a1[37]),
-		fsave!(f3 =>  a1[38]),
-		fsave!(f4 =>  a1[39]),
-		fsave!(f5 =>  a1[40]),
-		fsave!(f6 =>  a1[41]),
-		fsave!(f7 =>  a1[42]),
-		fsave!(f8 =>  a1[43]),
-		fsave!(f9 =>  a1[44]),
-		fsave!(f10 => a1[45]),
-		fsave!(f11 => a1[46]),
-		fsave!(f12 => a1[47]),
-		fsave!(f13 => a1[48]),
-		fsave!(f14 => a1[49]),
-		fsave!(f15 => a1[50]),
-		fsave!(f16 => a1[51]),
-		fsave!(f17 => a1[52]),
-		fsave!(f18 => a1[53]),
-		fsave!(f19 => a1[54]),
-		fsave!(f20 => a1[55]),
-		fsave!(f21 => a1[56]),
-		fsave!(f22 => a1[57]),
-		fsave!(f23 => a1[58]),
-		fsave!(f24 => a1[59]),
-		fsave!(f25 => a1[60]),
-		fsave!(f26 => a1[61]),
-		fsave!(f27 => a1[62]),
-		fsave!(f28 => a1[63]),
-		fsave!(f29 => a1[64]),
-		fsave!(f30 => a1[65]),
-		fsave!(f31 => a1[66]),
-		"fs_not_dirty1:",
 		// 调用完整路径函数
 		//
 		// | reg    | position
@@ -467,40 +714,6 @@ pub unsafe extern "C" fn trap_entry() {
 		load!(a1[27] => s11),
 		load!(a1[28] => gp),
 
-		fload!(a1[35] => f0),
-		fload!(a1[36] => f1),
-		fload!(a1[37] => f2),
-		fload!(a1[38] => f3),
-		fload!(a1[39] => f4),
-		fload!(a1[40] => f5),
-		fload!(a1[41] => f6),
-		fload!(a1[42] => f7),
-		fload!(a1[43] => f8),
-		fload!(a1[44] => f9),
-		fload!(a1[45] => f10),
-		fload!(a1[46] => f11),
-		fload!(a1[47] => f12),
-		fload!(a1[48] => f13),
-		fload!(a1[49] => f14),
-		fload!(a1[50] => f15),
-		fload!(a1[51] => f16),
-		fload!(a1[52] => f17),
-		fload!(a1[53] => f18),
-		fload!(a1[54] => f19),
-		fload!(a1[55] => f20),
-		fload!(a1[56] => f21),
-		fload!(a1[57] => f22),
-		fload!(a1[58] => f23),
-		fload!(a1[59] => f24),
-		fload!(a1[60] => f25),
-		fload!(a1[61] => f26),
-		fload!(a1[62] => f27),
-		fload!(a1[63] => f28),
-		fload!(a1[64] => f29),
-		fload!(a1[65] => f30),
-		fload!(a1[66] => f31),
-		"li t0, (1 << 13)",
-		"csrc sstatus, t0", //csr clear FS dirty(11) to clean(10)
 		"2:", // 设置所有调用者寄存器
 		load!(a1[ 0] => ra),
 		load!(a1[ 1] => t0),
@@ -555,3 +768,33 @@ pub extern "C" fn trap_end(switch: bool) {
 
 	task_context_in_trap_stage().app_info().user_time.start();
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test_case]
+	fn ext_of_test() {
+		// fadd.d f1, f1, f2 和 fld f0, 0(a0)
+		assert_eq!(ext_of(0x022080d3), CpuFeatures::FP);
+		assert_eq!(ext_of(0x00053007), CpuFeatures::FP);
+		// c.fldsp 和 c.lw
+		assert_eq!(ext_of(0x2002), CpuFeatures::FP);
+		assert_eq!(ext_of(0x4000), CpuFeatures::empty());
+		// vle64.v v1, (a0) 和 vsetvli zero, zero, e8, m1
+		assert_eq!(ext_of(0x02057087), CpuFeatures::V);
+		assert_eq!(ext_of(0x00007057), CpuFeatures::V);
+		// vfadd.vv v3, v1, v2、vfadd.vf v4, v1, ft0 和 vfmv.f.s ft0, v3
+		assert_eq!(ext_of(0x021111d7), CpuFeatures::FP | CpuFeatures::V);
+		assert_eq!(ext_of(0x02105257), CpuFeatures::FP | CpuFeatures::V);
+		assert_eq!(ext_of(0x42301057), CpuFeatures::FP | CpuFeatures::V);
+		// csrr a0, fflags、vlenb 和 sstatus
+		assert_eq!(ext_of(0x00102573), CpuFeatures::FP);
+		assert_eq!(ext_of(0xc2202573), CpuFeatures::V);
+		assert_eq!(ext_of(0x10002573), CpuFeatures::empty());
+		assert_eq!(ext_of(0x00000073), CpuFeatures::empty());
+		assert_eq!(ext_of(0), CpuFeatures::FP | CpuFeatures::V);
+
+		crate::println!("ext_of_test passed!");
+	}
+}
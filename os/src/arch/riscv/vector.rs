@@ -0,0 +1,136 @@
+//! State of the vector extension, which the riscv crate lacks.
+//!
+//! The CSRs go by number, 0x008 vstart, 0x00f vcsr, 0xc20 vl, 0xc21 vtype
+//! and 0xc22 vlenb. The registers move as groups of eight by whole register
+//! loads and stores, which ignore vtype and vl.
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::asm;
+
+const SSTATUS_VS_SHIFT: usize = 9;
+/// vtype after reset, nothing is configured
+const VTYPE_VILL: usize = 1 << (usize::BITS - 1);
+const NO_HART: usize = usize::MAX;
+
+/// sstatus.VS, which has the encoding of FS
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VS {
+	Off = 0,
+	Initial = 1,
+	Clean = 2,
+	Dirty = 3,
+}
+
+pub fn vs() -> VS {
+	let sstatus: usize;
+	unsafe { asm!("csrr {}, sstatus", out(reg) sstatus) };
+	match (sstatus >> SSTATUS_VS_SHIFT) & 0b11 {
+		0 => VS::Off,
+		1 => VS::Initial,
+		2 => VS::Clean,
+		_ => VS::Dirty,
+	}
+}
+
+/// # Safety
+///
+/// With Off every vector instruction of this hart traps, a hart without V
+/// keeps Off anyway.
+pub unsafe fn set_vs(state: VS) {
+	unsafe {
+		asm!(
+			"csrc sstatus, {mask}",
+			"csrs sstatus, {bits}",
+			mask = in(reg) 0b11 << SSTATUS_VS_SHIFT,
+			bits = in(reg) (state as usize) << SSTATUS_VS_SHIFT,
+		);
+	}
+}
+
+/// Bytes of one vector register, VS must not be Off.
+pub fn vlenb() -> usize {
+	let vlenb;
+	unsafe { asm!("csrr {}, 0xc22", out(reg) vlenb) };
+	vlenb
+}
+
+/// Vector registers and CSRs of a task, made when it first uses V.
+pub struct VectorState {
+	vstart: usize,
+	vcsr: usize,
+	vl: usize,
+	vtype: usize,
+	/// v0 to v31, vlenb bytes each
+	regs: Vec<u8>,
+	/// the hart the state was last loaded on, see `FlowContext::owns_vector`
+	pub hart: usize,
+}
+
+impl VectorState {
+	/// Sized by the vlenb of this hart, VS must not be Off.
+	pub fn new() -> Self {
+		Self {
+			vstart: 0,
+			vcsr: 0,
+			vl: 0,
+			vtype: VTYPE_VILL,
+			regs: vec![0; 32 * vlenb()],
+			hart: NO_HART,
+		}
+	}
+
+	/// # Safety
+	///
+	/// VS must not be Off.
+	pub unsafe fn save(&mut self) {
+		unsafe {
+			asm!("csrr {}, 0x008", out(reg) self.vstart);
+			asm!("csrr {}, 0x00f", out(reg) self.vcsr);
+			asm!("csrr {}, 0xc20", out(reg) self.vl);
+			asm!("csrr {}, 0xc21", out(reg) self.vtype);
+			asm!(
+				".option push",
+				".option arch, +v",
+				"vs8r.v v0, ({ptr})",
+				"add {ptr}, {ptr}, {group}",
+				"vs8r.v v8, ({ptr})",
+				"add {ptr}, {ptr}, {group}",
+				"vs8r.v v16, ({ptr})",
+				"add {ptr}, {ptr}, {group}",
+				"vs8r.v v24, ({ptr})",
+				".option pop",
+				ptr = inout(reg) self.regs.as_mut_ptr() => _,
+				group = in(reg) self.regs.len() / 4,
+			);
+		}
+	}
+
+	/// # Safety
+	///
+	/// VS must not be Off.
+	pub unsafe fn load(&self) {
+		unsafe {
+			asm!(
+				".option push",
+				".option arch, +v",
+				"vl8re8.v v0, ({ptr})",
+				"add {ptr}, {ptr}, {group}",
+				"vl8re8.v v8, ({ptr})",
+				"add {ptr}, {ptr}, {group}",
+				"vl8re8.v v16, ({ptr})",
+				"add {ptr}, {ptr}, {group}",
+				"vl8re8.v v24, ({ptr})",
+				// the saved vl is at most VLMAX, so it comes back as it was
+				"vsetvl zero, {vl}, {vtype}",
+				".option pop",
+				ptr = inout(reg) self.regs.as_ptr() => _,
+				group = in(reg) self.regs.len() / 4,
+				vl = in(reg) self.vl,
+				vtype = in(reg) self.vtype,
+			);
+			// every vector instruction clears vstart, so it goes last
+			asm!("csrw 0x00f, {}", in(reg) self.vcsr);
+			asm!("csrw 0x008, {}", in(reg) self.vstart);
+		}
+	}
+}
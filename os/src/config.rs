@@ -3,19 +3,30 @@ pub const TICK_MS: usize = 10;
 pub const KERNEL_HEAP_SIZE: usize = 4 * 1024 * 1024;
 pub const KERNEL_STACK_SIZE: usize = 1024 * 1024;
 pub const KERNEL_STACK_ALIGN: usize = 4096;
+pub const KTHREAD_STACK_SIZE: usize = 16 * 1024;
+pub const TASK_KERNEL_STACK_SIZE: usize = 64 * 1024;
 pub const USER_STACK_SIZE: usize = 4 * 1024;
-pub const NUM_HART_MAX: usize = 8;
-pub const MAX_APP_NUM: usize = 20;
+/// room for the symbol table xtask puts into the kernel image
+pub const KSYMS_SIZE: usize = 256 * 1024;
+pub const MAX_APP_NUM: usize = 32;
+pub const MAX_THREAD_NUM: usize = 16;
+/// apps which may take harts offline and online again
+pub const HOTPLUG_APPS: &[&str] = &["21hotplug"];
+/// every syscall id is below it, see `TaskInfo`
+pub const MAX_SYSCALL_NUM: usize = 1024;
 pub const APP_BASE_ADDR: usize = 0x80a00000; //TODO: remove it
 pub const APP_VIRT_ADDR: usize = 0x1000;
 pub const TRAMPOLINE_VADDR: usize = usize::MAX - PAGE_SIZE + 1;
 pub const FLOW_CONTEXT_VADDR: usize = TRAMPOLINE_VADDR - PAGE_SIZE;
 pub const TRAP_HANDLER_VADDR: usize = TRAMPOLINE_VADDR - 2*PAGE_SIZE;
 pub const HART_CONTEXT_VADDR: usize = TRAMPOLINE_VADDR - 3*PAGE_SIZE;
+// every thread has its own flow context, trap handler and hart context page,
+// the slot of thread tid is tid slots below the constants above
+pub const THREAD_SLOT_SIZE: usize = 3 * PAGE_SIZE;
 pub const PAGE_SIZE: usize = 4 * 1024; //4k page size
 pub const PAGE_SIZE_BITS: usize = PAGE_SIZE.trailing_zeros() as usize;
 pub const MEMORY_END: usize = 0x8200_0000;
 
-// each hart should have a kernel stack,
-// but kernel stack num is depend on MAX_APP_NUM
-const _: () = assert!(NUM_HART_MAX <= MAX_APP_NUM);
+pub const fn thread_slot_vaddr(vaddr: usize, tid: usize) -> usize {
+	vaddr - tid * THREAD_SLOT_SIZE
+}
@@ -1,11 +1,27 @@
 #![macro_use]
 #![allow(static_mut_refs)]
 use alloc::boxed::Box;
-use alloc::string::String;
+use alloc::vec::Vec;
 use core::fmt::{self, Write};
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, Ordering};
+use log::{info, warn};
 use spin::Mutex;
 
+use crate::driver::chardev::riscvsbi::RiscvSbi;
+use crate::driver::irq::{register_irq, IrqHandler};
+use crate::error::SysResult;
 use crate::global::PLATFORM;
+use crate::sync::IrqMutex;
+use crate::task::preempt_point;
+use crate::task::wait_queue::WaitQueue;
+
+/// set by the panic handler, from then on printing does not wait for the lock
+static EMERGENCY: AtomicBool = AtomicBool::new(false);
+/// tries to take the console lock in emergency before writing without it
+const EMERGENCY_LOCK_SPINS: usize = 1 << 20;
+/// calls of a device write which take nothing before the output is dropped
+const STALL_LIMIT: usize = 1 << 20;
 
 #[derive(Clone, Copy, Debug)]
 pub enum ConsoleType {
@@ -33,39 +49,274 @@ pub(crate) trait ConsoleDevice: Send {
 	fn read(&self, buf: &mut [u8]) -> usize;
 	/// write bytes to console output
 	fn write(&self, buf: &[u8]) -> usize;
+	/// `write` which waits for the device itself, for a panic, when no
+	/// interrupt may come any more
+	fn write_polled(&self, buf: &[u8]) -> usize {
+		self.flush();
+		self.write(buf)
+	}
+	/// Wait until what `write` took is sent, e.g. before a shutdown.
+	fn flush(&self) {}
+	/// Switch to interrupt driven mode, called once the irq has a handler.
+	fn enable_irq(&self) {}
+	/// called by the irq handler
+	fn handle_irq(&self) {}
+	/// input came in by the irq and can be read at once
+	fn has_input(&self) -> bool {
+		false
+	}
 }
 
 pub struct KernelConsole {
-	inner: Mutex<Box<dyn ConsoleDevice>>, //spinlock
+	/// the irq handler takes it as well
+	inner: IrqMutex<Box<dyn ConsoleDevice>>,
+	/// tasks blocked in `read` until the irq brings input
+	readers: WaitQueue,
+	irq_mode: AtomicBool,
 }
 
 impl KernelConsole {
-	pub fn new(inner: Mutex<Box<dyn ConsoleDevice>>) -> Self {
-		Self { inner }
+	pub fn new(inner: IrqMutex<Box<dyn ConsoleDevice>>) -> Self {
+		Self { inner, readers: WaitQueue::new(), irq_mode: AtomicBool::new(false) }
+	}
+
+	/// Take input and output by `irq` from now on, keep polling if it fails.
+	pub fn init_irq(&'static self, irq: usize) {
+		let handler: IrqHandler = Box::new(move |_| {
+			self.inner.lock().handle_irq();
+			self.readers.wake_all();
+		});
+		match register_irq(irq, handler) {
+			Ok(()) => {
+				self.inner.lock().enable_irq();
+				self.irq_mode.store(true, Ordering::Release);
+				info!("console takes interrupt {}", irq);
+			}
+			Err(err) => warn!("console polls, interrupt {} is not available: {:?}", irq, err),
+		}
+	}
+
+	/// Read what is there, at most `buf.len()` bytes.
+	pub fn read(&self, buf: &mut [u8]) -> usize {
+		self.inner.lock().read(buf)
+	}
+
+	/// Block the current task until there is input, or EINTR for a signal.
+	///
+	/// A polled console has no irq to wake it, the task gets preempted instead.
+	pub fn wait_input(&self) -> SysResult<()> {
+		if self.irq_mode.load(Ordering::Acquire) {
+			self.readers.wait_unless(|| self.inner.lock().has_input())?;
+		} else {
+			preempt_point();
+		}
+		Ok(())
+	}
+
+	/// Write raw bytes, which need not be UTF-8.
+	pub fn write_bytes(&self, bytes: &[u8]) {
+		let device = self.inner.lock();
+		let _ = DeviceWriter::new(&**device).write_bytes(bytes);
+	}
+
+	/// Format `args` straight to the device, the lock is held for the whole message.
+	pub fn print(&self, args: fmt::Arguments) {
+		let device = self.inner.lock();
+		let _ = DeviceWriter::new(&**device).write_fmt(args);
+	}
+
+	/// `print` for a panic, the lock may be held by a hart which never lets it go.
+	///
+	/// When the device takes nothing the message goes to the SBI debug console.
+	fn print_emergency(&self, args: fmt::Arguments) {
+		let mut spins = 0;
+		let device = loop {
+			if let Some(device) = self.inner.try_lock() {
+				break device;
+			}
+			spins += 1;
+			if spins == EMERGENCY_LOCK_SPINS {
+				// SAFETY: the holder may still write as well, mixed output beats none
+				unsafe { self.inner.force_unlock() };
+			}
+			spin_loop();
+		};
+		if DeviceWriter::polled(&**device).write_fmt(args).is_err() {
+			let _ = DeviceWriter::new(&RiscvSbi).write_fmt(args);
+		}
 	}
 }
 
-impl fmt::Write for &KernelConsole {
-	fn write_str(&mut self, s: &str) -> fmt::Result {
-		let console = self.inner.lock();
-		let mut bytes = s.as_bytes();
+/// Writes to a console device as it formats, without allocating.
+struct DeviceWriter<'a> {
+	device: &'a dyn ConsoleDevice,
+	/// by `write_polled`
+	polled: bool,
+}
+
+impl<'a> DeviceWriter<'a> {
+	fn new(device: &'a dyn ConsoleDevice) -> Self {
+		Self { device, polled: false }
+	}
+
+	fn polled(device: &'a dyn ConsoleDevice) -> Self {
+		Self { device, polled: true }
+	}
+
+	fn write_bytes(&mut self, mut bytes: &[u8]) -> fmt::Result {
+		let mut stalls = 0;
 		while !bytes.is_empty() {
-			let count = console.write(bytes);
+			let count = if self.polled {
+				self.device.write_polled(bytes)
+			} else {
+				self.device.write(bytes)
+			};
+			if count == 0 {
+				// e.g. the SBI has no debug console
+				stalls += 1;
+				if stalls == STALL_LIMIT {
+					return Err(fmt::Error);
+				}
+			}
 			bytes = &bytes[count..];
 		}
-		Ok(()) //TODO: error handle
+		Ok(())
+	}
+}
+
+impl fmt::Write for DeviceWriter<'_> {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		self.write_bytes(s.as_bytes())
 	}
 }
 
+fn kernel_console() -> Option<&'static KernelConsole> {
+	PLATFORM.get()?.board_device.console.as_ref()
+}
+
 pub fn print(args: fmt::Arguments) {
-	let mut s = String::new();
-	let _ = core::fmt::write(&mut s, args); //TODO: error handle
-	PLATFORM.get()
-		.unwrap()
-		.board_device
-		.console
-		.as_ref()
-		.unwrap()
-		.write_str(&s)
-		.unwrap();
+	match kernel_console() {
+		Some(console) if in_emergency() => console.print_emergency(args),
+		Some(console) => console.print(args),
+		// before the platform is up, or it has no console we know
+		None => {
+			let _ = DeviceWriter::new(&RiscvSbi).write_fmt(args);
+		}
+	}
+}
+
+/// Write raw bytes to the console, e.g. what apps write to stdout.
+pub fn write_bytes(bytes: &[u8]) {
+	match kernel_console() {
+		Some(console) => console.write_bytes(bytes),
+		None => {
+			let _ = DeviceWriter::new(&RiscvSbi).write_bytes(bytes);
+		}
+	}
+}
+
+/// Send what is still queued in the console, call before a shutdown.
+pub fn flush() {
+	if let Some(console) = kernel_console() {
+		console.inner.lock().flush();
+	}
+}
+
+/// Switch the console to its interrupt, if the devicetree gives one.
+///
+/// Called once the irq controller and the device mappings are up.
+pub fn init_irq() {
+	let Some(platform) = PLATFORM.get() else {
+		return;
+	};
+	if let (Some(console), Some(irq)) =
+		(platform.board_device.console.as_ref(), platform.board_info.console_irq) {
+		console.init_irq(irq);
+	}
+}
+
+/// Read console input into `buf`, at most its length.
+///
+/// Return 0 if there is nothing yet, see `wait_input`.
+pub fn read(buf: &mut [u8]) -> usize {
+	match kernel_console() {
+		Some(console) => console.read(buf),
+		None => RiscvSbi.read(buf),
+	}
+}
+
+/// Block the current task until `read` may return something, or EINTR for a signal.
+pub fn wait_input() -> SysResult<()> {
+	match kernel_console() {
+		Some(console) => console.wait_input(),
+		None => {
+			preempt_point();
+			Ok(())
+		}
+	}
+}
+
+/// From now on the console is only used to report a panic, see `print_emergency`.
+pub fn enter_emergency() {
+	EMERGENCY.store(true, Ordering::Relaxed);
+}
+
+pub fn in_emergency() -> bool {
+	EMERGENCY.load(Ordering::Relaxed)
+}
+
+/// a line longer than this is written in pieces
+const LINE_BUF_SIZE: usize = 1024;
+
+/// Output of one task, written to the console a line at a time, so the
+/// lines of tasks on other harts do not mix with it.
+pub struct LineBuffer {
+	buf: Mutex<Vec<u8>>,
+}
+
+impl LineBuffer {
+	pub const fn new() -> Self {
+		Self { buf: Mutex::new(Vec::new()) }
+	}
+
+	/// Buffer `bytes` and write out every complete line of task `task_id`.
+	pub fn write(&self, bytes: &[u8], task_id: usize) {
+		let mut buf = self.buf.lock();
+		buf.extend_from_slice(bytes);
+		let end = match buf.iter().rposition(|&byte| byte == b'\n') {
+			Some(newline) => newline + 1,
+			None if buf.len() >= LINE_BUF_SIZE => buf.len(),
+			None => return,
+		};
+		write_lines(&buf[..end], task_id);
+		buf.drain(..end);
+	}
+
+	/// Write out the last unfinished line, e.g. when the task exits.
+	pub fn flush(&self, task_id: usize) {
+		let mut buf = self.buf.lock();
+		if !buf.is_empty() {
+			write_lines(&buf, task_id);
+			buf.clear();
+		}
+	}
+}
+
+/// Write `lines` at once, each starts with the task id with the `task_prefix` feature.
+fn write_lines(lines: &[u8], task_id: usize) {
+	#[cfg(feature = "task_prefix")]
+	{
+		let prefix = alloc::format!("[task {:>2}] ", task_id);
+		let mut out = Vec::with_capacity(lines.len() + prefix.len());
+		for line in lines.split_inclusive(|&byte| byte == b'\n') {
+			out.extend_from_slice(prefix.as_bytes());
+			out.extend_from_slice(line);
+		}
+		write_bytes(&out);
+	}
+	#[cfg(not(feature = "task_prefix"))]
+	{
+		let _ = task_id;
+		write_bytes(lines);
+	}
 }
@@ -0,0 +1,252 @@
+//! Logical CPUs, the harts the kernel runs on.
+//!
+//! Hart ids come from `reg` of the cpu nodes and need not be dense, logical
+//! CPUs are numbered from 0 in devicetree order. Data of each hart is kept
+//! in a `PerCpu`, indexed by hart id.
+//!
+//! Tasks move between harts, so optional extensions are only used when every
+//! CPU has them, see `features`.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use core::ops::Index;
+use spin::Once;
+
+use crate::global::PLATFORM;
+
+bitflags! {
+	/// ISA extensions of a hart, named as in `riscv,isa-extensions`.
+	#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+	pub struct CpuFeatures: u32 {
+		const I = 1 << 0;
+		const M = 1 << 1;
+		const A = 1 << 2;
+		const F = 1 << 3;
+		const D = 1 << 4;
+		const C = 1 << 5;
+		const V = 1 << 6;
+		const H = 1 << 7;
+		const ZICSR = 1 << 8;
+		const ZIFENCEI = 1 << 9;
+		const ZICBOM = 1 << 10;
+		const ZICBOZ = 1 << 11;
+		const ZIHINTPAUSE = 1 << 12;
+		const ZBA = 1 << 13;
+		const ZBB = 1 << 14;
+		const ZBS = 1 << 15;
+		const SSTC = 1 << 16;
+		const SVPBMT = 1 << 17;
+		const SVNAPOT = 1 << 18;
+		const SVINVAL = 1 << 19;
+	}
+}
+
+impl CpuFeatures {
+	/// what the kernel code itself uses, the rest is checked at runtime
+	pub const REQUIRED: Self = Self::I.union(Self::M).union(Self::A).union(Self::C);
+	/// the FP registers the kernel saves are the 64 bit ones of D
+	pub const FP: Self = Self::F.union(Self::D);
+
+	fn add(&mut self, name: &str) {
+		// unknown extensions and version numbers are ignored
+		if let Some(ext) = Self::from_name(&name.to_ascii_uppercase()) {
+			*self |= ext;
+		}
+	}
+
+	/// Parse a `riscv,isa` string, None if it is not rv64.
+	pub fn from_isa(isa: &str) -> Option<Self> {
+		let isa = isa.to_ascii_lowercase();
+		let rest = isa.strip_prefix("rv64")?;
+		// multi letter extensions start with z, s or x, or follow an underscore
+		let split = rest.find(['_', 'z', 's', 'x']).unwrap_or(rest.len());
+		let (letters, multi) = rest.split_at(split);
+		let mut features = Self::empty();
+		for letter in letters.chars() {
+			if letter == 'g' {
+				features |= Self::I | Self::M | Self::A | Self::FP | Self::ZICSR | Self::ZIFENCEI;
+			} else {
+				features.add(letter.encode_utf8(&mut [0; 4]));
+			}
+		}
+		multi.split('_').for_each(|name| features.add(name));
+		Some(features)
+	}
+
+	/// Parse the strings of `riscv,isa-extensions`.
+	pub fn from_extensions<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+		let mut features = Self::empty();
+		names.into_iter().for_each(|name| features.add(name));
+		features
+	}
+}
+
+pub struct CpuInfo {
+	pub hartid: usize,
+	/// `riscv,isa`, e.g. "rv64imafdc_zicsr_zifencei"
+	pub isa: String,
+	pub features: CpuFeatures,
+}
+
+pub struct CpuMap {
+	cpus: Vec<CpuInfo>,
+	/// logical CPU of each hart id, None for harts the kernel does not run on
+	cpu_of_hart: Vec<Option<usize>>,
+	/// extensions every CPU has
+	common: CpuFeatures,
+}
+
+impl CpuMap {
+	pub const EMPTY: Self = Self {
+		cpus: Vec::new(),
+		cpu_of_hart: Vec::new(),
+		common: CpuFeatures::empty(),
+	};
+
+	pub fn new(cpus: Vec<CpuInfo>) -> Self {
+		let bound = cpus.iter().map(|info| info.hartid + 1).max().unwrap_or(0);
+		let mut cpu_of_hart = vec![None; bound];
+		for (cpu, info) in cpus.iter().enumerate() {
+			cpu_of_hart[info.hartid] = Some(cpu);
+		}
+		let common = cpus.iter()
+			.map(|info| info.features)
+			.reduce(CpuFeatures::intersection)
+			.unwrap_or(CpuFeatures::empty());
+		Self { cpus, cpu_of_hart, common }
+	}
+
+	pub fn features(&self) -> CpuFeatures {
+		self.common
+	}
+
+	pub fn num_cpus(&self) -> usize {
+		self.cpus.len()
+	}
+
+	pub fn cpu_id(&self, hartid: usize) -> Option<usize> {
+		self.cpu_of_hart.get(hartid).copied().flatten()
+	}
+
+	pub fn cpus(&self) -> &[CpuInfo] {
+		&self.cpus
+	}
+
+	pub fn hart_ids(&self) -> impl Iterator<Item = usize> + '_ {
+		self.cpus.iter().map(|info| info.hartid)
+	}
+}
+
+fn cpu_map() -> Option<&'static CpuMap> {
+	PLATFORM.get().map(|platform| &platform.board_info.cpus)
+}
+
+pub fn num_cpus() -> usize {
+	cpu_map().map_or(0, CpuMap::num_cpus)
+}
+
+/// Logical CPU of `hartid`, None if the kernel does not run on it.
+pub fn cpu_id(hartid: usize) -> Option<usize> {
+	cpu_map()?.cpu_id(hartid)
+}
+
+/// Extensions every CPU has, which the kernel may use on any hart.
+pub fn features() -> CpuFeatures {
+	cpu_map().map_or(CpuFeatures::empty(), CpuMap::features)
+}
+
+/// Tasks may use the FP registers, which the kernel then switches.
+pub fn has_fp() -> bool {
+	features().contains(CpuFeatures::FP)
+}
+
+/// Hart ids of all logical CPUs, in their order.
+pub fn hart_ids() -> impl Iterator<Item = usize> {
+	cpu_map().into_iter().flat_map(CpuMap::hart_ids)
+}
+
+/// One `T` for each logical CPU, indexed by hart id.
+///
+/// The slots are created on first use, once the devicetree is parsed.
+pub struct PerCpu<T> {
+	slots: Once<Vec<T>>,
+	init: fn() -> T,
+}
+
+// SAFETY: the slots are written once under the Once, then only shared
+unsafe impl<T: Sync> Sync for PerCpu<T> {}
+
+impl<T> PerCpu<T> {
+	pub const fn new(init: fn() -> T) -> Self {
+		Self { slots: Once::new(), init }
+	}
+
+	fn slots(&self) -> &[T] {
+		// no hart is known before the platform is up, keep nothing yet
+		if num_cpus() == 0 {
+			return &[];
+		}
+		self.slots.call_once(|| (0..num_cpus()).map(|_| (self.init)()).collect())
+	}
+
+	/// None if the kernel does not run on `hartid`.
+	pub fn get(&self, hartid: usize) -> Option<&T> {
+		self.slots().get(cpu_id(hartid)?)
+	}
+}
+
+impl<T> Index<usize> for PerCpu<T> {
+	type Output = T;
+
+	fn index(&self, hartid: usize) -> &T {
+		self.get(hartid)
+			.unwrap_or_else(|| panic!("the kernel does not run on hart {}", hartid))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test_case]
+	fn cpu_map_test() {
+		// hart 0 is a monitor core without MMU, as on some boards
+		let cpu = |hartid, isa: &str| CpuInfo {
+			hartid,
+			isa: String::from(isa),
+			features: CpuFeatures::from_isa(isa).unwrap(),
+		};
+		let map = CpuMap::new(vec![cpu(1, "rv64imafdc_sstc"), cpu(4, "rv64imac_sstc")]);
+		assert_eq!(map.num_cpus(), 2);
+		assert_eq!(map.cpu_id(0), None);
+		assert_eq!(map.cpu_id(1), Some(0));
+		assert_eq!(map.cpu_id(4), Some(1));
+		assert_eq!(map.cpu_id(9), None);
+		assert!(map.hart_ids().eq([1, 4]));
+		// hart 4 has no FP, so no task may use it
+		assert_eq!(map.features(), CpuFeatures::REQUIRED | CpuFeatures::SSTC);
+
+		crate::println!("cpu_map_test passed!");
+	}
+
+	#[test_case]
+	fn cpu_features_test() {
+		let gc = CpuFeatures::from_isa("rv64gc").unwrap();
+		assert!(gc.contains(CpuFeatures::REQUIRED | CpuFeatures::FP | CpuFeatures::ZIFENCEI));
+		let qemu = CpuFeatures::from_isa("rv64imafdch_zicbom_zicboz_zicsr_svpbmt_sstc").unwrap();
+		assert!(qemu.contains(CpuFeatures::H | CpuFeatures::ZICBOM | CpuFeatures::SVPBMT | CpuFeatures::SSTC));
+		assert!(!qemu.contains(CpuFeatures::V));
+		assert!(CpuFeatures::from_isa("RV64IMACV").unwrap().contains(CpuFeatures::V));
+		assert_eq!(CpuFeatures::from_isa("rv32imafdc"), None);
+		// the c in zicsr is not the C extension
+		let zicsr = CpuFeatures::from_isa("rv64imafdzicsr").unwrap();
+		assert!(!zicsr.contains(CpuFeatures::C));
+		assert!(zicsr.contains(CpuFeatures::ZICSR));
+		let listed = CpuFeatures::from_extensions(["i", "m", "a", "c", "zicboz", "foo"]);
+		assert_eq!(listed, CpuFeatures::REQUIRED | CpuFeatures::ZICBOZ);
+
+		crate::println!("cpu_features_test passed!");
+	}
+}
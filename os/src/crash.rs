@@ -0,0 +1,205 @@
+use core::cell::SyncUnsafeCell;
+use core::hint::spin_loop;
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::arch::common::{ArchHarts, ArchTime, ArchTrap};
+use crate::cpu::PerCpu;
+use crate::global::{ARCH, FRAME_ALLOCATOR, TASK_MANAGER};
+use crate::harts::{hart_id_if_running, online_harts};
+use crate::println;
+use crate::task::block::TaskControlBlock;
+use crate::task::processor::current_processor;
+
+/// `xtask crash` cuts the dump out of a console log by these lines
+const BEGIN_MARKER: &str = "-----BEGIN PIANOOS CRASH-----";
+const END_MARKER: &str = "-----END PIANOOS CRASH-----";
+/// how long the panicking hart waits for the others to stop
+const STOP_TIMEOUT_MS: u64 = 100;
+const NO_HART: usize = usize::MAX;
+
+static PANICKED: AtomicBool = AtomicBool::new(false);
+/// hart which panicked first, it dumps the others
+static PANIC_HART: AtomicUsize = AtomicUsize::new(NO_HART);
+/// set once the other harts are asked to stop
+static STOPPING: AtomicBool = AtomicBool::new(false);
+static REPORTS: PerCpu<HartReport> = PerCpu::new(HartReport::new);
+
+/// What a hart was doing when it stopped.
+#[derive(Clone, Copy)]
+enum Stopped {
+	/// panicked as well, after the first one
+	Panic,
+	/// at a poll point in the kernel, maybe in the middle of a syscall,
+	/// with sepc, scause, stval of the last trap and ra, sp of the poll point
+	Kernel { task: Option<usize>, at: &'static Location<'static>, regs: [usize; 5] },
+	/// in an app, with all of its registers
+	User { task: usize, app: usize, regs: [usize; 32] },
+}
+
+/// Written once by its hart before it parks, read by the panicking hart.
+struct HartReport {
+	done: AtomicBool,
+	stopped: SyncUnsafeCell<Stopped>,
+}
+
+impl HartReport {
+	const fn new() -> Self {
+		Self { done: AtomicBool::new(false), stopped: SyncUnsafeCell::new(Stopped::Panic) }
+	}
+}
+
+/// How the panic handler should go on.
+pub enum Panicking {
+	/// the first panic, this hart dumps the others
+	First,
+	/// the panic handler of this hart panicked, e.g. in the logger
+	Again,
+	/// another hart panicked first, this one waits to be dumped
+	Other,
+}
+
+/// Tell the panic handler who dumps, called before it prints anything.
+pub fn enter_panic() -> Panicking {
+	let me = hart_id_if_running().unwrap_or(NO_HART);
+	if !PANICKED.swap(true, Ordering::AcqRel) {
+		PANIC_HART.store(me, Ordering::Release);
+		return Panicking::First;
+	}
+	// before run_tasks a hart can not tell itself from the others
+	if me == NO_HART || PANIC_HART.load(Ordering::Acquire) == me {
+		Panicking::Again
+	} else {
+		Panicking::Other
+	}
+}
+
+pub fn stop_requested() -> bool {
+	STOPPING.load(Ordering::Acquire)
+}
+
+/// Stop here if a crash dump is going on, for kernel code which polls with
+/// interrupts off, e.g. the idle flow and `preempt_point`.
+#[track_caller]
+#[inline(never)]
+pub fn poll_stop() {
+	// ra is gone after the first call
+	let [ra, sp] = ARCH.caller_regs();
+	if stop_requested() {
+		let task = current_processor().current_task();
+		let [sepc, scause, stval] = ARCH.trap_regs();
+		let regs = [sepc, scause, stval, ra, sp];
+		stop_this_hart(Stopped::Kernel { task, at: Location::caller(), regs });
+	}
+}
+
+/// Stop a hart which took the stop IPI in user mode.
+///
+/// The flow context must hold every user register.
+pub fn stop_in_user(tcb: &TaskControlBlock) -> ! {
+	stop_this_hart(Stopped::User {
+		task: tcb.id(),
+		app: tcb.process.app_id,
+		regs: tcb.flow_context().user_regs(),
+	})
+}
+
+/// Stop a hart which panicked after another one, see `Panicking::Other`.
+pub fn stop_panicked() -> ! {
+	stop_this_hart(Stopped::Panic)
+}
+
+fn stop_this_hart(stopped: Stopped) -> ! {
+	if let Some(hartid) = hart_id_if_running() {
+		let report = &REPORTS[hartid];
+		unsafe { *report.stopped.get() = stopped };
+		report.done.store(true, Ordering::Release);
+	}
+	ARCH.park()
+}
+
+/// Stop the other harts and print what they and all tasks were doing.
+///
+/// Locks held by a stopped hart are never taken, what is behind them is
+/// reported as busy. The block goes between markers for `cargo xtask crash`.
+pub fn dump() {
+	let me = hart_id_if_running();
+	STOPPING.store(true, Ordering::Release);
+	// only harts which run tasks can answer the stop request
+	let others = || online_harts().filter(move |&hartid| Some(hartid) != me);
+	others().for_each(|hartid| ARCH.send_ipi(hartid));
+	let deadline = ARCH.time_ms() + STOP_TIMEOUT_MS;
+	while ARCH.time_ms() < deadline
+		&& !others().all(|hartid| REPORTS[hartid].done.load(Ordering::Acquire)) {
+		spin_loop();
+	}
+
+	println!("{}", BEGIN_MARKER);
+	if let Some(hartid) = me {
+		println!("hart {} panic", hartid);
+	}
+	others().for_each(print_hart);
+	print_tasks();
+	match FRAME_ALLOCATOR.get().map(|allocator| allocator.try_usage()) {
+		Some(Some((total, free))) => println!("frames total {} free {}", total, free),
+		Some(None) => println!("frames busy"),
+		None => {}
+	}
+	println!("{}", END_MARKER);
+}
+
+fn print_hart(hartid: usize) {
+	let report = &REPORTS[hartid];
+	if !report.done.load(Ordering::Acquire) {
+		println!("hart {} silent", hartid);
+		return;
+	}
+	match unsafe { *report.stopped.get() } {
+		Stopped::Panic => println!("hart {} panic", hartid),
+		Stopped::Kernel { task, at, regs } => {
+			match task {
+				Some(task) => println!("hart {} kernel task {} at {}", hartid, task, at),
+				None => println!("hart {} kernel task - at {}", hartid, at),
+			}
+			// sepc scause stval ra sp
+			println!("hart {} regs {}", hartid, Hex(&regs));
+		}
+		Stopped::User { task, app, regs } => {
+			println!("hart {} user task {} app {}", hartid, task, app);
+			// in the order of FlowContext::user_regs
+			println!("hart {} regs {}", hartid, Hex(&regs));
+		}
+	}
+}
+
+/// status, pc and sp of every task, pc and sp are where it left user mode
+fn print_tasks() {
+	let Some(task_manager) = TASK_MANAGER.get() else {
+		return;
+	};
+	let Some(tasks) = task_manager.try_tasks() else {
+		println!("tasks busy");
+		return;
+	};
+	for tcb in tasks.iter().flatten() {
+		let ctx = tcb.flow_context();
+		println!(
+			"task {} app {} tid {} status {:?} pc {:#x} sp {:#x}",
+			tcb.id(), tcb.process.app_id, tcb.tid, tcb.status(), ctx.pc, ctx.sp
+		);
+	}
+}
+
+struct Hex<'a>(&'a [usize]);
+
+impl core::fmt::Display for Hex<'_> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		for (i, val) in self.0.iter().enumerate() {
+			if i > 0 {
+				write!(f, " ")?;
+			}
+			write!(f, "{:#x}", val)?;
+		}
+		Ok(())
+	}
+}
@@ -1,3 +1,5 @@
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::ops::Range;
 use serde::Deserialize;
 use serde_device_tree::{
@@ -71,6 +73,63 @@ pub fn get_compatible_and_range<'de>(node: &Node) -> Option<(StrSeq<'de>, Range<
 	}
 }
 
+pub fn is_compatible(node: &Node, compats: &[&str]) -> bool {
+	node.get_prop("compatible")
+	    .map(|prop| prop.deserialize::<StrSeq>().iter().any(|compat| compats.contains(&compat)))
+	    .unwrap_or(false)
+}
+
+/// The first node below `node` for which `pred` holds, depth first.
+pub fn find_node<'de>(node: &Node<'de>, pred: &dyn Fn(&Node<'de>) -> bool) -> Option<Node<'de>> {
+	node.nodes().find_map(|item| {
+		let child: Node<'de> = item.deserialize();
+		if pred(&child) {
+			Some(child)
+		} else {
+			find_node(&child, pred)
+		}
+	})
+}
+
+/// The first node below `node` compatible with one of `compats`.
+pub fn find_compatible<'de>(node: &Node<'de>, compats: &[&str]) -> Option<Node<'de>> {
+	find_node(node, &|child| is_compatible(child, compats))
+}
+
+/// A property which is a single cell, e.g. `riscv,ndev`.
+pub fn get_u32(node: &Node, name: &str) -> Option<u32> {
+	node.get_prop(name).map(|prop| prop.deserialize::<u32>())
+}
+
+/// The first cell of a property, e.g. the number in `interrupts`, which has
+/// one cell with a PLIC and also the trigger type with an APLIC.
+pub fn get_first_cell(node: &Node, name: &str) -> Option<u32> {
+	let bytes = node.get_prop(name)?.deserialize::<&[u8]>();
+	Some(u32::from_be_bytes(bytes.get(..4)?.try_into().unwrap()))
+}
+
+/// All cells of a property, e.g. `interrupts-extended`.
+pub fn get_cells(node: &Node, name: &str) -> Vec<u32> {
+	node.get_prop(name)
+	    .map(|prop| prop.deserialize::<&[u8]>()
+		.chunks_exact(4)
+		.map(|cell| u32::from_be_bytes(cell.try_into().unwrap()))
+		.collect())
+	    .unwrap_or_default()
+}
+
+/// The first string of a property, e.g. `status`.
+pub fn get_str(node: &Node, name: &str) -> Option<String> {
+	node.get_prop(name)?.deserialize::<StrSeq>().iter().next().map(String::from)
+}
+
+/// All strings of a property, e.g. `riscv,isa-extensions`.
+pub fn get_strs(node: &Node, name: &str) -> Vec<String> {
+	node.get_prop(name)
+	    .map(|prop| prop.deserialize::<StrSeq>().iter().map(String::from).collect())
+	    .unwrap_or_default()
+}
+
 pub fn parse_device_tree(opaque: usize) -> Result<Dtb, ParseDeviceTreeError> {
 	// this will also check the validity of the dtb header
 	let Ok(ptr) = DtbPtr::from_raw(opaque as *mut _) else {
@@ -1,2 +1,3 @@
 pub mod riscvsbi;
+pub mod ring;
 pub mod uart16550;
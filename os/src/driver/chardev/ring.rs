@@ -0,0 +1,81 @@
+/// Fixed size byte FIFO, it never allocates, so interrupt handlers can use it.
+pub struct RingBuffer<const N: usize> {
+	buf: [u8; N],
+	head: usize,
+	len: usize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+	pub const fn new() -> Self {
+		Self { buf: [0; N], head: 0, len: 0 }
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	pub fn is_full(&self) -> bool {
+		self.len == N
+	}
+
+	/// Return false if it is full, the byte is dropped then.
+	pub fn push(&mut self, byte: u8) -> bool {
+		if self.is_full() {
+			return false;
+		}
+		self.buf[(self.head + self.len) % N] = byte;
+		self.len += 1;
+		true
+	}
+
+	pub fn pop(&mut self) -> Option<u8> {
+		if self.is_empty() {
+			return None;
+		}
+		let byte = self.buf[self.head];
+		self.head = (self.head + 1) % N;
+		self.len -= 1;
+		Some(byte)
+	}
+
+	/// Push bytes of `bytes` until it is full, return how many were taken.
+	pub fn push_slice(&mut self, bytes: &[u8]) -> usize {
+		bytes.iter().take_while(|&&byte| self.push(byte)).count()
+	}
+
+	/// Pop into `buf` until it is empty, return how many were popped.
+	pub fn pop_slice(&mut self, buf: &mut [u8]) -> usize {
+		let mut count = 0;
+		for slot in buf.iter_mut() {
+			let Some(byte) = self.pop() else {
+				break;
+			};
+			*slot = byte;
+			count += 1;
+		}
+		count
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test_case]
+	fn ring_buffer_test() {
+		let mut ring = RingBuffer::<4>::new();
+		assert!(ring.is_empty());
+		assert_eq!(ring.push_slice(b"abc"), 3);
+		assert_eq!(ring.pop(), Some(b'a'));
+		// wraps around the end
+		assert_eq!(ring.push_slice(b"defg"), 2);
+		assert!(ring.is_full());
+		assert!(!ring.push(b'h'));
+		let mut buf = [0; 8];
+		assert_eq!(ring.pop_slice(&mut buf), 4);
+		assert_eq!(&buf[..4], b"bcde");
+		assert_eq!(ring.pop(), None);
+
+		crate::println!("ring_buffer_test passed!");
+	}
+}
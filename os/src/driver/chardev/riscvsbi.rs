@@ -2,19 +2,17 @@ use sbi_rt::Physical;
 
 use crate::console::ConsoleDevice;
 
+/// The SBI debug console, also the fallback when there is no other console.
 pub struct RiscvSbi;
 
 impl ConsoleDevice for RiscvSbi {
 	/// read bytes from console input
 	fn read(&self, buf: &mut [u8]) -> usize {
-		sbi_rt::console_read(Physical::new(buf.len(),
-						   buf.as_ptr() as usize,
-						   buf[buf.len() - 1] as usize)).value
+		// the kernel is mapped one to one, so the buffer address is physical
+		sbi_rt::console_read(Physical::new(buf.len(), buf.as_ptr() as usize, 0)).value
 	}
 	/// write bytes to console output
 	fn write(&self, buf: &[u8]) -> usize {
-		sbi_rt::console_write(Physical::new(buf.len(),
-						    buf.as_ptr() as usize,
-						    buf[buf.len() - 1] as usize)).value
+		sbi_rt::console_write(Physical::new(buf.len(), buf.as_ptr() as usize, 0)).value
 	}
 }
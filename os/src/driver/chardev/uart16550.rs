@@ -1,25 +1,152 @@
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use uart16550::{Register, Uart16550};
 
 use crate::console::ConsoleDevice;
+use crate::driver::chardev::ring::RingBuffer;
 use crate::platform::BaseAddr;
+use crate::sync::IrqMutex;
+
+const RX_BUF_SIZE: usize = 1024;
+const TX_BUF_SIZE: usize = 4096;
+
+// register indexes, the byte offset is the index times the register width
+const RBR_THR: usize = 0;
+const IER: usize = 1;
+const IIR: usize = 2;
+const MCR: usize = 4;
+const LSR: usize = 5;
 
+/// received data available
+const IER_RX: u8 = 1 << 0;
+/// transmitter holding register empty
+const IER_TX: u8 = 1 << 1;
+/// gates the interrupt line on PC style boards
+const MCR_OUT2: u8 = 1 << 3;
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+/// A 16550 UART, polled until `enable_irq`.
+///
+/// With the irq, received bytes are kept in `rx` by the handler, and written
+/// ones are queued in `tx` and sent as the transmitter gets empty. Polling
+/// stays for early boot and `write_polled`.
 pub struct Uart16550Wrapper<R: Register> {
 	inner: *const Uart16550<R>,
+	base: BaseAddr,
+	irq_mode: AtomicBool,
+	rx: IrqMutex<RingBuffer<RX_BUF_SIZE>>,
+	tx: IrqMutex<RingBuffer<TX_BUF_SIZE>>,
+	_reg: PhantomData<R>,
 }
 
 impl<R: Register> Uart16550Wrapper<R> {
 	pub fn new(base: BaseAddr) -> Self {
-		Uart16550Wrapper { inner: base as *const Uart16550<R> }
+		Uart16550Wrapper {
+			inner: base as *const Uart16550<R>,
+			base,
+			irq_mode: AtomicBool::new(false),
+			rx: IrqMutex::new(RingBuffer::new()),
+			tx: IrqMutex::new(RingBuffer::new()),
+			_reg: PhantomData,
+		}
+	}
+
+	fn reg_read(&self, reg: usize) -> u8 {
+		let addr = self.base + reg * size_of::<R>();
+		// SAFETY: the registers are mapped by mm::init, wide ones are accessed whole
+		unsafe {
+			match size_of::<R>() {
+				4 => (addr as *const u32).read_volatile() as u8,
+				_ => (addr as *const u8).read_volatile(),
+			}
+		}
+	}
+
+	fn reg_write(&self, reg: usize, val: u8) {
+		let addr = self.base + reg * size_of::<R>();
+		unsafe {
+			match size_of::<R>() {
+				4 => (addr as *mut u32).write_volatile(val as u32),
+				_ => (addr as *mut u8).write_volatile(val),
+			}
+		}
+	}
+
+	/// Move queued bytes to the transmitter while it takes them, the THR
+	/// empty interrupt stays on as long as some are left.
+	fn pump_tx(&self, tx: &mut RingBuffer<TX_BUF_SIZE>) {
+		while !tx.is_empty() && self.reg_read(LSR) & LSR_THR_EMPTY != 0 {
+			self.reg_write(RBR_THR, tx.pop().unwrap());
+		}
+		let ier = self.reg_read(IER);
+		let want = if tx.is_empty() { ier & !IER_TX } else { ier | IER_TX };
+		if want != ier {
+			self.reg_write(IER, want);
+		}
+	}
+
+	fn drain_rx(&self) {
+		let mut rx = self.rx.lock();
+		while self.reg_read(LSR) & LSR_DATA_READY != 0 {
+			// on overflow the newest bytes are dropped, like the FIFO does
+			rx.push(self.reg_read(RBR_THR));
+		}
 	}
 }
 
 impl<R: Register> ConsoleDevice for Uart16550Wrapper<R> {
 	fn read(&self, buf: &mut [u8]) -> usize {
-		unsafe { (*self.inner).read(buf) }
+		if self.irq_mode.load(Ordering::Acquire) {
+			self.rx.lock().pop_slice(buf)
+		} else {
+			unsafe { (*self.inner).read(buf) }
+		}
 	}
+
 	fn write(&self, buf: &[u8]) -> usize {
+		if !self.irq_mode.load(Ordering::Acquire) {
+			return unsafe { (*self.inner).write(buf) };
+		}
+		let mut tx = self.tx.lock();
+		let count = tx.push_slice(buf);
+		// the writer pumps as well, a full queue then drains without the irq
+		self.pump_tx(&mut tx);
+		count
+	}
+
+	fn write_polled(&self, buf: &[u8]) -> usize {
+		// what is queued goes first, or the output would be out of order
+		self.flush();
 		unsafe { (*self.inner).write(buf) }
 	}
+
+	fn flush(&self) {
+		// in a panic the queue may be locked by a stopped hart, it is lost then
+		if let Some(mut tx) = self.tx.try_lock() {
+			while !tx.is_empty() {
+				self.pump_tx(&mut tx);
+			}
+		}
+	}
+
+	fn enable_irq(&self) {
+		self.irq_mode.store(true, Ordering::Release);
+		self.reg_write(MCR, self.reg_read(MCR) | MCR_OUT2);
+		self.reg_write(IER, self.reg_read(IER) | IER_RX);
+	}
+
+	fn handle_irq(&self) {
+		// reading IIR acknowledges a THR empty interrupt
+		self.reg_read(IIR);
+		self.drain_rx();
+		self.pump_tx(&mut self.tx.lock());
+	}
+
+	fn has_input(&self) -> bool {
+		!self.rx.lock().is_empty()
+	}
 }
 
 unsafe impl<R: Register> Send for Uart16550Wrapper<R> {}
@@ -0,0 +1,102 @@
+use core::ptr::{read_volatile, write_volatile};
+
+use alloc::vec::Vec;
+
+use crate::driver::irq::IrqController;
+use crate::driver::irq::imsic::Imsic;
+use crate::platform::BaseAddr;
+
+const DOMAINCFG: usize = 0x0;
+const SOURCECFG_BASE: usize = 0x4;
+const SETIPNUM: usize = 0x1cdc;
+const IN_CLRIP_BASE: usize = 0x1d00;
+const SETIENUM: usize = 0x1edc;
+const CLRIENUM: usize = 0x1fdc;
+const TARGET_BASE: usize = 0x3004;
+
+/// interrupt enable and MSI delivery mode
+const DOMAINCFG_IE: u32 = 1 << 8;
+const DOMAINCFG_DM_MSI: u32 = 1 << 2;
+/// source mode: active high level, what the devices on QEMU virt raise
+const SOURCECFG_LEVEL_HIGH: u32 = 6;
+const TARGET_HART_SHIFT: u32 = 18;
+/// sources are 1..=1023, 0 means none
+pub const MAX_SOURCES: usize = 1023;
+
+/// The S mode domain of an APLIC, which forwards its sources as MSIs to the
+/// IMSIC of a hart.
+///
+/// The M mode domain above it must delegate the sources, which SBI
+/// firmware does. Source `irq` is sent as identity `irq`, so only sources
+/// up to the number of ids work. An MSI reaches one hart, so each source
+/// goes to a hart picked round robin, with hart index equal to hart id.
+pub struct Aplic {
+	base: BaseAddr,
+	num_sources: usize,
+	/// hart ids the sources are spread over
+	harts: Vec<usize>,
+	imsic: Imsic,
+}
+
+impl Aplic {
+	pub fn new(base: BaseAddr, num_sources: usize, harts: Vec<usize>, imsic: Imsic) -> Self {
+		let aplic = Self {
+			base,
+			num_sources: num_sources.min(MAX_SOURCES),
+			harts,
+			imsic,
+		};
+		aplic.write(DOMAINCFG, DOMAINCFG_IE | DOMAINCFG_DM_MSI);
+		aplic
+	}
+
+	fn reg(&self, offset: usize) -> *mut u32 {
+		(self.base + offset) as *mut u32
+	}
+
+	fn write(&self, offset: usize, val: u32) {
+		unsafe { write_volatile(self.reg(offset), val) }
+	}
+
+	fn read(&self, offset: usize) -> u32 {
+		unsafe { read_volatile(self.reg(offset)) }
+	}
+
+	/// the rectified input of `irq` is still asserted
+	fn asserted(&self, irq: usize) -> bool {
+		self.read(IN_CLRIP_BASE + irq / 32 * 4) & (1 << (irq % 32)) != 0
+	}
+}
+
+impl IrqController for Aplic {
+	fn init_hart(&self, _hartid: usize) {
+		self.imsic.init_hart();
+	}
+
+	fn enable(&self, irq: usize) {
+		let hartid = self.harts[irq % self.harts.len()];
+		self.write(SOURCECFG_BASE + (irq - 1) * 4, SOURCECFG_LEVEL_HIGH);
+		self.write(TARGET_BASE + (irq - 1) * 4, ((hartid as u32) << TARGET_HART_SHIFT) | irq as u32);
+		self.write(SETIENUM, irq as u32);
+	}
+
+	fn disable(&self, irq: usize) {
+		self.write(CLRIENUM, irq as u32);
+	}
+
+	fn claim(&self, _hartid: usize) -> Option<usize> {
+		self.imsic.claim()
+	}
+
+	fn complete(&self, irq: usize, _hartid: usize) {
+		// a level source sends one MSI per edge, send it again if the
+		// device still wants service
+		if self.asserted(irq) {
+			self.write(SETIPNUM, irq as u32);
+		}
+	}
+
+	fn max_irq(&self) -> usize {
+		self.num_sources.min(self.imsic.num_ids())
+	}
+}
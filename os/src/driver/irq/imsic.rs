@@ -0,0 +1,35 @@
+use crate::arch::riscv::aia::{claim_ei, ireg_write, EIDELIVERY, EIE0, EITHRESHOLD};
+
+/// The S mode interrupt file of each hart, reached through CSRs.
+///
+/// Every identity is enabled in every file, which ids arrive is up to the
+/// APLIC, so a hart never touches the file of another.
+pub struct Imsic {
+	num_ids: usize,
+}
+
+impl Imsic {
+	pub fn new(num_ids: usize) -> Self {
+		Self { num_ids }
+	}
+
+	pub fn num_ids(&self) -> usize {
+		self.num_ids
+	}
+
+	/// Turn on delivery of this hart's file, must run on the hart itself.
+	pub fn init_hart(&self) {
+		ireg_write(EIDELIVERY, 1);
+		// no threshold, every enabled id comes through
+		ireg_write(EITHRESHOLD, 0);
+		for word in 0..=self.num_ids / 64 {
+			// id 0 does not exist, its bit is read only
+			ireg_write(EIE0 + 2 * word, usize::MAX);
+		}
+	}
+
+	/// The highest pending id of this hart, which is no longer pending.
+	pub fn claim(&self) -> Option<usize> {
+		claim_ei()
+	}
+}
@@ -0,0 +1,200 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use log::warn;
+use spin::RwLock;
+
+use crate::global::PLATFORM;
+
+pub mod aplic;
+pub mod imsic;
+pub mod plic;
+
+#[derive(Clone, Copy, Debug)]
+pub enum IrqControllerType {
+	Plic,
+	/// an APLIC which forwards to the IMSIC of each hart as MSIs
+	AplicImsic,
+}
+
+impl IrqControllerType {
+	pub const PLIC_COMPATIBLE: &[&str] = &["riscv,plic0", "sifive,plic-1.0.0"];
+	/// the local interrupt controller in each cpu node
+	pub const CPU_INTC_COMPATIBLE: &[&str] = &["riscv,cpu-intc"];
+	pub const APLIC_COMPATIBLE: &[&str] = &["riscv,aplic"];
+	pub const IMSIC_COMPATIBLE: &[&str] = &["riscv,imsics"];
+
+	pub fn compatible(device: &str) -> Option<IrqControllerType> {
+		use IrqControllerType::*;
+		if Self::PLIC_COMPATIBLE.contains(&device) {
+			Some(Plic)
+		} else if Self::APLIC_COMPATIBLE.contains(&device) {
+			Some(AplicImsic)
+		} else {
+			None
+		}
+	}
+}
+
+/// external interrupt controller driver should impl this trait
+pub(crate) trait IrqController: Send + Sync {
+	/// Set up the part of the controller which belongs to `hartid`.
+	fn init_hart(&self, hartid: usize);
+	/// Let `irq` reach supervisor mode, on every hart if the controller can
+	/// or else on one of them.
+	fn enable(&self, irq: usize);
+	fn disable(&self, irq: usize);
+	/// The highest pending interrupt of `hartid`, it is not delivered again
+	/// until `complete`.
+	fn claim(&self, hartid: usize) -> Option<usize>;
+	fn complete(&self, irq: usize, hartid: usize);
+	/// sources are numbered from 1 to this
+	fn max_irq(&self) -> usize;
+}
+
+/// Runs in the trap handler with interrupts off, so it must not block.
+pub type IrqHandler = Box<dyn Fn(usize) + Send + Sync>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IrqError {
+	/// the platform has no interrupt controller we know
+	NoController,
+	InvalidIrq,
+	/// another driver has the irq
+	Busy,
+}
+
+pub struct IrqManager {
+	controller: Box<dyn IrqController>,
+	handlers: RwLock<BTreeMap<usize, IrqHandler>>,
+}
+
+impl IrqManager {
+	pub(crate) fn new(controller: Box<dyn IrqController>) -> Self {
+		Self { controller, handlers: RwLock::new(BTreeMap::new()) }
+	}
+
+	pub fn register(&self, irq: usize, handler: IrqHandler) -> Result<(), IrqError> {
+		if irq == 0 || irq > self.controller.max_irq() {
+			return Err(IrqError::InvalidIrq);
+		}
+		let mut handlers = self.handlers.write();
+		if handlers.contains_key(&irq) {
+			return Err(IrqError::Busy);
+		}
+		handlers.insert(irq, handler);
+		self.controller.enable(irq);
+		Ok(())
+	}
+
+	pub fn unregister(&self, irq: usize) {
+		if self.handlers.write().remove(&irq).is_some() {
+			self.controller.disable(irq);
+		}
+	}
+
+	/// Handle every interrupt pending for `hartid`.
+	pub fn dispatch(&self, hartid: usize) {
+		while let Some(irq) = self.controller.claim(hartid) {
+			match self.handlers.read().get(&irq) {
+				Some(handler) => handler(irq),
+				None => warn!("Interrupt {} on hart {} has no handler", irq, hartid),
+			}
+			self.controller.complete(irq, hartid);
+		}
+	}
+}
+
+fn irq_manager() -> Option<&'static IrqManager> {
+	PLATFORM.get()?.board_device.irq.as_ref()
+}
+
+/// Call `handler` with the irq number whenever `irq` is raised, on any hart.
+pub fn register_irq(irq: usize, handler: IrqHandler) -> Result<(), IrqError> {
+	irq_manager().ok_or(IrqError::NoController)?.register(irq, handler)
+}
+
+pub fn unregister_irq(irq: usize) {
+	if let Some(manager) = irq_manager() {
+		manager.unregister(irq);
+	}
+}
+
+/// Per hart setup, before the hart takes external interrupts.
+pub fn init_hart(hartid: usize) {
+	if let Some(manager) = irq_manager() {
+		manager.controller.init_hart(hartid);
+	}
+}
+
+/// Handle the external interrupts pending for `hartid`, from the trap
+/// handlers and from the idle flow, which runs with interrupts off.
+pub fn handle_irq(hartid: usize) {
+	if let Some(manager) = irq_manager() {
+		manager.dispatch(hartid);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloc::sync::Arc;
+	use alloc::vec::Vec;
+	use core::sync::atomic::{AtomicUsize, Ordering};
+	use spin::Mutex;
+
+	/// pending irqs and enable calls instead of registers
+	struct FakeController {
+		pending: Mutex<Vec<usize>>,
+		enabled: AtomicUsize,
+		completed: AtomicUsize,
+	}
+
+	impl IrqController for Arc<FakeController> {
+		fn init_hart(&self, _hartid: usize) {}
+		fn enable(&self, _irq: usize) {
+			self.enabled.fetch_add(1, Ordering::Relaxed);
+		}
+		fn disable(&self, _irq: usize) {
+			self.enabled.fetch_sub(1, Ordering::Relaxed);
+		}
+		fn claim(&self, _hartid: usize) -> Option<usize> {
+			self.pending.lock().pop()
+		}
+		fn complete(&self, _irq: usize, _hartid: usize) {
+			self.completed.fetch_add(1, Ordering::Relaxed);
+		}
+		fn max_irq(&self) -> usize {
+			16
+		}
+	}
+
+	#[test_case]
+	fn irq_dispatch_test() {
+		let fake = Arc::new(FakeController {
+			pending: Mutex::new(Vec::new()),
+			enabled: AtomicUsize::new(0),
+			completed: AtomicUsize::new(0),
+		});
+		let manager = IrqManager::new(Box::new(fake.clone()));
+		static HANDLED: AtomicUsize = AtomicUsize::new(0);
+		let handler = || Box::new(|irq: usize| {
+			HANDLED.fetch_add(irq, Ordering::Relaxed);
+		});
+
+		assert_eq!(manager.register(0, handler()), Err(IrqError::InvalidIrq));
+		assert_eq!(manager.register(17, handler()), Err(IrqError::InvalidIrq));
+		assert_eq!(manager.register(10, handler()), Ok(()));
+		assert_eq!(manager.register(10, handler()), Err(IrqError::Busy));
+		assert_eq!(fake.enabled.load(Ordering::Relaxed), 1);
+
+		// an irq without handler is completed as well
+		fake.pending.lock().extend([10, 3, 10]);
+		manager.dispatch(0);
+		assert_eq!(HANDLED.load(Ordering::Relaxed), 20);
+		assert_eq!(fake.completed.load(Ordering::Relaxed), 3);
+
+		manager.unregister(10);
+		assert_eq!(fake.enabled.load(Ordering::Relaxed), 0);
+		crate::println!("irq_dispatch_test passed!");
+	}
+}
@@ -0,0 +1,97 @@
+use core::ptr::{read_volatile, write_volatile};
+
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::driver::irq::IrqController;
+use crate::platform::BaseAddr;
+
+const PRIORITY_BASE: usize = 0x0;
+const ENABLE_BASE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT_BASE: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const THRESHOLD: usize = 0x0;
+const CLAIM_COMPLETE: usize = 0x4;
+/// sources are 1..=1023, 0 means none
+pub const MAX_SOURCES: usize = 1023;
+
+/// The RISC-V platform-level interrupt controller.
+///
+/// The contexts of the harts come from `interrupts-extended`, e.g. hart h
+/// has 2h for M mode and 2h + 1 for S mode on QEMU virt, while a monitor
+/// hart with only M mode shifts all later ones on the FU540.
+pub struct Plic {
+	base: BaseAddr,
+	ndev: usize,
+	/// `(hartid, context)` of the S mode context of each hart, every hart
+	/// gets every irq, the first to claim it handles it
+	contexts: Vec<(usize, usize)>,
+	/// the enable bits of all sources share words
+	enable_lock: Mutex<()>,
+}
+
+impl Plic {
+	pub fn new(base: BaseAddr, ndev: usize, contexts: Vec<(usize, usize)>) -> Self {
+		Self { base, ndev: ndev.min(MAX_SOURCES), contexts, enable_lock: Mutex::new(()) }
+	}
+
+	fn s_context(&self, hartid: usize) -> Option<usize> {
+		self.contexts.iter().find(|&&(hart, _)| hart == hartid).map(|&(_, context)| context)
+	}
+
+	fn reg(&self, offset: usize) -> *mut u32 {
+		(self.base + offset) as *mut u32
+	}
+
+	/// None if the hart has no S mode context
+	fn context_reg(&self, hartid: usize, offset: usize) -> Option<*mut u32> {
+		let context = self.s_context(hartid)?;
+		Some(self.reg(CONTEXT_BASE + context * CONTEXT_STRIDE + offset))
+	}
+
+	fn set_enable(&self, irq: usize, context: usize, enable: bool) {
+		let word = self.reg(ENABLE_BASE + context * ENABLE_STRIDE + irq / 32 * 4);
+		let bit = 1 << (irq % 32);
+		let _guard = self.enable_lock.lock();
+		unsafe {
+			let val = read_volatile(word);
+			write_volatile(word, if enable { val | bit } else { val & !bit });
+		}
+	}
+}
+
+impl IrqController for Plic {
+	fn init_hart(&self, hartid: usize) {
+		// every source with a priority above 0 gets through
+		if let Some(threshold) = self.context_reg(hartid, THRESHOLD) {
+			unsafe { write_volatile(threshold, 0) };
+		}
+	}
+
+	fn enable(&self, irq: usize) {
+		unsafe { write_volatile(self.reg(PRIORITY_BASE + irq * 4), 1) };
+		self.contexts.iter().for_each(|&(_, context)| self.set_enable(irq, context, true));
+	}
+
+	fn disable(&self, irq: usize) {
+		self.contexts.iter().for_each(|&(_, context)| self.set_enable(irq, context, false));
+	}
+
+	fn claim(&self, hartid: usize) -> Option<usize> {
+		match unsafe { read_volatile(self.context_reg(hartid, CLAIM_COMPLETE)?) } {
+			0 => None,
+			irq => Some(irq as usize),
+		}
+	}
+
+	fn complete(&self, irq: usize, hartid: usize) {
+		if let Some(claim) = self.context_reg(hartid, CLAIM_COMPLETE) {
+			unsafe { write_volatile(claim, irq as u32) };
+		}
+	}
+
+	fn max_irq(&self) -> usize {
+		self.ndev
+	}
+}
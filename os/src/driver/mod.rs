@@ -1 +1,2 @@
 pub mod chardev;
+pub mod irq;
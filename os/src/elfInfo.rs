@@ -1,10 +1,11 @@
 use core::{array};
 use crate::{config::MAX_APP_NUM};
-use crate::_num_app;
+use crate::{_app_names, _num_app};
 use crate::info;
 pub struct ElfsInfo {
 	pub num_app: usize,
-	elf_info: [&'static [u8]; MAX_APP_NUM]
+	elf_info: [&'static [u8]; MAX_APP_NUM],
+	names: [&'static str; MAX_APP_NUM],
 }
 
 impl ElfsInfo {
@@ -25,9 +26,22 @@ impl ElfsInfo {
 				core::slice::from_raw_parts(app_start_addr[i] as *const u8, len)
 			}
 		});
+		// one NUL terminated name after another
+		let mut name_ptr: *const u8 = core::ptr::addr_of!(_app_names);
+		let names: [&str; MAX_APP_NUM] = array::from_fn(|i| {
+			if i >= num_app_usize {
+				return "";
+			}
+			unsafe {
+				let name = core::ffi::CStr::from_ptr(name_ptr.cast());
+				name_ptr = name_ptr.add(name.count_bytes() + 1);
+				name.to_str().unwrap()
+			}
+		});
 		Self {
 			num_app: num_app_usize,
-			elf_info
+			elf_info,
+			names,
 		}
 	}
 
@@ -35,6 +49,11 @@ impl ElfsInfo {
 		self.elf_info.get(idx).unwrap()
 	}
 
+	/// file name of the app in `user/elf`, e.g. `00hello_world`
+	pub fn app_name(&self, app_id: usize) -> &'static str {
+		self.names[app_id]
+	}
+
 	pub fn print_app_info(&self) {
 		info!("Kernel app number: {}", self.num_app);
 	}
@@ -2,6 +2,8 @@ use core::panic::PanicInfo;
 
 use crate::arch::common::ArchPower;
 use crate::arch::common::ArchMem;
+use crate::console;
+use crate::crash::{self, Panicking};
 use crate::devicetree::ParseDeviceTreeError;
 use crate::syscall::syscallid::SyscallError;
 use crate::global::ARCH;
@@ -9,6 +11,14 @@ use log::error;
 
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
+	// never wait for a console lock or allocate from now on
+	console::enter_emergency();
+	let panicking = crash::enter_panic();
+	if let Panicking::Again = panicking {
+		// the logger, the unwinder or the dump may be what panics, so print and stop
+		crate::println!("\x1b[1;31m[kernel] panic while panicking: {}\x1b[0m", _info);
+		ARCH.shutdown(true);
+	}
 	if let Some(location) = _info.location() {
 		error!("Panic at {}, line: {}, column: {}, due to {}.",
 		       location.file(),
@@ -20,6 +30,10 @@ fn panic(_info: &PanicInfo) -> ! {
 	}
 	#[cfg(has_frame_pointers)]
 	ARCH.unwind();
+	if let Panicking::Other = panicking {
+		crash::stop_panicked();
+	}
+	crash::dump();
 	ARCH.shutdown(true);
 }
 
@@ -34,3 +48,61 @@ impl From<ParseDeviceTreeError> for KernelError {
 		Self::DeviceTree(value)
 	}
 }
+
+impl From<SyscallError> for KernelError {
+	fn from(value: SyscallError) -> Self {
+		Self::Syscall(value)
+	}
+}
+
+/// Why a syscall failed, returned to user mode as `-errno`.
+///
+/// The numbers are the ones of linux, so user code can share them.
+#[repr(isize)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Errno {
+	/// the caller may not do this
+	EPERM = 1,
+	/// no such process or thread
+	ESRCH = 3,
+	/// a signal came during a wait
+	EINTR = 4,
+	/// the device or the firmware failed
+	EIO = 5,
+	/// bad file descriptor
+	EBADF = 9,
+	/// try again, e.g. a futex value changed or no thread slot is free
+	EAGAIN = 11,
+	/// out of memory
+	ENOMEM = 12,
+	/// bad user address
+	EFAULT = 14,
+	/// the resource is in use or changing, e.g. the last online hart
+	EBUSY = 16,
+	/// no such device, e.g. a hart the SBI does not know
+	ENODEV = 19,
+	/// invalid argument
+	EINVAL = 22,
+	/// waiting for itself
+	EDEADLK = 35,
+	/// no such syscall
+	ENOSYS = 38,
+}
+
+/// Result of a `sys_*` function, the value is returned to user mode as is.
+pub type SysResult<T = usize> = Result<T, Errno>;
+
+impl Errno {
+	/// what user mode sees in a0
+	pub fn to_ret(self) -> isize {
+		-(self as isize)
+	}
+}
+
+impl From<SyscallError> for Errno {
+	fn from(value: SyscallError) -> Self {
+		match value {
+			SyscallError::InvalidSyscallID => Self::ENOSYS,
+		}
+	}
+}
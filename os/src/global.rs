@@ -1,7 +1,9 @@
 use crate::mm::addr_space::AddrSpace;
 use crate::mm::frame_allocator::{FrameAllocator, StackFrameAllocator};
 use crate::task::TaskManager;
-use crate::config::{MAX_APP_NUM, NUM_HART_MAX};
+use crate::task::processor::Processor;
+use crate::config::MAX_APP_NUM;
+use crate::cpu::PerCpu;
 use crate::elfInfo::ElfsInfo;
 use crate::mm::stack::{KernelStack, UserStack};
 use crate::platform::Platform;
@@ -15,6 +17,8 @@ unsafe extern "C" {
 	pub static etext: usize;
 	pub static srodata: usize;
 	pub static erodata: usize;
+	pub static sksyms: usize;
+	pub static eksyms: usize;
 	pub static sdata: usize;
 	pub static edata: usize;
 	pub static skstack: usize;
@@ -31,6 +35,7 @@ unsafe extern "C" {
 
 	// in app link asm
 	pub static _num_app: usize;
+	pub static _app_names: u8;
 }
 
 pub static PLATFORM: Once<Platform> = Once::new();
@@ -39,13 +44,16 @@ pub static TASK_MANAGER: Once<TaskManager> = Once::new();
 
 pub static ELFS_INFO: Once<ElfsInfo> = Once::new();
 
+pub static PROCESSORS: PerCpu<Processor> = PerCpu::new(Processor::new);
+
 pub static FRAME_ALLOCATOR: Once<FrameAllocator> = Once::new();
 
 //TODO: support muti-harts
 pub static KERNEL_ADDRSPACE: Once<AddrSpace> = Once::new();
 
+/// stack of the boot hart, the others get theirs from frames, see `mm::stack::hart_stack`
 #[unsafe(link_section = ".bss.kstack")]
-pub static mut KERNEL_STACK: [KernelStack; NUM_HART_MAX] = [KernelStack::ZERO; NUM_HART_MAX];
+pub static mut BOOT_STACK: KernelStack = KernelStack::ZERO;
 
 #[unsafe(link_section = ".bss.ustack")]
 pub static mut USER_STACK: [UserStack; MAX_APP_NUM] = [UserStack::ZERO; MAX_APP_NUM];
@@ -3,12 +3,13 @@ pub const HART_INFO_SIZE: usize = size_of::<HartContext>();
 
 use core::ptr::NonNull;
 use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 // Make sure HartContext is aligned.
 //
 // HartContext will always at the end of Stack, so we should make sure
 // STACK_SIZE_PER_HART is a multiple of b.
-use crate::{arch::common::{ArchHarts, FlowContext}, config::KERNEL_STACK_SIZE, global::ARCH, task::{block::TaskControlBlock, harts::AppHartInfo}, trap::{LoadedTrapStack, TrapHandler}};
+use crate::{arch::common::{ArchHarts, FlowContext}, config::KERNEL_STACK_SIZE, cpu::{self, PerCpu}, global::ARCH, task::{block::TaskControlBlock, harts::AppHartInfo}, trap::{LoadedTrapStack, TrapHandler}};
 const _: () = assert!(KERNEL_STACK_SIZE % core::mem::align_of::<HartContext>() == 0);
 
 #[repr(C, align(128))]
@@ -30,8 +31,9 @@ impl HartContext {
 		self.ksp = ksp;
 	}
 
-	pub fn get_hartnum() -> usize {
-		crate::PLATFORM.get().unwrap().board_info.cpu_num.unwrap()
+	/// the trampoline switches to `ksp` when the hart traps from user
+	pub fn set_ksp(&mut self, ksp: usize) {
+		self.ksp = ksp;
 	}
 
 	pub fn hartid(&self) -> usize {
@@ -47,6 +49,17 @@ pub fn trap_handler_in_trap_stage() -> &'static mut TrapHandler {
 	}
 }
 
+/// Point `tp` at `traph`, which every `*_in_trap_stage` helper reads.
+///
+/// # Safety
+///
+/// `traph` must stay valid, and its `hart_id` must be this hart.
+pub unsafe fn set_trap_handler(traph: *mut TrapHandler) {
+	unsafe {
+		asm!("mv tp, {}", in(reg) traph);
+	}
+}
+
 pub fn hart_context_in_boot_stage() -> &'static mut HartContext {
 	let scratch = ARCH.get_scratch() as *mut TrapHandler;
 	let mut hart_context = unsafe { (*scratch).hart };
@@ -65,6 +78,36 @@ pub fn hart_id_in_trap_stage() -> usize {
 	}
 }
 
+/// Hart id for code which may run before `run_tasks`, where `tp` is still null.
+pub fn hart_id_if_running() -> Option<usize> {
+	let mut scratch: *mut TrapHandler;
+	unsafe {
+		asm!("mv {}, tp", out(reg) scratch);
+		scratch.as_ref().map(|traph| traph.hart_id)
+	}
+}
+
+/// harts which run tasks, only they answer IPIs and run flows
+static ONLINE: PerCpu<AtomicBool> = PerCpu::new(|| AtomicBool::new(false));
+
+/// `hartid` runs tasks from now on, see `run_tasks`.
+pub fn set_hart_online(hartid: usize) {
+	ONLINE[hartid].store(true, Ordering::Release);
+}
+
+/// `hartid` is about to stop, see `ipi::close_mailbox`.
+pub fn set_hart_offline(hartid: usize) {
+	ONLINE[hartid].store(false, Ordering::Release);
+}
+
+pub fn is_hart_online(hartid: usize) -> bool {
+	ONLINE[hartid].load(Ordering::Acquire)
+}
+
+pub fn online_harts() -> impl Iterator<Item = usize> {
+	cpu::hart_ids().filter(|&hartid| ONLINE[hartid].load(Ordering::Acquire))
+}
+
 pub fn task_block_in_boot_stage() -> &'static mut TaskControlBlock {
 	let scratch = ARCH.get_scratch() as *mut TrapHandler;
 	let task_block = unsafe { (*scratch).context.as_ptr() as *mut TaskControlBlock };
@@ -84,12 +127,18 @@ pub fn task_context_in_trap_stage() -> &'static mut TaskControlBlock {
 	}
 }
 
+/// State of a hart in the SBI hart state management, the numbers are the SBI ones.
+#[repr(usize)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum HartState {
-	Started,
-	Stoped,
-	StartPeding,
-	StopPeding,
-	Invalid,
+	Started = 0,
+	Stoped = 1,
+	StartPeding = 2,
+	StopPeding = 3,
+	/// by the SBI suspend call, which includes its pending states
+	Suspended = 4,
+	/// no such hart, or the SBI has no HSM
+	Invalid = usize::MAX,
 }
 
 impl core::fmt::Display for HartState {
@@ -99,6 +148,7 @@ impl core::fmt::Display for HartState {
 			HartState::Stoped => write!(f, "Hart is Stoped"),
 			HartState::StartPeding => write!(f, "Hart is Start pending"),
 			HartState::StopPeding => write!(f, "Hart is Stop pending"),
+			HartState::Suspended => write!(f, "Hart is Suspended"),
 			HartState::Invalid => write!(f, "Hart is Invalid"),
 		}
 	}
@@ -0,0 +1,212 @@
+//! Messages between harts.
+//!
+//! A message is queued in the mailbox of its target, then a software
+//! interrupt tells the target to look. A hart in user mode takes it at once,
+//! the idle flow and `preempt_point` poll the mailbox, see `handle_messages`.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+use crate::arch::common::{ArchHarts, ArchMem};
+use crate::cpu::PerCpu;
+use crate::crash;
+use crate::global::{ARCH, PROCESSORS};
+use crate::harts::{hart_id_if_running, is_hart_online, online_harts, set_hart_offline};
+
+/// Runs on the target in its trap handler or idle flow, so it must not block.
+pub type RemoteCall = Box<dyn FnOnce() + Send>;
+
+pub enum IpiMessage {
+	/// give the hart to the next ready flow at the next chance
+	Resched,
+	/// flush the TLB entries of `vaddr` in every address space, or all entries
+	FlushTlb { vaddr: Option<usize>, done: Completion },
+	Call { func: RemoteCall, done: Completion },
+}
+
+static MAILBOXES: PerCpu<Mutex<VecDeque<IpiMessage>>> = PerCpu::new(|| Mutex::new(VecDeque::new()));
+
+/// Counts down the harts which still have to handle a message.
+#[derive(Clone)]
+pub struct Completion(Arc<AtomicUsize>);
+
+impl Completion {
+	fn new(harts: usize) -> Self {
+		Self(Arc::new(AtomicUsize::new(harts)))
+	}
+
+	fn finish(&self) {
+		self.0.fetch_sub(1, Ordering::AcqRel);
+	}
+
+	pub fn is_done(&self) -> bool {
+		self.0.load(Ordering::Acquire) == 0
+	}
+
+	/// Spin until every target is done.
+	///
+	/// The mailbox of this hart is handled meanwhile, so two harts waiting
+	/// for each other go on. The caller must not hold a spin lock, a target
+	/// in the kernel may spin on it without looking at its mailbox.
+	pub fn wait(&self) {
+		while !self.is_done() {
+			crash::poll_stop();
+			if let Some(hartid) = hart_id_if_running() {
+				handle_messages(hartid);
+			}
+			spin_loop();
+		}
+	}
+}
+
+/// Queue `msg` for `hartid` and interrupt it.
+///
+/// Return false and drop `msg` if the hart does not run tasks, it would
+/// never handle it. The check holds the mailbox, see `close_mailbox`.
+#[must_use]
+pub fn send(hartid: usize, msg: IpiMessage) -> bool {
+	{
+		let mut mailbox = MAILBOXES[hartid].lock();
+		if !is_hart_online(hartid) {
+			return false;
+		}
+		mailbox.push_back(msg);
+	}
+	ARCH.send_ipi(hartid);
+	true
+}
+
+/// Take `hartid`, the current hart, out of the online set before it stops
+/// and handle what was queued until then, nothing is queued afterwards.
+pub fn close_mailbox(hartid: usize) {
+	{
+		let _mailbox = MAILBOXES[hartid].lock();
+		set_hart_offline(hartid);
+	}
+	handle_messages(hartid);
+}
+
+/// Handle every message queued for `hartid`, which is the current hart.
+pub fn handle_messages(hartid: usize) {
+	// a message queued from now on raises it again
+	ARCH.clear_ipi();
+	loop {
+		// not locked while the message runs, it may send messages itself
+		let Some(msg) = MAILBOXES[hartid].lock().pop_front() else {
+			break;
+		};
+		match msg {
+			IpiMessage::Resched => PROCESSORS[hartid].set_need_resched(),
+			IpiMessage::FlushTlb { vaddr, done } => {
+				ARCH.flush_tlb(vaddr);
+				done.finish();
+			}
+			IpiMessage::Call { func, done } => {
+				func();
+				done.finish();
+			}
+		}
+	}
+}
+
+/// Ask `hartid` to give its hart away, the current flow goes on elsewhere later.
+pub fn resched(hartid: usize) {
+	if Some(hartid) == hart_id_if_running() {
+		PROCESSORS[hartid].set_need_resched();
+	} else {
+		// a hart which is not online has nothing to give away
+		let _ = send(hartid, IpiMessage::Resched);
+	}
+}
+
+/// Flush the TLB entries of `vaddr`, or all entries, on `harts` and wait
+/// until they are done.
+///
+/// Harts which do not run tasks are left out, they would never answer and
+/// flush everything before they enter user mode anyway. See
+/// `Completion::wait` for the locks the caller may hold.
+pub fn flush_tlb(harts: &[usize], vaddr: Option<usize>) {
+	let me = hart_id_if_running();
+	if harts.iter().any(|&hartid| Some(hartid) == me) {
+		ARCH.flush_tlb(vaddr);
+	}
+	let others: Vec<usize> = online_harts()
+		.filter(|&hartid| Some(hartid) != me && harts.contains(&hartid))
+		.collect();
+	let done = Completion::new(others.len());
+	for hartid in others {
+		// went offline meanwhile, it flushes everything when it comes back
+		if !send(hartid, IpiMessage::FlushTlb { vaddr, done: done.clone() }) {
+			done.finish();
+		}
+	}
+	done.wait();
+}
+
+/// Run `func` on `hartid` and wait until it returned.
+///
+/// Return false if the hart does not run tasks yet, it would never answer.
+pub fn call_on<F: FnOnce() + Send + 'static>(hartid: usize, func: F) -> bool {
+	if Some(hartid) == hart_id_if_running() {
+		func();
+		return true;
+	}
+	let done = Completion::new(1);
+	if !send(hartid, IpiMessage::Call { func: Box::new(func), done: done.clone() }) {
+		return false;
+	}
+	done.wait();
+	true
+}
+
+/// Wake an idle hart other than this one for new work, return false if none sleeps.
+pub fn wake_idle_hart() -> bool {
+	let me = hart_id_if_running();
+	let idle = online_harts()
+		.filter(|&hartid| Some(hartid) != me)
+		.find(|&hartid| PROCESSORS[hartid].take_idle());
+	if let Some(hartid) = idle {
+		ARCH.send_ipi(hartid);
+	}
+	idle.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::sync::atomic::AtomicBool;
+
+	#[test_case]
+	fn ipi_mailbox_test() {
+		// before run_tasks the boot hart takes the messages by hand
+		let hartid = crate::cpu::hart_ids().next().unwrap();
+		static CALLED: AtomicBool = AtomicBool::new(false);
+		// nothing is queued for a hart which does not run tasks
+		assert!(!send(hartid, IpiMessage::Resched));
+		assert!(MAILBOXES[hartid].lock().is_empty());
+
+		crate::harts::set_hart_online(hartid);
+		let done = Completion::new(2);
+		assert!(send(hartid, IpiMessage::Resched));
+		assert!(send(hartid, IpiMessage::FlushTlb { vaddr: None, done: done.clone() }));
+		assert!(send(hartid, IpiMessage::Call {
+			func: Box::new(|| CALLED.store(true, Ordering::Relaxed)),
+			done: done.clone(),
+		}));
+		assert!(!done.is_done());
+		handle_messages(hartid);
+		assert!(done.is_done());
+		assert!(CALLED.load(Ordering::Relaxed));
+		assert!(PROCESSORS[hartid].take_need_resched());
+		assert!(MAILBOXES[hartid].lock().is_empty());
+		close_mailbox(hartid);
+		assert!(!is_hart_online(hartid));
+
+		crate::println!("ipi_mailbox_test passed!");
+	}
+}
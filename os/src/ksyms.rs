@@ -0,0 +1,92 @@
+//! Kernel symbol table, to print addresses as `function+offset`.
+//!
+//! The build leaves the `.ksyms` section zeroed, xtask fills it after
+//! linking with the text symbols of the kernel ELF:
+//!
+//! | offset | content
+//! | ------ | -
+//! | 0      | magic `KSYM`, u32
+//! | 4      | number of symbols, u32
+//! | 8      | link address of `skernel`, u64
+//! | 16     | symbols sorted by address, `{ addr: u64, name_off: u32, name_len: u32 }`
+//! | ...    | names, utf-8, not terminated
+
+use core::fmt;
+use core::str::from_utf8;
+
+use crate::config::KSYMS_SIZE;
+use crate::global::{eksyms, skernel, sksyms};
+
+const KSYMS_MAGIC: u32 = u32::from_le_bytes(*b"KSYM");
+const HEADER_SIZE: usize = 16;
+const ENTRY_SIZE: usize = 16;
+
+/// the room the linker script places in `.ksyms`
+#[used]
+#[unsafe(link_section = ".ksyms")]
+static KSYMS_SPACE: [u8; KSYMS_SIZE] = [0; KSYMS_SIZE];
+
+fn table() -> &'static [u8] {
+	// read through the linker symbols, the zeros of KSYMS_SPACE must not be folded
+	unsafe {
+		let start = &raw const sksyms as usize;
+		let end = &raw const eksyms as usize;
+		core::slice::from_raw_parts(start as *const u8, end - start)
+	}
+}
+
+fn read_u32(table: &[u8], off: usize) -> u32 {
+	u32::from_le_bytes(table[off..off + 4].try_into().unwrap())
+}
+
+fn read_u64(table: &[u8], off: usize) -> u64 {
+	u64::from_le_bytes(table[off..off + 8].try_into().unwrap())
+}
+
+/// Function containing `addr` and the offset in it, None if the table is empty.
+pub fn lookup(addr: usize) -> Option<(&'static str, usize)> {
+	let table = table();
+	if table.len() < HEADER_SIZE || read_u32(table, 0) != KSYMS_MAGIC {
+		return None;
+	}
+	let num = read_u32(table, 4) as usize;
+	if HEADER_SIZE + num * ENTRY_SIZE > table.len() {
+		return None;
+	}
+	// the table holds link addresses
+	let addr = addr
+		.wrapping_sub(&raw const skernel as usize)
+		.wrapping_add(read_u64(table, 8) as usize);
+	let entry_addr = |i: usize| read_u64(table, HEADER_SIZE + i * ENTRY_SIZE) as usize;
+	// the last symbol starting at or below addr
+	let (mut lo, mut hi) = (0, num);
+	while lo < hi {
+		let mid = (lo + hi) / 2;
+		if entry_addr(mid) <= addr {
+			lo = mid + 1;
+		} else {
+			hi = mid;
+		}
+	}
+	if lo == 0 {
+		return None;
+	}
+	let idx = lo - 1;
+	let entry = HEADER_SIZE + idx * ENTRY_SIZE;
+	let name_off = read_u32(table, entry + 8) as usize;
+	let name_len = read_u32(table, entry + 12) as usize;
+	let name = from_utf8(table.get(name_off..name_off + name_len)?).ok()?;
+	Some((name, addr - entry_addr(idx)))
+}
+
+/// Print an address as `function+offset`, or as hex if it is not found.
+pub struct Symbolized(pub usize);
+
+impl fmt::Display for Symbolized {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match lookup(self.0) {
+			Some((name, off)) => write!(f, "{}+{:#x}", name, off),
+			None => write!(f, "{:#x}", self.0),
+		}
+	}
+}
@@ -1,4 +1,5 @@
-use crate::harts::{hart_id_in_trap_stage, task_context_in_trap_stage};
+use crate::harts::hart_id_in_trap_stage;
+use crate::task::processor::current_processor;
 use crate::println;
 use alloc::boxed::Box;
 use log::Level;
@@ -32,6 +33,11 @@ impl Log for PianoLogger {
 		self.inner.read().flush();
 	}
 	fn log(&self, record: &log::Record) {
+		// a panic may come from the trap logger or with the lock held
+		if crate::console::in_emergency() {
+			BootLogger.log(record);
+			return;
+		}
 		self.inner.read().log(record);
 	}
 }
@@ -85,7 +91,9 @@ impl Log for TrapLogger {
 		let ansi_reset = "\x1b[0m";
 		let bold = "\x1b[1;37m";
 		let hart_id = hart_id_in_trap_stage();
-		let app_id = task_context_in_trap_stage().app_info().app_id;
+		// -1: no task on this hart, e.g. the idle flow or a kernel thread
+		let app_id = current_processor().current_task()
+			.map_or(-1, |app_id| app_id as isize);
 		println!("{bold}[kernel]{reset}{color_log} {:<5}[{:>2}][{:>2}]{reset} - {}",
 			 record.level(),
 			 hart_id,
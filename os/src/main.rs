@@ -17,7 +17,6 @@ use alloc::boxed::Box;
 use log::info;
 
 use crate::arch::common::ArchPower;
-use crate::arch::common::ArchHarts;
 use crate::global::*;
 use crate::elfInfo::ElfsInfo;
 use crate::logging::PIANOLOGGER;
@@ -25,13 +24,15 @@ use crate::logging::PianoLogger;
 use crate::mm::frame_allocator::FrameAllocator;
 use crate::mm::frame_allocator::StackFrameAllocator;
 use crate::{
-	harts::HartContext, task::TaskManager, mm::heap::heap_init, platform::Platform,
+	task::TaskManager, mm::heap::heap_init, platform::Platform,
 };
 
 mod arch;
 mod task;
 mod config;
 mod console;
+mod cpu;
+mod crash;
 mod devicetree;
 mod driver;
 mod error;
@@ -42,19 +43,26 @@ mod mm;
 mod platform;
 mod trap;
 mod harts;
+mod ipi;
+mod sync;
 mod syscall;
 mod elfInfo;
+mod ksyms;
 mod test;
 
 extern crate alloc;
 
 #[unsafe(no_mangle)]
 extern "C" fn rust_main(hartid: usize, device_tree: usize) -> ! {
+	// no trap handler until run_tasks, see hart_id_if_running
+	unsafe { harts::set_trap_handler(core::ptr::null_mut()) };
 	clear_bss();
 	heap_init();
 
 	// parse dtb and init platform
 	PLATFORM.call_once(|| Platform::init_platform(device_tree).unwrap());
+	// panics if the boot hart is not one the kernel runs on
+	mm::stack::set_boot_stack(hartid);
 
 	// init log system
 	PIANOLOGGER.call_once(|| { PianoLogger::set_boot_logger() });
@@ -66,6 +74,8 @@ extern "C" fn rust_main(hartid: usize, device_tree: usize) -> ! {
 
 	// init mm
 	mm::init();
+	// the uart is mapped now, from here it is interrupt driven
+	console::init_irq();
 
 	// get elf info and init loader
 	ELFS_INFO.call_once(|| ElfsInfo::new());
@@ -74,12 +84,6 @@ extern "C" fn rust_main(hartid: usize, device_tree: usize) -> ! {
 	TASK_MANAGER.call_once(||
 		TaskManager::new()
 	);
-	// map flow context to user space
-	TASK_MANAGER.get().unwrap()
-		.map_flow_context();
-	// map trap handler to user space, link hart and app
-	let next_app =TASK_MANAGER.get().unwrap()
-		.prepare_next_at_boot(hartid);
 
 	//test
 	#[cfg(test)]
@@ -88,17 +92,15 @@ extern "C" fn rust_main(hartid: usize, device_tree: usize) -> ! {
 	if TASK_MANAGER.get().unwrap().num_app != 0 {
 		//  switch logger
 		PIANOLOGGER.get().unwrap().set_trap_logger();
-		for i in 0..HartContext::get_hartnum() {
-			let start_addr = arch::common::entry::hart_start as *const () as usize;
-			ARCH.hart_start(i, start_addr, 0);
-		}
+		task::hotplug::start_harts();
 
 		TASK_MANAGER
 			.get()
 			.unwrap()
-			.run_next_at_boot(next_app)
+			.run_tasks(hartid)
 	} else {
 		info!("No app should be run, kernel shutdown");
+		console::flush();
 		ARCH.shutdown(false);
 	}
 
@@ -107,16 +109,11 @@ extern "C" fn rust_main(hartid: usize, device_tree: usize) -> ! {
 
 #[unsafe(no_mangle)]
 extern "C" fn hart_main(hartid: usize, _opaque: usize) -> ! {
-	let next_app =
-		//init HartContext, TrapContext, TaskContext
-		TASK_MANAGER.get().unwrap().prepare_next_at_boot(hartid);
-
+	unsafe { harts::set_trap_handler(core::ptr::null_mut()) };
 	TASK_MANAGER
 		.get()
 		.unwrap()
-		.run_next_at_boot(next_app);
-
-	unreachable!();
+		.run_tasks(hartid)
 }
 
 fn clear_bss() {
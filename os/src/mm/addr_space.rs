@@ -3,7 +3,7 @@ use core::fmt::Formatter;
 use core::ops::Range;
 use core::iter::Step;
 
-use crate::config::{APP_VIRT_ADDR, FLOW_CONTEXT_VADDR, HART_CONTEXT_VADDR, MEMORY_END, PAGE_SIZE, TRAMPOLINE_VADDR, TRAP_HANDLER_VADDR, USER_STACK_SIZE};
+use crate::config::{thread_slot_vaddr, APP_VIRT_ADDR, FLOW_CONTEXT_VADDR, HART_CONTEXT_VADDR, MEMORY_END, PAGE_SIZE, TRAMPOLINE_VADDR, TRAP_HANDLER_VADDR, USER_STACK_SIZE};
 use crate::global::FRAME_ALLOCATOR;
 use crate::mm::address::{PhysAddr, PhysPageNum, VPNRange, VirtAddr};
 use crate::mm::page_table::{PTEFlags, PageTableTree};
@@ -21,6 +21,7 @@ use elf::{
 };
 
 bitflags! {
+	#[derive(Clone, Copy)]
 	pub struct MapPermission: u8 {
 		const R = 1 << 1;
 		const W = 1 << 2;
@@ -77,19 +78,21 @@ impl AddrSpace {
 		self.push(vma, None);
 	}
 
-	pub fn insert_uflow_context(&mut self, flow: PhysAddr) -> VirtAddr {
+	pub fn insert_uflow_context(&mut self, flow: PhysAddr, tid: usize) -> VirtAddr {
 		//TODO: check flow is aligned
+		let uflow_va = thread_slot_vaddr(FLOW_CONTEXT_VADDR, tid);
 		self.page_table.map(
-			VirtPageNum::from_addr_floor(FLOW_CONTEXT_VADDR),
+			VirtPageNum::from_addr_floor(uflow_va),
 			flow.ppn_floor(),
 			PTEFlags::R | PTEFlags::W,
 			None
 		);
-		FLOW_CONTEXT_VADDR.into()
+		uflow_va.into()
 	}
 
-	pub fn insert_utrap_handler(&mut self, traph: PhysAddr, unmap: bool) -> VirtAddr {
-		let utraph_vpn = VirtPageNum::from_addr_floor(TRAP_HANDLER_VADDR);
+	pub fn insert_utrap_handler(&mut self, traph: PhysAddr, tid: usize, unmap: bool) -> VirtAddr {
+		let utraph_va = thread_slot_vaddr(TRAP_HANDLER_VADDR, tid);
+		let utraph_vpn = VirtPageNum::from_addr_floor(utraph_va);
 		if unmap {
 			self.page_table.unmap(utraph_vpn);
 		}
@@ -99,12 +102,13 @@ impl AddrSpace {
 			PTEFlags::R | PTEFlags::W,
 			None
 		);
-		TRAP_HANDLER_VADDR.into()
+		utraph_va.into()
 	}
 
-	pub fn insert_uhart_context(&mut self, hc: PhysAddr, unmap: bool) -> VirtAddr {
+	pub fn insert_uhart_context(&mut self, hc: PhysAddr, tid: usize, unmap: bool) -> VirtAddr {
 		//TODO: check hc is aligned
-		let uhc_vpn = VirtPageNum::from_addr_floor(HART_CONTEXT_VADDR);
+		let uhc_va = thread_slot_vaddr(HART_CONTEXT_VADDR, tid);
+		let uhc_vpn = VirtPageNum::from_addr_floor(uhc_va);
 		if unmap {
 			self.page_table.unmap(uhc_vpn);
 		}
@@ -114,7 +118,44 @@ impl AddrSpace {
 			PTEFlags::R | PTEFlags::W,
 			None
 		);
-		TRAP_HANDLER_VADDR.into()
+		uhc_va.into()
+	}
+
+	/// The user stack of thread `tid`.
+	///
+	/// `ustack_base` is the bottom of the main thread stack, the stacks of
+	/// other threads follow it upward with a guard page between each two.
+	pub fn thread_stack(ustack_base: usize, tid: usize) -> Range<usize> {
+		let bottom = ustack_base + tid * (USER_STACK_SIZE + PAGE_SIZE);
+		bottom..bottom + USER_STACK_SIZE
+	}
+
+	/// Map the user stack of thread `tid` and return its top.
+	pub fn insert_thread_stack(&mut self, ustack_base: usize, tid: usize) -> usize {
+		let stack = Self::thread_stack(ustack_base, tid);
+		self.insert_framed_area(
+			stack.start.into(),
+			stack.end.into(),
+			MapPermission::R | MapPermission::W | MapPermission::U
+		);
+		stack.end
+	}
+
+	/// Unmap the user stack and the slots of thread `tid`, so the tid can
+	/// be used again. The frames of the stack are freed.
+	pub fn remove_thread(&mut self, ustack_base: usize, tid: usize) {
+		let start = VirtAddr::from(Self::thread_stack(ustack_base, tid).start).vpn_floor();
+		if let Some(idx) = self.vma.iter().position(|vma| vma.vpn_range.start == start) {
+			let mut vma = self.vma.remove(idx);
+			vma.unmap_all(&mut self.page_table);
+		}
+		for slot in [FLOW_CONTEXT_VADDR, TRAP_HANDLER_VADDR, HART_CONTEXT_VADDR] {
+			let vpn = VirtPageNum::from_addr_floor(thread_slot_vaddr(slot, tid));
+			// the hart context is only mapped once the thread ran
+			if self.page_table.translate(vpn).is_some_and(|pte| pte.is_valid()) {
+				self.page_table.unmap(vpn);
+			}
+		}
 	}
 
 	/// Create the kernel address space
@@ -277,11 +318,26 @@ impl AddrSpace {
 		self.page_table.token()
 	}
 
+	/// physical address of `va`, only in framed areas
+	pub fn translate_vaddr(&self, va: VirtAddr) -> Option<PhysAddr> {
+		self.page_table.translate_vaddr(va)
+	}
+
 	pub fn translated_byte_buffer(
 		&self,
 		ptr: *const u8,
 		len: usize
 	) -> Option<Vec<&'static [u8]>> {
+		self.translated_byte_buffer_mut(ptr, len)
+			.map(|bufs| bufs.into_iter().map(|buf| &*buf).collect())
+	}
+
+	/// pieces of the user buffer `ptr..ptr+len` in each page, None if some page is not mapped
+	pub fn translated_byte_buffer_mut(
+		&self,
+		ptr: *const u8,
+		len: usize
+	) -> Option<Vec<&'static mut [u8]>> {
 		let start_va: VirtAddr = (ptr as usize).into();
 		let start_vpn: VirtPageNum = start_va.vpn_floor();
 		let end_va: VirtAddr = (ptr as usize + len).into();
@@ -294,7 +350,8 @@ impl AddrSpace {
 				0
 			};
 			let step_next = VirtPageNum::forward_checked(vpn, 1).unwrap();
-			let end_offset = if step_next == end_vpn {
+			// an end aligned to a page takes the whole last page
+			let end_offset = if step_next == end_vpn && end_va.page_offset() != 0 {
 				end_va.page_offset()
 			} else {
 				PAGE_SIZE
@@ -303,11 +360,68 @@ impl AddrSpace {
 			let ppn = self.page_table
 				.translate_vpn(vpn)?;
 			Some(unsafe {
-				&ppn.get_byte_array()[start_offset..end_offset]
+				&mut ppn.get_byte_array()[start_offset..end_offset]
 			})
 		}).collect()
 	}
 
+	/// the area containing `va`
+	pub(crate) fn find_vma(&self, va: usize) -> Option<&VMArea> {
+		let vpn = VirtAddr::from(va).vpn_floor();
+		self.vma.iter().find(|vma| vma.vpn_range.contains(&vpn))
+	}
+
+	/// `(start, end, perm)` of each area user mode can access
+	#[cfg(feature = "coredump")]
+	pub fn user_areas(&self) -> impl Iterator<Item = (usize, usize, MapPermission)> + '_ {
+		self.vma.iter()
+			.filter(|vma| vma.map_perm.contains(MapPermission::U))
+			.map(|vma| (
+				VirtAddr::from(vma.vpn_range.start).0,
+				VirtAddr::from(vma.vpn_range.end).0,
+				vma.map_perm,
+			))
+	}
+
+	/// `va..va+len` lies in user areas which allow `perm`
+	pub fn check_user_buf(&self, va: usize, len: usize, perm: MapPermission) -> bool {
+		let Some(end) = va.checked_add(len) else {
+			return false;
+		};
+		let mut vpn = VirtAddr::from(va).vpn_floor();
+		let end_vpn = VirtAddr::from(end).vpn_ceil();
+		while vpn < end_vpn {
+			let Some(vma) = self.vma.iter().find(|vma| vma.vpn_range.contains(&vpn)) else {
+				return false;
+			};
+			if !vma.map_perm.contains(perm | MapPermission::U) {
+				return false;
+			}
+			vpn = vma.vpn_range.end;
+		}
+		true
+	}
+
+	/// Copy `src` to the user address `va`, None if some page is not mapped.
+	pub fn copy_to_user(&self, va: usize, src: &[u8]) -> Option<()> {
+		let mut copied = 0;
+		for buf in self.translated_byte_buffer_mut(va as *const u8, src.len())? {
+			buf.copy_from_slice(&src[copied..copied + buf.len()]);
+			copied += buf.len();
+		}
+		Some(())
+	}
+
+	/// Copy from the user address `va` to `dst`, None if some page is not mapped.
+	pub fn copy_from_user(&self, va: usize, dst: &mut [u8]) -> Option<()> {
+		let mut copied = 0;
+		for buf in self.translated_byte_buffer(va as *const u8, dst.len())? {
+			dst[copied..copied + buf.len()].copy_from_slice(buf);
+			copied += buf.len();
+		}
+		Some(())
+	}
+
 	pub fn print_addr_space(&self) {
 		info!("Address                      Permision  Map type");
 		self.vma.iter().for_each(|vma| info!("{}", vma));
@@ -12,6 +12,10 @@ use crate::{config, global::{self, FRAME_ALLOCATOR}, mm::address::PhysPageNum};
 pub trait FrameAllocatorInterface: Send {
 	fn alloc(&mut self) -> Option<PhysPageNum>;
 	fn dealloc(&mut self, ppn: PhysPageNum);
+	/// `num` frames in a row, return the first
+	fn alloc_contiguous(&mut self, num: usize) -> Option<PhysPageNum>;
+	/// (total, free) frames
+	fn usage(&self) -> (usize, usize);
 }
 
 pub struct FrameAllocator {
@@ -62,12 +66,29 @@ impl FrameAllocator {
 		}
 	}
 
+	/// `num` zeroed frames in a row, which are never given back, e.g. the
+	/// kernel stack of a hart.
+	pub fn frames_alloc_contiguous(&self, num: usize) -> Option<PhysPageNum> {
+		let first = self.inner.lock().alloc_contiguous(num)?;
+		for ppn in first.0..first.0 + num {
+			let byte_array = unsafe { PhysPageNum::from(ppn).get_byte_array() };
+			byte_array.iter_mut().for_each(|b| *b = 0);
+		}
+		Some(first)
+	}
+
 	pub fn frame_dealloc(&self, ppn: PhysPageNum) {
 		self.inner.lock().dealloc(ppn);
 	}
+
+	/// (total, free) frames, unless the allocator is locked, e.g. in a crash dump
+	pub fn try_usage(&self) -> Option<(usize, usize)> {
+		self.inner.try_lock().map(|inner| inner.usage())
+	}
 }
 
 pub struct StackFrameAllocator {
+	start: usize,
 	current: usize,
 	end: usize,
 	recycled: Vec<usize>
@@ -102,11 +123,26 @@ impl FrameAllocatorInterface for StackFrameAllocator {
 		// recycle
         	self.recycled.push(ppn);
 	}
+
+	fn alloc_contiguous(&mut self, num: usize) -> Option<PhysPageNum> {
+		assert!(self.current <= self.end);
+		// recycled frames are scattered, take fresh ones
+		if self.end - self.current < num {
+			return None;
+		}
+		self.current += num;
+		Some((self.current - num).into())
+	}
+
+	fn usage(&self) -> (usize, usize) {
+		(self.end - self.start, self.end - self.current + self.recycled.len())
+	}
 }
 
 impl StackFrameAllocator {
 	pub fn new() -> Self {
 		Self {
+			start: 0,
 			current: 0,
 			end: 0,
 			recycled: Vec::new(),
@@ -114,6 +150,7 @@ impl StackFrameAllocator {
 	}
 
 	pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+		self.start = l.0;
 		self.current = l.0;
 		self.end = r.0;
 	}
@@ -164,4 +201,26 @@ mod tests {
 		assert_eq!(ppn1, ppn2);
 		crate::println!("test_frame_recycling passed!");
 	}
+
+	#[test_case]
+	fn frame_usage_test() {
+		let allocator = FRAME_ALLOCATOR.get().unwrap();
+		let (total, free) = allocator.try_usage().unwrap();
+		let frame = allocator.frame_alloc().unwrap();
+		assert_eq!(allocator.try_usage(), Some((total, free - 1)));
+		drop(frame);
+		assert_eq!(allocator.try_usage(), Some((total, free)));
+		crate::println!("frame_usage_test passed!");
+	}
+
+	#[test_case]
+	fn frame_contiguous_test() {
+		let allocator = FRAME_ALLOCATOR.get().unwrap();
+		let (total, free) = allocator.try_usage().unwrap();
+		let first = allocator.frames_alloc_contiguous(4).unwrap();
+		assert_eq!(allocator.try_usage(), Some((total, free - 4)));
+		let last = PhysPageNum::from(first.0 + 3);
+		assert!(unsafe { last.get_byte_array() }.iter().all(|&b| b == 0));
+		crate::println!("frame_contiguous_test passed!");
+	}
 }
\ No newline at end of file
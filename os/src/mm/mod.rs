@@ -9,6 +9,7 @@ pub mod address;
 pub mod page_table;
 pub mod frame_allocator;
 pub mod addr_space;
+pub mod user;
 
 pub fn init() {
 	// init frame allocator
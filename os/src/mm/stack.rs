@@ -1,17 +1,21 @@
 use core::intrinsics::forget;
 use core::ptr::NonNull;
 use core::ops::Range;
-use alloc::alloc::dealloc;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use alloc::alloc::{alloc, alloc_zeroed, dealloc};
 use riscv::interrupt::Trap;
 use core::alloc::Layout;
 
 use crate::arch::common::{ArchHarts, ArchPower, ArchTrap, FlowContext};
-use crate::global::{ARCH, sbss};
+use crate::cpu::PerCpu;
+use crate::global::{ARCH, BOOT_STACK, FRAME_ALLOCATOR, sbss};
+use crate::mm::address::PhysAddr;
 use crate::arch::common::Arch;
 use crate::{harts::HartContext, config::{USER_STACK_SIZE, KERNEL_STACK_SIZE}};
 use crate::trap::{FreeTrapStack, LoadedTrapStack, TrapHandler};
 use crate::trap::fast::FastHandler;
-use crate::config::KERNEL_STACK_ALIGN;
+use crate::config::{KERNEL_STACK_ALIGN, KTHREAD_STACK_SIZE, PAGE_SIZE, TASK_KERNEL_STACK_SIZE};
 
 // Make sure stack address can be aligned.
 const _: () = assert!(KERNEL_STACK_SIZE % align_of::<KernelStack>() == 0);
@@ -63,6 +67,14 @@ impl KernelStack {
 			}
 	}
 
+	pub fn traph_mut(&mut self) -> &mut TrapHandler {
+		unsafe {
+			&mut *(self.0.as_mut_ptr()
+				.byte_add(Self::hart_context_size() + Self::stack_space_size())
+				as *mut TrapHandler)
+			}
+	}
+
 	/// get hart context size
 	pub const fn hart_context_size() -> usize {
 		size_of::<HartContext>()
@@ -164,6 +176,36 @@ impl KernelStack {
 	}
 }
 
+/// kernel stack of each hart, where its idle flow runs and its `HartContext` lives
+static HART_STACKS: PerCpu<AtomicPtr<KernelStack>> = PerCpu::new(|| AtomicPtr::new(null_mut()));
+
+/// The boot hart runs on `BOOT_STACK`, see `entry::locate`.
+pub fn set_boot_stack(hartid: usize) {
+	HART_STACKS[hartid].store(&raw mut BOOT_STACK, Ordering::Release);
+}
+
+/// Give `hartid` a kernel stack from frames unless it has one, and return
+/// its top for `entry::hart_start`. An offline hart keeps it for the next start.
+pub fn alloc_hart_stack(hartid: usize) -> Option<usize> {
+	let slot = &HART_STACKS[hartid];
+	let mut stack = slot.load(Ordering::Acquire);
+	if stack.is_null() {
+		let ppn = FRAME_ALLOCATOR.get().unwrap()
+			.frames_alloc_contiguous(KERNEL_STACK_SIZE / PAGE_SIZE)?;
+		stack = PhysAddr::from(ppn).0 as *mut KernelStack;
+		slot.store(stack, Ordering::Release);
+	}
+	Some(stack as usize + KERNEL_STACK_SIZE)
+}
+
+/// Kernel stack of `hartid`, only used by that hart.
+pub fn hart_stack(hartid: usize) -> &'static mut KernelStack {
+	let stack = HART_STACKS[hartid].load(Ordering::Acquire);
+	assert!(!stack.is_null(), "hart {} has no kernel stack", hartid);
+	// SAFETY: the stack is never freed
+	unsafe { &mut *stack }
+}
+
 /// drop kernel stack and internel flow_context
 pub fn stack_drop(range: Range<usize>) {
 	assert_eq!(range.end - range.start, KERNEL_STACK_SIZE);
@@ -176,3 +218,92 @@ pub fn stack_drop(range: Range<usize>) {
 		dealloc(flow_context.as_ptr() as *mut u8, flow_context_layout);
 	}
 }
+
+/// Stack of a kernel thread, allocated from the kernel heap.
+///
+/// It holds no `TrapHandler` or `HartContext`, a kernel thread never
+/// enters or leaves the trampoline.
+pub struct KThreadStack(NonNull<u8>);
+
+// SAFETY: the stack memory is owned by this struct only
+unsafe impl Send for KThreadStack {}
+unsafe impl Sync for KThreadStack {}
+
+impl KThreadStack {
+	const LAYOUT: Layout = unsafe {
+		Layout::from_size_align_unchecked(KTHREAD_STACK_SIZE, KERNEL_STACK_ALIGN)
+	};
+
+	pub fn new() -> Self {
+		let base = unsafe { alloc(Self::LAYOUT) };
+		Self(NonNull::new(base).expect("kernel thread stack allocation fail"))
+	}
+
+	/// initial sp of the thread
+	pub fn top(&self) -> usize {
+		self.0.as_ptr() as usize + KTHREAD_STACK_SIZE
+	}
+}
+
+impl Drop for KThreadStack {
+	fn drop(&mut self) {
+		unsafe { dealloc(self.0.as_ptr(), Self::LAYOUT) }
+	}
+}
+
+/// Kernel stack owned by one task, allocated from the kernel heap.
+///
+/// The trampoline switches to it through the `ksp` of the hart running the
+/// task, so a task can leave the hart in the middle of a trap and go on
+/// later, maybe on another hart. `HartContext` stays in `KernelStack`.
+//
+//                 TaskKernelStack
+//     low_addr   +----Stack Space---+
+//                |                  |
+//                |                  |
+//                +----TrapHandler---+
+//          ksp-> | context(ptr)     |
+//                | ...(unalign)     |
+//     hign addr  +------------------+
+pub struct TaskKernelStack(FreeTrapStack);
+
+impl TaskKernelStack {
+	const LAYOUT: Layout = unsafe {
+		Layout::from_size_align_unchecked(TASK_KERNEL_STACK_SIZE, KERNEL_STACK_ALIGN)
+	};
+
+	/// None if the kernel heap has no room for it.
+	pub fn new(
+		flow_context: NonNull<FlowContext>,
+		hart_context_va: NonNull<HartContext>,
+		fast_handler: FastHandler,
+	) -> Option<Self> {
+		// TrapHandler fields which are not set here must start from zero
+		let base = unsafe { alloc_zeroed(Self::LAYOUT) };
+		if base.is_null() {
+			return None;
+		}
+		let base = base as usize;
+		Some(Self(FreeTrapStack::new(
+			base..base + TASK_KERNEL_STACK_SIZE,
+			task_stack_drop,
+			flow_context,
+			hart_context_va,
+			fast_handler
+		).unwrap()))
+	}
+
+	/// physical address of the trap handler, which is also the ksp
+	pub fn traph(&self) -> usize {
+		self.0.kstack_ptr()
+	}
+
+	pub fn trap_handler(&self) -> &mut TrapHandler {
+		unsafe { &mut *(self.traph() as *mut TrapHandler) }
+	}
+}
+
+fn task_stack_drop(range: Range<usize>) {
+	assert_eq!(range.end - range.start, TASK_KERNEL_STACK_SIZE);
+	unsafe { dealloc(range.start as *mut u8, TaskKernelStack::LAYOUT) }
+}
@@ -0,0 +1,215 @@
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::slice::{from_raw_parts, from_raw_parts_mut};
+use core::sync::atomic::AtomicU32;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::config::PAGE_SIZE;
+use crate::error::{Errno, SysResult};
+use crate::mm::addr_space::{AddrSpace, MapPermission};
+
+/// Plain data, which is copied from and to user space as bytes.
+///
+/// # Safety
+///
+/// Any bytes must be a valid value and there must be no padding, which
+/// would leak kernel memory. So only integers and repr(C) structs or arrays
+/// of them, never e.g. bool, char, enums or references.
+pub unsafe trait Pod: Sized {}
+
+macro_rules! impl_pod {
+	($($ty:ty),*) => {
+		$(unsafe impl Pod for $ty {})*
+	};
+}
+
+impl_pod!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, AtomicU32);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// Bytes of a plain value, to copy it to user space or into a core dump.
+pub(crate) fn as_bytes<T: Pod>(val: &T) -> &[u8] {
+	unsafe { from_raw_parts(val as *const T as *const u8, size_of::<T>()) }
+}
+
+/// A `T` in user space, given to a syscall by address.
+///
+/// Every access checks that the area is user accessible with the needed
+/// permission, may cross pages and fails with EFAULT. Any bytes the user
+/// left there are a valid `T`, see `Pod`.
+pub struct UserPtr<T: Pod> {
+	addr: usize,
+	_marker: PhantomData<*mut T>,
+}
+
+impl<T: Pod> Clone for UserPtr<T> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<T: Pod> Copy for UserPtr<T> {}
+
+impl<T: Pod> UserPtr<T> {
+	pub fn new(addr: usize) -> Self {
+		Self { addr, _marker: PhantomData }
+	}
+
+	pub fn is_null(&self) -> bool {
+		self.addr == 0
+	}
+
+	fn check(&self, addr_space: &AddrSpace, perm: MapPermission) -> SysResult<()> {
+		if addr_space.check_user_buf(self.addr, size_of::<T>(), perm) {
+			Ok(())
+		} else {
+			Err(Errno::EFAULT)
+		}
+	}
+
+	pub fn read(&self, addr_space: &AddrSpace) -> SysResult<T> {
+		self.check(addr_space, MapPermission::R)?;
+		let mut val = MaybeUninit::<T>::uninit();
+		// SAFETY: only written through the byte view before it is read, and
+		// any bytes are a valid `T`
+		let bytes = unsafe { from_raw_parts_mut(val.as_mut_ptr() as *mut u8, size_of::<T>()) };
+		addr_space.copy_from_user(self.addr, bytes).ok_or(Errno::EFAULT)?;
+		Ok(unsafe { val.assume_init() })
+	}
+
+	pub fn write(&self, addr_space: &AddrSpace, val: &T) -> SysResult<()> {
+		self.check(addr_space, MapPermission::W)?;
+		addr_space.copy_to_user(self.addr, as_bytes(val)).ok_or(Errno::EFAULT)
+	}
+
+	/// Kernel address of the value, for in place access like atomics.
+	///
+	/// It must be aligned, EINVAL otherwise, and not cross a page.
+	pub fn translate(&self, addr_space: &AddrSpace, perm: MapPermission) -> SysResult<*mut T> {
+		if self.addr % align_of::<T>() != 0 {
+			return Err(Errno::EINVAL);
+		}
+		if self.addr % PAGE_SIZE + size_of::<T>() > PAGE_SIZE {
+			return Err(Errno::EFAULT);
+		}
+		self.check(addr_space, perm)?;
+		let paddr = addr_space.translate_vaddr(self.addr.into()).ok_or(Errno::EFAULT)?;
+		Ok(paddr.0 as *mut T)
+	}
+}
+
+/// `len` bytes of user space.
+#[derive(Clone, Copy)]
+pub struct UserSlice {
+	addr: usize,
+	len: usize,
+}
+
+impl UserSlice {
+	pub fn new(addr: usize, len: usize) -> Self {
+		Self { addr, len }
+	}
+
+	fn check(&self, addr_space: &AddrSpace, perm: MapPermission) -> SysResult<()> {
+		if addr_space.check_user_buf(self.addr, self.len, perm) {
+			Ok(())
+		} else {
+			Err(Errno::EFAULT)
+		}
+	}
+
+	/// The slice split at page boundaries, to read in place.
+	pub fn buffers(&self, addr_space: &AddrSpace) -> SysResult<Vec<&'static [u8]>> {
+		self.check(addr_space, MapPermission::R)?;
+		addr_space
+			.translated_byte_buffer(self.addr as *const u8, self.len)
+			.ok_or(Errno::EFAULT)
+	}
+
+	/// The slice split at page boundaries, to write in place.
+	pub fn buffers_mut(&self, addr_space: &AddrSpace) -> SysResult<Vec<&'static mut [u8]>> {
+		self.check(addr_space, MapPermission::W)?;
+		addr_space
+			.translated_byte_buffer_mut(self.addr as *const u8, self.len)
+			.ok_or(Errno::EFAULT)
+	}
+}
+
+/// A NUL terminated UTF-8 string in user space.
+#[derive(Clone, Copy)]
+pub struct UserStr {
+	addr: usize,
+}
+
+impl UserStr {
+	pub fn new(addr: usize) -> Self {
+		Self { addr }
+	}
+
+	/// Copy the string without its NUL, EINVAL if it is longer than `max_len`
+	/// bytes or not UTF-8.
+	pub fn read(&self, addr_space: &AddrSpace, max_len: usize) -> SysResult<String> {
+		let mut bytes = Vec::new();
+		let mut addr = self.addr;
+		loop {
+			// up to the end of the page, so no page past the NUL is touched
+			let buf = UserSlice::new(addr, PAGE_SIZE - addr % PAGE_SIZE).buffers(addr_space)?[0];
+			let end = buf.iter().position(|&byte| byte == 0);
+			bytes.extend_from_slice(&buf[..end.unwrap_or(buf.len())]);
+			if bytes.len() > max_len {
+				return Err(Errno::EINVAL);
+			}
+			if end.is_some() {
+				return String::from_utf8(bytes).map_err(|_| Errno::EINVAL);
+			}
+			addr += buf.len();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mm::address::VirtAddr;
+
+	#[test_case]
+	fn user_ptr_test() {
+		let base = 0x10000;
+		let mut addr_space = AddrSpace::new_bare();
+		addr_space.insert_framed_area(
+			VirtAddr::from(base),
+			VirtAddr::from(base + 2 * PAGE_SIZE),
+			MapPermission::R | MapPermission::W | MapPermission::U
+		);
+		addr_space.insert_framed_area(
+			VirtAddr::from(base + 2 * PAGE_SIZE),
+			VirtAddr::from(base + 3 * PAGE_SIZE),
+			MapPermission::R | MapPermission::U
+		);
+
+		// across the first page boundary
+		let ptr = UserPtr::<u64>::new(base + PAGE_SIZE - 4);
+		ptr.write(&addr_space, &0x1122_3344_5566_7788).unwrap();
+		assert_eq!(ptr.read(&addr_space), Ok(0x1122_3344_5566_7788));
+		assert_eq!(ptr.translate(&addr_space, MapPermission::R).err(), Some(Errno::EINVAL));
+		// read only, and not mapped
+		let ro = UserPtr::<u64>::new(base + 2 * PAGE_SIZE);
+		assert!(ro.read(&addr_space).is_ok());
+		assert_eq!(ro.write(&addr_space, &1), Err(Errno::EFAULT));
+		assert_eq!(UserPtr::<u64>::new(base + 3 * PAGE_SIZE).read(&addr_space), Err(Errno::EFAULT));
+		assert_eq!(UserPtr::<u64>::new(0).read(&addr_space), Err(Errno::EFAULT));
+
+		let s = b"piano\0";
+		let at = base + PAGE_SIZE - 3;
+		for (i, buf) in UserSlice::new(at, s.len()).buffers_mut(&addr_space).unwrap().into_iter().enumerate() {
+			let start = if i == 0 { 0 } else { 3 };
+			buf.copy_from_slice(&s[start..start + buf.len()]);
+		}
+		assert_eq!(UserStr::new(at).read(&addr_space, 16).as_deref(), Ok("piano"));
+		assert_eq!(UserStr::new(at).read(&addr_space, 4), Err(Errno::EINVAL));
+
+		crate::println!("user_ptr_test passed!");
+	}
+}
@@ -3,19 +3,26 @@ use core::ops::Range;
 use crate::console::ConsoleDevice;
 use crate::console::ConsoleType;
 use crate::console::KernelConsole;
+use crate::cpu::{CpuFeatures, CpuInfo, CpuMap};
 use crate::devicetree::ParseDeviceTreeError;
 use crate::devicetree::Tree;
 use crate::devicetree::get_compatible_and_range;
 use crate::devicetree::parse_device_tree;
 use crate::driver::chardev::riscvsbi::RiscvSbi;
 use crate::driver::chardev::uart16550::Uart16550Wrapper;
+use crate::driver::irq::{IrqController, IrqControllerType, IrqManager};
+use crate::driver::irq::aplic::Aplic;
+use crate::driver::irq::imsic::Imsic;
+use crate::driver::irq::plic::Plic;
+use crate::devicetree::{find_compatible, find_node, get_cells, get_first_cell, get_str, get_strs, get_u32, is_compatible};
 use crate::error::KernelError;
+use crate::sync::IrqMutex;
 
 use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::vec::Vec;
 use log::info;
 use serde_device_tree::buildin::Node;
-use spin::mutex::Mutex;
 
 pub type BaseAddr = usize;
 
@@ -34,16 +41,36 @@ impl<T> DeviceInfo<T> {
 
 pub struct BoardInfo {
 	pub cpu_num: Option<usize>,
+	/// harts the kernel runs on, see `cpu`
+	pub cpus: CpuMap,
+	/// hart ids of the cpu nodes which are disabled or can not run the kernel
+	pub skipped_harts: Vec<usize>,
 	pub cpu_freq: Option<usize>,
 	pub console: Option<DeviceInfo<ConsoleType>>,
+	/// `interrupts` of the stdout node
+	pub console_irq: Option<usize>,
+	pub irq_controller: Option<DeviceInfo<IrqControllerType>>,
+	/// interrupt sources of the controller
+	pub irq_num: usize,
+	/// identities of each IMSIC interrupt file, with an APLIC
+	pub msi_ids: usize,
+	/// `(hartid, context)` of the S mode PLIC context of each hart
+	pub plic_contexts: Vec<(usize, usize)>,
 }
 
 impl BoardInfo {
 	pub const fn new() -> BoardInfo {
 		BoardInfo {
 			cpu_num: None,
+			cpus: CpuMap::EMPTY,
+			skipped_harts: Vec::new(),
 			cpu_freq: None,
-			console: None
+			console: None,
+			console_irq: None,
+			irq_controller: None,
+			irq_num: 0,
+			msi_ids: 0,
+			plic_contexts: Vec::new(),
 		}
 	}
 
@@ -53,6 +80,9 @@ impl BoardInfo {
 		if let Some(console) = &self.console {
 			ranges.push(console.range.clone());
 		}
+		if let Some(irq_controller) = &self.irq_controller {
+			ranges.push(irq_controller.range.clone());
+		}
 
 		ranges
 	}
@@ -60,11 +90,12 @@ impl BoardInfo {
 
 pub struct BoardDevice {
 	pub console: Option<KernelConsole>,
+	pub irq: Option<IrqManager>,
 }
 
 impl BoardDevice {
 	pub const fn new() -> Self {
-		BoardDevice { console: None }
+		BoardDevice { console: None, irq: None }
 	}
 }
 
@@ -96,14 +127,114 @@ impl Platform {
 
 	fn init_board_info(tree: &Tree, root: &Node) -> Result<BoardInfo, ParseDeviceTreeError> {
 		let mut board_info = BoardInfo::new();
-		board_info.cpu_num = Some(tree.cpus.cpu.len());
+		Self::init_cpu_info(&mut board_info, root);
 		board_info.cpu_freq = Some(tree.cpus.timebase_frequency as usize);
-		board_info.console = Self::init_console_info(root)?;
+		Self::init_console_info(&mut board_info, root)?;
+		Self::init_irq_info(&mut board_info, root);
 		Ok(board_info)
 	}
 
-	fn init_console_info(root: &Node)
-			     -> Result<Option<DeviceInfo<ConsoleType>>, ParseDeviceTreeError>
+	/// Enabled rv64 harts with the extensions the kernel is built for, hart
+	/// ids are the `reg` of the cpu nodes and may have holes.
+	fn init_cpu_info(board_info: &mut BoardInfo, root: &Node) {
+		let mut cpus = Vec::new();
+		if let Some(cpus_node) = root.find("/cpus") {
+			for item in cpus_node.nodes() {
+				let node: Node = item.deserialize();
+				// also skips cpu-map
+				if get_str(&node, "device_type").as_deref() != Some("cpu") {
+					continue;
+				}
+				let Some(hartid) = get_first_cell(&node, "reg").map(|reg| reg as usize) else {
+					continue;
+				};
+				let enabled = get_str(&node, "status")
+					.is_none_or(|status| status == "okay" || status == "ok");
+				// newer trees list the extensions, older ones only have the string
+				let isa = get_str(&node, "riscv,isa")
+					.or_else(|| get_str(&node, "riscv,isa-base"))
+					.unwrap_or_default();
+				let extensions = get_strs(&node, "riscv,isa-extensions");
+				let features = CpuFeatures::from_isa(&isa).map(|features| {
+					features | CpuFeatures::from_extensions(extensions.iter().map(String::as_str))
+				});
+				match features {
+					Some(features) if enabled && features.contains(CpuFeatures::REQUIRED) => {
+						cpus.push(CpuInfo { hartid, isa, features });
+					}
+					_ => board_info.skipped_harts.push(hartid),
+				}
+			}
+		}
+		board_info.cpus = CpuMap::new(cpus);
+		board_info.cpu_num = Some(board_info.cpus.num_cpus());
+	}
+
+	/// A PLIC, or else an APLIC with IMSICs, none is fine as well, the kernel
+	/// then runs without external interrupts.
+	fn init_irq_info(board_info: &mut BoardInfo, root: &Node) {
+		let node = if let Some(plic) = find_compatible(root, IrqControllerType::PLIC_COMPATIBLE) {
+			board_info.irq_num = get_u32(&plic, "riscv,ndev").unwrap_or(0) as usize;
+			board_info.plic_contexts = Self::plic_contexts(&plic, root);
+			plic
+		} else {
+			// the S mode domain is the leaf, the M mode one has children
+			let Some(aplic) = find_node(root, &|node| {
+				is_compatible(node, IrqControllerType::APLIC_COMPATIBLE)
+					&& node.get_prop("riscv,children").is_none()
+			}) else {
+				return;
+			};
+			// without interrupt files the APLIC would need direct delivery
+			let Some(imsic) = find_compatible(root, IrqControllerType::IMSIC_COMPATIBLE) else {
+				return;
+			};
+			board_info.irq_num = get_u32(&aplic, "riscv,num-sources").unwrap_or(0) as usize;
+			board_info.msi_ids = get_u32(&imsic, "riscv,num-ids").unwrap_or(0) as usize;
+			aplic
+		};
+		let Some((compat, reg)) = get_compatible_and_range(&node) else {
+			return;
+		};
+		board_info.irq_controller = compat.iter()
+			.find_map(|dev| IrqControllerType::compatible(dev))
+			.map(|ctype| DeviceInfo::new(reg, ctype));
+	}
+
+	/// The S mode context of each hart, the i-th `<intc irq>` pair of
+	/// `interrupts-extended` is context i and irq 9 is the S mode external
+	/// interrupt of the hart of `intc`.
+	fn plic_contexts(plic: &Node, root: &Node) -> Vec<(usize, usize)> {
+		const S_EXTERNAL: u32 = 9;
+		// phandle of the interrupt controller of each cpu node
+		let mut intcs = Vec::new();
+		if let Some(cpus_node) = root.find("/cpus") {
+			for item in cpus_node.nodes() {
+				let cpu: Node = item.deserialize();
+				let Some(hartid) = get_first_cell(&cpu, "reg").map(|reg| reg as usize) else {
+					continue;
+				};
+				let intc = cpu.nodes()
+					.map(|item| item.deserialize::<Node>())
+					.find(|node| is_compatible(node, IrqControllerType::CPU_INTC_COMPATIBLE));
+				if let Some(phandle) = intc.and_then(|intc| get_u32(&intc, "phandle")) {
+					intcs.push((phandle, hartid));
+				}
+			}
+		}
+		get_cells(plic, "interrupts-extended")
+			.chunks_exact(2)
+			.enumerate()
+			.filter(|(_, pair)| pair[1] == S_EXTERNAL)
+			.filter_map(|(context, pair)| {
+				let &(_, hartid) = intcs.iter().find(|&&(phandle, _)| phandle == pair[0])?;
+				Some((hartid, context))
+			})
+			.collect()
+	}
+
+	fn init_console_info(board_info: &mut BoardInfo, root: &Node)
+			     -> Result<(), ParseDeviceTreeError>
 	{
 		let Some(stdout_path) = root.chosen_stdout_path() else {
 			return Err(ParseDeviceTreeError::NoStdout);
@@ -114,17 +245,42 @@ impl Platform {
 		let Some((compat, reg)) = get_compatible_and_range(&stdout_node) else {
 			return Err(ParseDeviceTreeError::NoCompatOrRange);
 		};
-		Ok(compat.iter()
+		board_info.console = compat.iter()
 			 .find_map(|dev| ConsoleType::compatible(dev))
-			 .map(|ctype| DeviceInfo::new(reg, ctype)))
+			 .map(|ctype| DeviceInfo::new(reg, ctype));
+		board_info.console_irq = get_first_cell(&stdout_node, "interrupts").map(|irq| irq as usize);
+		Ok(())
 	}
 
 	fn init_board_device(board_info: &BoardInfo) -> BoardDevice {
 		let mut board_device = BoardDevice::new();
 		board_device.console = Self::init_console(&board_info);
+		board_device.irq = Self::init_irq(&board_info);
 		board_device
 	}
 
+	fn init_irq(board_info: &BoardInfo) -> Option<IrqManager> {
+		let DeviceInfo{ range, devtype } = board_info.irq_controller.as_ref()?;
+		let harts: Vec<usize> = board_info.cpus.hart_ids().collect();
+		let controller: Box<dyn IrqController> = match devtype {
+			IrqControllerType::Plic => {
+				// only the harts the kernel runs on claim irqs
+				let contexts = board_info.plic_contexts.iter()
+					.filter(|&&(hartid, _)| harts.contains(&hartid))
+					.copied()
+					.collect();
+				Box::new(Plic::new(range.start, board_info.irq_num, contexts))
+			}
+			IrqControllerType::AplicImsic => Box::new(Aplic::new(
+				range.start,
+				board_info.irq_num,
+				harts,
+				Imsic::new(board_info.msi_ids)
+			)),
+		};
+		Some(IrqManager::new(controller))
+	}
+
 	fn init_console(board_info: &BoardInfo) -> Option<KernelConsole> {
 		let Some(DeviceInfo{ range, devtype }) = &board_info.console else {
 			return None;
@@ -134,16 +290,30 @@ impl Platform {
 			ConsoleType::Uart16550U32 => Box::new(Uart16550Wrapper::<u32>::new(range.start)),
 			ConsoleType::RiscvSbi => Box::new(RiscvSbi),
 		};
-		Some(KernelConsole::new(Mutex::new(console)))
+		Some(KernelConsole::new(IrqMutex::new(console)))
 	}
 
 	pub fn print_platform_info(&self) {
 		info!("cpu number: {}", self.board_info.cpu_num.unwrap());
+		for (cpu, info) in self.board_info.cpus.cpus().iter().enumerate() {
+			info!("cpu {}: hart {}, isa {}", cpu, info.hartid, info.isa);
+		}
+		info!("extensions of every cpu: {:?}", self.board_info.cpus.features());
+		if !self.board_info.skipped_harts.is_empty() {
+			info!("harts {:?} are disabled or unsupported", self.board_info.skipped_harts);
+		}
 		info!("cpu freq: {}", self.board_info.cpu_freq.unwrap());
 		info!("uart type is {:#?}, addr is 0x{:X} - 0x{:X}",
 		      self.board_info.console.as_ref().unwrap().devtype,
 		      self.board_info.console.as_ref().unwrap().range.start,
 		      self.board_info.console.as_ref().unwrap().range.end
-		)
+		);
+		match &self.board_info.irq_controller {
+			Some(DeviceInfo{ range, devtype }) => info!(
+				"irq controller is {:?} with {} sources, addr is 0x{:X} - 0x{:X}",
+				devtype, self.board_info.irq_num, range.start, range.end
+			),
+			None => info!("no irq controller, external interrupts are off"),
+		}
 	}
 }
@@ -0,0 +1,112 @@
+//! Locks which interrupt handlers take as well.
+//!
+//! With `nested_trap` the kernel runs with interrupts on, so a handler may
+//! come on a hart which holds the lock it wants and spin forever. These
+//! locks turn interrupts off on the hart while they are held.
+
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use spin::{Mutex, MutexGuard};
+
+use crate::arch::common::ArchHarts;
+use crate::global::ARCH;
+
+/// Interrupts are off on this hart until it is dropped, then as they were.
+pub struct IrqOff {
+	was_on: bool,
+}
+
+impl IrqOff {
+	pub fn new() -> Self {
+		Self { was_on: ARCH.irq_save() }
+	}
+}
+
+impl Drop for IrqOff {
+	fn drop(&mut self) {
+		ARCH.irq_restore(self.was_on);
+	}
+}
+
+/// A spin lock which keeps interrupts off while it is held.
+pub struct IrqMutex<T: ?Sized> {
+	inner: Mutex<T>,
+}
+
+pub struct IrqMutexGuard<'a, T: ?Sized> {
+	guard: ManuallyDrop<MutexGuard<'a, T>>,
+	irq: ManuallyDrop<IrqOff>,
+}
+
+impl<T> IrqMutex<T> {
+	pub const fn new(value: T) -> Self {
+		Self { inner: Mutex::new(value) }
+	}
+}
+
+impl<T: ?Sized> IrqMutex<T> {
+	pub fn lock(&self) -> IrqMutexGuard<'_, T> {
+		// off before it spins, the holder may be interrupted on this hart
+		let irq = IrqOff::new();
+		IrqMutexGuard { guard: ManuallyDrop::new(self.inner.lock()), irq: ManuallyDrop::new(irq) }
+	}
+
+	pub fn try_lock(&self) -> Option<IrqMutexGuard<'_, T>> {
+		let irq = IrqOff::new();
+		let guard = self.inner.try_lock()?;
+		Some(IrqMutexGuard { guard: ManuallyDrop::new(guard), irq: ManuallyDrop::new(irq) })
+	}
+
+	/// # Safety
+	///
+	/// As `spin::Mutex::force_unlock`, e.g. the holder never lets it go.
+	pub unsafe fn force_unlock(&self) {
+		unsafe { self.inner.force_unlock() };
+	}
+}
+
+impl<T: ?Sized> Deref for IrqMutexGuard<'_, T> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		&self.guard
+	}
+}
+
+impl<T: ?Sized> DerefMut for IrqMutexGuard<'_, T> {
+	fn deref_mut(&mut self) -> &mut T {
+		&mut self.guard
+	}
+}
+
+impl<T: ?Sized> Drop for IrqMutexGuard<'_, T> {
+	fn drop(&mut self) {
+		// unlocked first, an interrupt must not find it still held
+		unsafe {
+			ManuallyDrop::drop(&mut self.guard);
+			ManuallyDrop::drop(&mut self.irq);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test_case]
+	fn irq_mutex_test() {
+		// tests run with interrupts off, they stay off
+		let was_on = ARCH.irq_save();
+		let lock = IrqMutex::new(1);
+		{
+			let mut guard = lock.lock();
+			*guard += 1;
+			assert!(lock.try_lock().is_none());
+		}
+		assert_eq!(*lock.try_lock().unwrap(), 2);
+		assert!(!ARCH.irq_save());
+		ARCH.irq_restore(was_on);
+
+		crate::println!("irq_mutex_test passed!");
+	}
+}
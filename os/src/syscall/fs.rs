@@ -1,58 +1,62 @@
-use core::{slice::from_raw_parts, str::from_utf8};
-
-use alloc::task;
-use log::warn;
-
-use crate::{global::{TASK_MANAGER, USER_STACK}, harts::{task_context_in_trap_stage}, print};
+use crate::{console, error::{Errno, SysResult}, harts::{task_context_in_trap_stage}, mm::user::UserSlice, task::preempt_point};
 
+const FD_STDIN: usize = 0;
 const FD_STDOUT: usize = 1;
 
-pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-	let addr_space = task_context_in_trap_stage()
-		.addr_space();
-	let Some(phy_buf) = addr_space.translated_byte_buffer(buf, len) else {
-        	return -1;
-    	};
-	// TODO: check
-//	if !check_buf_valid(buf, len) {
-//		return -1;
-//	}
+/// Read at most `len` bytes of `fd` to `buf`.
+///
+/// stdin blocks until some input came in, then returns what is there, or
+/// EINTR if a signal came first.
+pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> SysResult {
 	match fd {
-		FD_STDOUT => {
-			for buf in phy_buf {
-				let str = from_utf8(buf).unwrap();
-				print!("{}", str);
+		FD_STDIN => {
+			let tcb = task_context_in_trap_stage();
+			let mut buffers = UserSlice::new(buf as usize, len).buffers_mut(tcb.addr_space())?;
+			if len == 0 {
+				return Ok(0);
+			}
+			loop {
+				let mut count = 0;
+				for buf in buffers.iter_mut() {
+					let read = console::read(buf);
+					count += read;
+					if read < buf.len() {
+						break;
+					}
+				}
+				if count > 0 {
+					return Ok(count);
+				}
+				// a polled console never blocks, so look for signals here
+				if tcb.signals.has_deliverable() {
+					return Err(Errno::EINTR);
+				}
+				// another reader may take the input first, so check again
+				console::wait_input()?;
 			}
-			len as isize
 		}
 		_ => {
-			-1
+			Err(Errno::EBADF)
 		}
 	}
 }
 
-fn check_buf_valid(buf: *const u8, len: usize) -> bool {
-	let app_info = task_context_in_trap_stage().app_info();
-	let cur_app = app_info.app_id;
-	let app_size = TASK_MANAGER.get().unwrap().app_size(cur_app);
-
-	let app_range = app_info.app_range.clone();
-	let app_stack_range = unsafe { USER_STACK[cur_app].as_ptr_range() };
-	if unsafe {
-		(app_range.contains(&buf) && app_range.contains(&buf.add(len))) ||
-		(app_stack_range.contains(&buf) && app_stack_range.contains(&buf.add(len)))
-	} {
-		true
-	}
-	else {
-		warn!("buf out of scope");
-		warn!("buf addr: 0x{:x}", buf as usize);
-		warn!("buf size: 0x{:x}", len as usize);
-		warn!("ustack start: 0x{:x}", app_stack_range.start as usize);
-		warn!("ustack end  : 0x{:x}", app_stack_range.end as usize);
-		warn!("app start: 0x{:x}", app_range.start as usize);
-		warn!("app end:   0x{:x}", app_range.end as usize);
-		warn!("app size: 0x{:x}", app_size);
-		false
+/// Write `len` bytes at `buf` to `fd`, they go to the device as they are.
+///
+/// stdout is line buffered per task, see `LineBuffer`.
+pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> SysResult {
+	match fd {
+		FD_STDOUT => {
+			let tcb = task_context_in_trap_stage();
+			for buf in UserSlice::new(buf as usize, len).buffers(tcb.addr_space())? {
+				tcb.stdout.write(buf, tcb.id());
+				// long writes should not hold the hart for the whole tick
+				preempt_point();
+			}
+			Ok(len)
+		}
+		_ => {
+			Err(Errno::EBADF)
+		}
 	}
-}
\ No newline at end of file
+}
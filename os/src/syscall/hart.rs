@@ -0,0 +1,38 @@
+use crate::config::HOTPLUG_APPS;
+use crate::error::{Errno, SysResult};
+use crate::global::ELFS_INFO;
+use crate::harts::{task_context_in_trap_stage, HartState};
+use crate::task::hotplug;
+
+const HART_STATUS: usize = 0;
+const HART_OFFLINE: usize = 1;
+const HART_ONLINE: usize = 2;
+
+/// Hotplug of hart `hartid`.
+///
+/// - `HART_STATUS`: return its `HartState`, the numbers of the SBI, or
+///   ENODEV if the SBI does not know it.
+/// - `HART_OFFLINE`: stop it, return 0 once it stopped. The tasks there go
+///   on on other harts, EBUSY if it is the last online hart.
+/// - `HART_ONLINE`: start it again, return 0 at once.
+///
+/// Only the apps in `HOTPLUG_APPS` may take harts offline or online, the
+/// others get EPERM. Return EINVAL for a bad `hartid` or `op`, EIO if the SBI refused.
+pub fn sys_hart_ctl(hartid: usize, op: usize) -> SysResult {
+	match op {
+		HART_STATUS => match hotplug::hart_status(hartid)? {
+			HartState::Invalid => Err(Errno::ENODEV),
+			state => Ok(state as usize),
+		},
+		HART_OFFLINE | HART_ONLINE if !privileged() => Err(Errno::EPERM),
+		HART_OFFLINE => Ok(hotplug::offline(hartid).map(|_| 0)?),
+		HART_ONLINE => Ok(hotplug::online(hartid).map(|_| 0)?),
+		_ => Err(Errno::EINVAL),
+	}
+}
+
+/// The caller belongs to an app in `HOTPLUG_APPS`.
+fn privileged() -> bool {
+	let app_id = task_context_in_trap_stage().process.app_id;
+	HOTPLUG_APPS.contains(&ELFS_INFO.get().unwrap().app_name(app_id))
+}
@@ -1,15 +1,29 @@
 pub mod syscallid;
 pub mod fs;
 pub mod process;
+pub mod thread;
+pub mod sync;
+pub mod signal;
+pub mod hart;
 
+use crate::error::SysResult;
 use crate::syscall::process::sys_get_time;
 use crate::syscall::syscallid::SyscallID;
-use crate::syscall::fs::sys_write;
+use crate::syscall::fs::{sys_read, sys_write};
 use crate::syscall::process::sys_exit;
 use crate::syscall::process::sys_get_taskid;
+use crate::syscall::process::sys_task_info;
+use crate::syscall::thread::{sys_gettid, sys_thread_create, sys_waittid};
+use crate::syscall::sync::sys_futex;
+use crate::syscall::signal::{sys_kill, sys_sigaction, sys_sigprocmask};
+use crate::syscall::hart::sys_hart_ctl;
 
+/// Run `syscall_id`, return its value or `-errno`.
 pub fn syscall(syscall_id: SyscallID, args: [usize; 3]) -> isize {
-	match syscall_id {
+	let result: SysResult = match syscall_id {
+		SyscallID::Read => {
+			sys_read(args[0], args[1] as *mut u8, args[2])
+		},
 	    	SyscallID::Write => {
 			sys_write(args[0], args[1] as *const u8, args[2])
 		},
@@ -17,11 +31,43 @@ pub fn syscall(syscall_id: SyscallID, args: [usize; 3]) -> isize {
 			sys_exit(args[0] as i32)
 		},
 		SyscallID::GetTaskID => {
-			sys_get_taskid() as isize
+			sys_get_taskid()
 		},
 		SyscallID::GetTime => {
 			sys_get_time()
 		}
-		_ => 0
+		SyscallID::ThreadCreate => {
+			sys_thread_create(args[0], args[1])
+		}
+		SyscallID::GetTid => {
+			sys_gettid()
+		}
+		SyscallID::WaitTid => {
+			sys_waittid(args[0])
+		}
+		SyscallID::Futex => {
+			sys_futex(args[0], args[1], args[2] as u32)
+		}
+		SyscallID::Kill => {
+			sys_kill(args[0], args[1])
+		}
+		SyscallID::SigAction => {
+			sys_sigaction(args[0], args[1], args[2])
+		}
+		SyscallID::SigProcMask => {
+			sys_sigprocmask(args[0], args[1])
+		}
+		SyscallID::TaskInfo => {
+			sys_task_info(args[0])
+		}
+		SyscallID::HartCtl => {
+			sys_hart_ctl(args[0], args[1])
+		}
+		// Yield and SigReturn switch the user context, see syscall_handler
+		_ => Ok(0)
+	};
+	match result {
+		Ok(ret) => ret as isize,
+		Err(errno) => errno.to_ret(),
 	}
-}
\ No newline at end of file
+}
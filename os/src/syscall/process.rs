@@ -1,17 +1,64 @@
 use crate::arch::common::ArchTime;
+use crate::config::MAX_SYSCALL_NUM;
+use crate::error::{Errno, SysResult};
 use crate::global::ARCH;
 use crate::harts::task_context_in_trap_stage;
 use crate::info;
+use crate::mm::user::{Pod, UserPtr};
 
-pub fn sys_exit(xstate: i32) -> isize {
+/// What `sys_task_info` reports, the layout is shared with user_lib.
+#[repr(C)]
+pub struct TaskInfo {
+	/// `TaskStatus` as u8, always Running for the caller
+	pub status: u32,
+	/// tid inside the app
+	pub tid: u32,
+	/// calls of each syscall, indexed by syscall id
+	pub syscall_times: [u32; MAX_SYSCALL_NUM],
+	/// ms since the task first ran
+	pub time: u64,
+	/// time spent in user mode, in us
+	pub user_time: u64,
+	/// time spent in the kernel, in us
+	pub kernel_time: u64,
+}
+
+// SAFETY: integers only, `syscall_times` has an even length so `time` needs no padding
+unsafe impl Pod for TaskInfo {}
+const _: () = assert!(MAX_SYSCALL_NUM % 2 == 0);
+
+pub fn sys_exit(xstate: i32) -> SysResult {
 	info!("Application exited with code {}", xstate);
-	0
+	// the task ends in the trap handler, after the syscall
+	task_context_in_trap_stage().set_exit_code(xstate);
+	Ok(0)
+}
+
+pub fn sys_get_taskid() -> SysResult {
+	Ok(task_context_in_trap_stage().app_info().app_id)
 }
 
-pub fn sys_get_taskid() -> isize {
-	task_context_in_trap_stage().app_info().app_id as isize
+pub fn sys_get_time() -> SysResult {
+	Ok(ARCH.time_ms() as usize)
 }
 
-pub fn sys_get_time() -> isize {
-	ARCH.time_ms() as isize
+/// Fill `*ti` with the status, syscall counts and run time of the current task.
+///
+/// Return 0, or EFAULT if `ti` is not a writable user buffer.
+pub fn sys_task_info(ti: usize) -> SysResult {
+	let tcb = task_context_in_trap_stage();
+	let app_info = tcb.app_info();
+	let mut info = TaskInfo {
+		status: u8::from(tcb.status()) as u32,
+		tid: tcb.tid as u32,
+		syscall_times: [0; MAX_SYSCALL_NUM],
+		time: ARCH.time_ms() - app_info.start_time,
+		user_time: app_info.user_time.elapsed() / 1000,
+		kernel_time: app_info.kernel_time.elapsed() / 1000,
+	};
+	app_info.syscall_record.iter().for_each(|(syscall, count)| {
+		info.syscall_times[*syscall as usize] = *count as u32;
+	});
+	UserPtr::<TaskInfo>::new(ti).write(tcb.addr_space(), &info)?;
+	Ok(0)
 }
@@ -0,0 +1,81 @@
+use crate::error::{Errno, SysResult};
+use crate::global::TASK_MANAGER;
+use crate::harts::task_context_in_trap_stage;
+use crate::mm::user::UserPtr;
+use crate::task::signal::{
+	restore_frame, send_signal, valid_signal, SigAction, NSIG, SIGKILL, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK
+};
+
+/// Send `signo` to the app `pid`, as returned by get_taskid.
+///
+/// It is delivered to the main thread the next time that returns to user
+/// mode, a wait of it in the kernel ends with EINTR. `signo` 0 only checks
+/// that the app is alive. Return 0, ESRCH if there is no such app or EINVAL
+/// if there is no such signal.
+pub fn sys_kill(pid: usize, signo: usize) -> SysResult {
+	let manager = TASK_MANAGER.get().unwrap();
+	if signo >= NSIG {
+		return Err(Errno::EINVAL);
+	}
+	if pid >= manager.num_app {
+		return Err(Errno::ESRCH);
+	}
+	// the main thread of app i is task i
+	let target = manager.task(pid);
+	if target.process.is_exiting() {
+		return Err(Errno::ESRCH);
+	}
+	if signo != 0 {
+		send_signal(&target, signo);
+	}
+	Ok(0)
+}
+
+/// Set the action of `signo` to `*act` if `act` is not null, and store the
+/// old one to `*oldact` if that is not null.
+///
+/// Return 0, EINVAL for a bad signal or EFAULT for a bad pointer. The action
+/// of SIGKILL can not change.
+pub fn sys_sigaction(signo: usize, act: usize, oldact: usize) -> SysResult {
+	if !valid_signal(signo) || signo == SIGKILL {
+		return Err(Errno::EINVAL);
+	}
+	let tcb = task_context_in_trap_stage();
+	let (act, oldact) = (UserPtr::<SigAction>::new(act), UserPtr::<SigAction>::new(oldact));
+	let old = if !act.is_null() {
+		let new = act.read(tcb.addr_space())?;
+		tcb.process.set_sigaction(signo, new)
+	} else {
+		tcb.process.sigaction(signo)
+	};
+	if !oldact.is_null() {
+		oldact.write(tcb.addr_space(), &old)?;
+	}
+	Ok(0)
+}
+
+/// Change the blocked signals of the current thread by `how` and `set`.
+///
+/// Return the old mask, or EINVAL for a bad `how`. SIGKILL is never blocked.
+pub fn sys_sigprocmask(how: usize, set: usize) -> SysResult {
+	let signals = &task_context_in_trap_stage().signals;
+	let old = signals.blocked();
+	let set = set as u32;
+	let new = match how {
+		SIG_BLOCK => old | set,
+		SIG_UNBLOCK => old & !set,
+		SIG_SETMASK => set,
+		_ => return Err(Errno::EINVAL),
+	};
+	signals.set_blocked(new);
+	Ok(old as usize)
+}
+
+/// Return from a signal handler, the frame is at the user sp.
+///
+/// It runs on the entire path, with all user registers in the flow context,
+/// and restores them from the frame. Return EFAULT if the frame is not mapped.
+pub fn sys_sigreturn() -> SysResult {
+	restore_frame(task_context_in_trap_stage()).ok_or(Errno::EFAULT)?;
+	Ok(0)
+}
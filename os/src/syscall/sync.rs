@@ -0,0 +1,50 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::error::{Errno, SysResult};
+use crate::harts::task_context_in_trap_stage;
+use crate::mm::addr_space::MapPermission;
+use crate::mm::user::UserPtr;
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+
+/// Futex on the u32 at `uaddr` of the current app.
+///
+/// - `FUTEX_WAIT`: block while the value is `val`, return 0 once woken,
+///   EAGAIN at once if the value differs, or EINTR if a signal came first.
+/// - `FUTEX_WAKE`: wake at most `val` waiters, return how many were woken.
+///
+/// Return EINVAL for a misaligned `uaddr` or bad `op`, EFAULT if `uaddr` is not mapped.
+pub fn sys_futex(uaddr: usize, op: usize, val: u32) -> SysResult {
+	let process = task_context_in_trap_stage().process.clone();
+	let futex = UserPtr::<AtomicU32>::new(uaddr)
+		.translate(process.addr_space(), MapPermission::R | MapPermission::W)?;
+	// SAFETY: aligned, framed and owned by the app, the app changes it atomically
+	let futex = unsafe { &*futex };
+	match op {
+		FUTEX_WAIT => {
+			let queue = process.futex_queue(uaddr);
+			// the value is read under the queue lock, a wake after the
+			// user changed it can not be missed
+			let waited = queue.wait_unless(|| futex.load(Ordering::SeqCst) != val);
+			process.put_futex_queue(uaddr, queue);
+			match waited? {
+				true => Ok(0),
+				false => Err(Errno::EAGAIN),
+			}
+		}
+		FUTEX_WAKE => {
+			// a futex nobody waits on gets no queue
+			let Some(queue) = process.find_futex_queue(uaddr) else {
+				return Ok(0);
+			};
+			let mut woken = 0;
+			while woken < val as usize && queue.wake_one() {
+				woken += 1;
+			}
+			process.put_futex_queue(uaddr, queue);
+			Ok(woken)
+		}
+		_ => Err(Errno::EINVAL)
+	}
+}
@@ -1,18 +1,40 @@
 use strum_macros::EnumIter;
+const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_FUTEX: usize = 98;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGRETURN: usize = 139;
 const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_GETTID: usize = 178;
+const SYSCALL_TASK_INFO: usize = 410;
+const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GET_TASKID: usize = 1001;
+const SYSCALL_WAITTID: usize = 1002;
+const SYSCALL_HART_CTL: usize = 1003;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, EnumIter)]
 #[repr(usize)]
 pub enum SyscallID {
+	Read = SYSCALL_READ,
     	Write = SYSCALL_WRITE,
     	Exit = SYSCALL_EXIT,
 	GetTaskID = SYSCALL_GET_TASKID,
 	Yield = SYSCALL_YIELD,
 	GetTime = SYSCALL_GET_TIME,
+	GetTid = SYSCALL_GETTID,
+	ThreadCreate = SYSCALL_THREAD_CREATE,
+	WaitTid = SYSCALL_WAITTID,
+	HartCtl = SYSCALL_HART_CTL,
+	Futex = SYSCALL_FUTEX,
+	Kill = SYSCALL_KILL,
+	SigAction = SYSCALL_SIGACTION,
+	SigProcMask = SYSCALL_SIGPROCMASK,
+	SigReturn = SYSCALL_SIGRETURN,
+	TaskInfo = SYSCALL_TASK_INFO,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -24,11 +46,22 @@ impl TryFrom<usize> for SyscallID {
 	type Error = SyscallError;
 	fn try_from(value: usize) -> Result<Self, Self::Error> {
 		match value {
+			SYSCALL_READ => Ok(Self::Read),
 			SYSCALL_WRITE => Ok(Self::Write),
 			SYSCALL_EXIT => Ok(Self::Exit),
 			SYSCALL_GET_TASKID => Ok(Self::GetTaskID),
 			SYSCALL_YIELD => Ok(Self::Yield),
 			SYSCALL_GET_TIME => Ok(Self::GetTime),
+			SYSCALL_GETTID => Ok(Self::GetTid),
+			SYSCALL_THREAD_CREATE => Ok(Self::ThreadCreate),
+			SYSCALL_WAITTID => Ok(Self::WaitTid),
+			SYSCALL_HART_CTL => Ok(Self::HartCtl),
+			SYSCALL_FUTEX => Ok(Self::Futex),
+			SYSCALL_KILL => Ok(Self::Kill),
+			SYSCALL_SIGACTION => Ok(Self::SigAction),
+			SYSCALL_SIGPROCMASK => Ok(Self::SigProcMask),
+			SYSCALL_SIGRETURN => Ok(Self::SigReturn),
+			SYSCALL_TASK_INFO => Ok(Self::TaskInfo),
 			_ => Err(SyscallError::InvalidSyscallID)
 		}
 	}
@@ -39,9 +72,20 @@ impl core::fmt::Display for SyscallID {
 		match self {
 			Self::Exit => write!(f, "Exit"),
 			Self::GetTaskID => write!(f, "GetTaskID"),
+			Self::Read => write!(f, "Read"),
 			Self::Write => write!(f, "Write"),
 			Self::Yield => write!(f, "Yield"),
 			Self::GetTime => write!(f, "GetTime"),
+			Self::GetTid => write!(f, "GetTid"),
+			Self::ThreadCreate => write!(f, "ThreadCreate"),
+			Self::WaitTid => write!(f, "WaitTid"),
+			Self::HartCtl => write!(f, "HartCtl"),
+			Self::Futex => write!(f, "Futex"),
+			Self::Kill => write!(f, "Kill"),
+			Self::SigAction => write!(f, "SigAction"),
+			Self::SigProcMask => write!(f, "SigProcMask"),
+			Self::SigReturn => write!(f, "SigReturn"),
+			Self::TaskInfo => write!(f, "TaskInfo"),
 		}
 	}
 }
\ No newline at end of file
@@ -0,0 +1,42 @@
+use crate::config::MAX_THREAD_NUM;
+use crate::error::{Errno, SysResult};
+use crate::global::TASK_MANAGER;
+use crate::harts::task_context_in_trap_stage;
+use crate::task::status::TaskStatus;
+
+/// Create a thread in the current app, it starts at `entry` with `arg` in a0.
+///
+/// Return the tid of the new thread, EAGAIN if the app has too many threads
+/// or ENOMEM if the kernel is out of memory.
+pub fn sys_thread_create(entry: usize, arg: usize) -> SysResult {
+	let process = task_context_in_trap_stage().process.clone();
+	TASK_MANAGER.get().unwrap().create_thread(&process, entry, arg)
+}
+
+pub fn sys_gettid() -> SysResult {
+	Ok(task_context_in_trap_stage().tid)
+}
+
+/// Wait for thread `tid` of the current app to exit, return its exit code.
+///
+/// The thread is freed then and its tid may belong to a new thread later.
+///
+/// Return ESRCH if there is no such thread, EDEADLK if it is the caller
+/// itself, or EINTR if a signal came first.
+pub fn sys_waittid(tid: usize) -> SysResult {
+	let tcb = task_context_in_trap_stage();
+	if tid == tcb.tid {
+		return Err(Errno::EDEADLK);
+	}
+	if tid >= MAX_THREAD_NUM {
+		return Err(Errno::ESRCH);
+	}
+	let manager = TASK_MANAGER.get().unwrap();
+	let target = manager.thread(&tcb.process, tid).ok_or(Errno::ESRCH)?;
+	while target.status() != TaskStatus::Exited {
+		target.exit_wait.wait_unless(|| target.status() == TaskStatus::Exited)?;
+	}
+	manager.reap_thread(&target);
+	// sign extended, a negative exit code reads like an errno to the caller
+	Ok(target.exit_code() as usize)
+}
@@ -1,59 +1,144 @@
 use core::sync::atomic::AtomicU8;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicI32;
 use core::sync::atomic::Ordering;
 use core::cell::SyncUnsafeCell;
+use core::ptr::NonNull;
+use alloc::sync::Arc;
 use log::debug;
 
-use crate::arch::common::FlowContext;
-use crate::config::TRAP_HANDLER_VADDR;
-use crate::global::TASK_MANAGER;
+use crate::arch::common::{Arch, ArchTrap, FlowContext, KernelContext};
+use crate::console::LineBuffer;
+use crate::config::{thread_slot_vaddr, FLOW_CONTEXT_VADDR, HART_CONTEXT_VADDR, PAGE_SIZE};
+use crate::global::{ELFS_INFO, TASK_MANAGER};
 use crate::mm::addr_space::AddrSpace;
+use crate::mm::stack::TaskKernelStack;
+use crate::task::process::ProcessControlBlock;
+use crate::task::signal::SignalState;
+use crate::task::task_entry;
+use crate::task::wait_queue::WaitQueue;
+use crate::trap::TrapHandler;
 use crate::task::status::ReadyLevel;
 use crate::task::status::TaskStatus;
 use crate::task::harts::AppHartInfo;
 
-//TODO: 最好做成可分配可回收的结构
 #[repr(C, align(4096))]
 pub struct TaskControlBlock {
 	// SAFETY: one flow_context will only bind to one harts
 	pub flow_context: SyncUnsafeCell<FlowContext>,
 	pub task_status: AtomicU8,
 	app_info: SyncUnsafeCell<AppHartInfo>,
-	pub addr_space: SyncUnsafeCell<AddrSpace>,
-	pub base_size: usize
+	pub process: Arc<ProcessControlBlock>,
+	/// thread id inside the process, 0 is the main thread
+	pub tid: usize,
+	pub base_size: usize,
+	/// kernel side of the task, switched by the per hart scheduler
+	kernel_context: SyncUnsafeCell<KernelContext>,
+	kstack: TaskKernelStack,
+	/// still on a hart, its kernel context may not be saved yet
+	pub on_cpu: AtomicBool,
+	exit_code: AtomicI32,
+	/// tasks waiting in waittid for this one
+	pub exit_wait: WaitQueue,
+	pub signals: SignalState,
+	/// line buffer of what the task writes to stdout
+	pub stdout: LineBuffer,
 }
 
 impl TaskControlBlock {
+	/// Build task `id`, i.e. thread `tid` of `process`, which enters user
+	/// mode at `u_entry` on the stack `u_sp` with `arg` in a0.
+	///
+	/// The flow context is linked in `link`, once the block has its final place.
+	/// Return None if there is no memory for its kernel stack.
 	pub fn new(
-		app_id: usize,
-		status: TaskStatus,
-		elf_data: Option<&[u8]>,
-	) -> Self {
-		let task_status = AtomicU8::new(u8::from(status));
-		if elf_data == None {
-			return Self {
-				flow_context: SyncUnsafeCell::new(FlowContext::ZERO),
-				task_status,
-				app_info: SyncUnsafeCell::new(AppHartInfo::ZERO),
-				addr_space: SyncUnsafeCell::new(AddrSpace::new_bare()),
-				base_size: 0
-			};
-		} else {
-			let (u_addr_space, u_sp, u_entry) = AddrSpace::from_elf(elf_data.unwrap());
-			let app_info = SyncUnsafeCell::new(AppHartInfo::new(app_id, elf_data.unwrap().as_ptr_range()));
-			let flow_context= SyncUnsafeCell::new(FlowContext::new(
-				u_sp,
-				u_entry,
-				app_id,
-				u_addr_space.token(),
-				0)); //utrah will be set in link with hart place
-			Self {
-				flow_context, //lack utraph
-				task_status,
-				app_info,
-				addr_space: SyncUnsafeCell::new(u_addr_space), //lack the map of utraph and uflow
-				base_size: u_sp
-			}
+		id: usize,
+		process: Arc<ProcessControlBlock>,
+		tid: usize,
+		u_sp: usize,
+		u_entry: usize,
+		arg: usize,
+	) -> Option<Self> {
+		let app_id = process.app_id;
+		let elf_data = ELFS_INFO.get().unwrap().elf_info(app_id);
+		let app_info = SyncUnsafeCell::new(AppHartInfo::new(app_id, elf_data.as_ptr_range()));
+		let kstack = unsafe {
+			TaskKernelStack::new(
+				NonNull::new_unchecked(thread_slot_vaddr(FLOW_CONTEXT_VADDR, tid) as *mut _),
+				NonNull::new_unchecked(thread_slot_vaddr(HART_CONTEXT_VADDR, tid) as *mut _),
+				<Arch as ArchTrap>::fast_handler_user,
+			)
+		}?;
+		// the trap handler never moves, so map it once for the whole task
+		let utraph = {
+			let _vm = process.vm_lock.lock();
+			process.addr_space().insert_utrap_handler(kstack.traph().into(), tid, false)
+		};
+		// traph not align to 4k, so we should find the offset of traph
+		let offset = kstack.traph() & (PAGE_SIZE - 1);
+		let mut flow_context = FlowContext::new(
+			u_sp,
+			u_entry,
+			id,
+			process.addr_space().token(),
+			utraph.0 + offset);
+		flow_context.a[0] = arg;
+		// the first switch to the task lands in task_entry
+		let kernel_context = SyncUnsafeCell::new(KernelContext::new(
+			task_entry as *const () as usize,
+			kstack.traph() & !0xf
+		));
+		Some(Self {
+			flow_context: SyncUnsafeCell::new(flow_context),
+			task_status: AtomicU8::new(u8::from(TaskStatus::UnInit)),
+			app_info,
+			process,
+			tid,
+			base_size: u_sp,
+			kernel_context,
+			kstack,
+			on_cpu: AtomicBool::new(false),
+			exit_code: AtomicI32::new(0),
+			exit_wait: WaitQueue::new(),
+			signals: SignalState::new(),
+			stdout: LineBuffer::new(),
+		})
+	}
+
+	/// Map the flow context to user space and let the trap handler find it.
+	pub fn link(&self) {
+		// self ref
+		{
+			let _vm = self.process.vm_lock.lock();
+			self.addr_space().insert_uflow_context(
+				(&self.flow_context as *const _ as usize).into(),
+				self.tid
+			);
 		}
+		self.trap_handler().transed_context =
+			unsafe { NonNull::new_unchecked(self.flow_context.get()) };
+	}
+
+	/// task id, the index in `TaskManager`
+	pub fn id(&self) -> usize {
+		self.flow_context().id
+	}
+
+	pub fn exit_code(&self) -> i32 {
+		self.exit_code.load(Ordering::Acquire)
+	}
+
+	pub fn set_exit_code(&self, code: i32) {
+		self.exit_code.store(code, Ordering::Release);
+	}
+
+	pub fn kernel_context_ptr(&self) -> *mut KernelContext {
+		self.kernel_context.get()
+	}
+
+	/// the trap handler on the kernel stack of this task
+	pub fn trap_handler(&self) -> &mut TrapHandler {
+		self.kstack.trap_handler()
 	}
 
 	pub fn app_info(&self) -> &mut AppHartInfo {
@@ -74,22 +159,25 @@ impl TaskControlBlock {
 	}
 
 	pub fn addr_space(&self) -> &mut AddrSpace {
-		unsafe {
-			&mut (*self.addr_space.get())
-		}
+		self.process.addr_space()
 	}
 
 	pub fn mark_suspend_low(&self) {
 		self.task_status.store(u8::from(TaskStatus::Ready(ReadyLevel::Low)), Ordering::Release);
 	}
 
-	pub fn mark_suspend_high_cas(&self, cur: TaskStatus) {
-		let _ = self.task_status.compare_exchange(
-			u8::from(cur),
-			u8::from(TaskStatus::Ready(ReadyLevel::High)),
-			Ordering::Acquire,
+	pub fn mark_blocked(&self) {
+		self.task_status.store(u8::from(TaskStatus::Blocked), Ordering::Release);
+	}
+
+	/// Blocked -> Ready, false if the task is not blocked
+	pub fn wakeup_cas(&self) -> bool {
+		self.task_status.compare_exchange(
+			u8::from(TaskStatus::Blocked),
+			u8::from(TaskStatus::Ready(ReadyLevel::Low)),
+			Ordering::AcqRel,
 			Ordering::Relaxed
-		);
+		).is_ok()
 	}
 
 	pub fn mark_exit(&self) {
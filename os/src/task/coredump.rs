@@ -0,0 +1,354 @@
+use core::fmt::{self, Display, Formatter};
+
+use alloc::format;
+use alloc::vec::Vec;
+use elf::abi::{
+	ELFCLASS64, ELFDATA2LSB, EM_RISCV, ET_CORE, EV_CURRENT, NT_PRPSINFO, NT_PRSTATUS, PF_R,
+	PF_W, PF_X, PT_LOAD, PT_NOTE,
+};
+
+use crate::config::PAGE_SIZE;
+use crate::cpu;
+use crate::mm::addr_space::{AddrSpace, MapPermission};
+use crate::mm::user::{as_bytes, Pod};
+use crate::println;
+use crate::task::block::TaskControlBlock;
+use crate::task::fault::Fault;
+
+/// `xtask core` cuts the cores out of a console log by these lines
+const BEGIN_MARKER: &str = "-----BEGIN PIANOOS CORE";
+const END_MARKER: &str = "-----END PIANOOS CORE-----";
+/// bytes of core per console line
+const LINE_BYTES: usize = 32;
+
+const EHDR_SIZE: usize = size_of::<Elf64Ehdr>();
+const PHDR_SIZE: usize = size_of::<Elf64Phdr>();
+/// "CORE\0" padded to 4 bytes
+const NOTE_NAME: &[u8; 8] = b"CORE\0\0\0\0";
+const NOTE_NAME_SIZE: u32 = 5;
+
+#[repr(C)]
+struct Elf64Ehdr {
+	e_ident: [u8; 16],
+	e_type: u16,
+	e_machine: u16,
+	e_version: u32,
+	e_entry: u64,
+	e_phoff: u64,
+	e_shoff: u64,
+	e_flags: u32,
+	e_ehsize: u16,
+	e_phentsize: u16,
+	e_phnum: u16,
+	e_shentsize: u16,
+	e_shnum: u16,
+	e_shstrndx: u16,
+}
+
+#[repr(C)]
+struct Elf64Phdr {
+	p_type: u32,
+	p_flags: u32,
+	p_offset: u64,
+	p_vaddr: u64,
+	p_paddr: u64,
+	p_filesz: u64,
+	p_memsz: u64,
+	p_align: u64,
+}
+
+/// `struct elf_prstatus` of riscv64 linux, which gdb reads the registers from
+#[repr(C)]
+struct PrStatus {
+	si_signo: i32,
+	si_code: i32,
+	si_errno: i32,
+	pr_cursig: i16,
+	_pad0: u16,
+	pr_sigpend: u64,
+	pr_sighold: u64,
+	pr_pid: i32,
+	pr_ppid: i32,
+	pr_pgrp: i32,
+	pr_sid: i32,
+	/// (sec, usec)
+	pr_utime: [u64; 2],
+	pr_stime: [u64; 2],
+	pr_cutime: [u64; 2],
+	pr_cstime: [u64; 2],
+	/// pc, then x1 to x31
+	pr_reg: [u64; 32],
+	pr_fpvalid: i32,
+	_pad1: u32,
+}
+
+/// `struct elf_prpsinfo` of riscv64 linux
+#[repr(C)]
+struct PrPsInfo {
+	pr_state: i8,
+	pr_sname: u8,
+	pr_zomb: i8,
+	pr_nice: i8,
+	_pad0: u32,
+	pr_flag: u64,
+	pr_uid: u32,
+	pr_gid: u32,
+	pr_pid: i32,
+	pr_ppid: i32,
+	pr_pgrp: i32,
+	pr_sid: i32,
+	pr_fname: [u8; 16],
+	pr_psargs: [u8; 80],
+}
+
+/// `union __riscv_fp_state` with the d extension
+#[repr(C)]
+struct FpRegs {
+	f: [u64; 32],
+	fcsr: u32,
+	_pad0: u32,
+}
+
+// SAFETY: integers only, the padding of the C structs is explicit
+unsafe impl Pod for Elf64Ehdr {}
+unsafe impl Pod for Elf64Phdr {}
+unsafe impl Pod for PrStatus {}
+unsafe impl Pod for PrPsInfo {}
+unsafe impl Pod for FpRegs {}
+
+/// Write an ELF core of `tcb`, which is killed by `fault`.
+///
+/// It holds the registers of the faulting thread and every user area. There
+/// is no filesystem to put it in yet, so it goes hex encoded to the console,
+/// `cargo xtask core <log>` turns it back into a file for gdb. That floods
+/// the log, so it is only built with the `coredump` feature.
+pub fn dump(tcb: &TaskControlBlock, fault: &Fault) {
+	let notes = notes(tcb, fault);
+
+	// other threads may still change the address space
+	let _vm = tcb.process.vm_lock.lock();
+	let addr_space = tcb.addr_space();
+	let areas: Vec<(usize, usize, MapPermission)> = addr_space.user_areas().collect();
+
+	let phnum = 1 + areas.len();
+	let notes_offset = EHDR_SIZE + phnum * PHDR_SIZE;
+	let data_offset = (notes_offset + notes.len()).next_multiple_of(PAGE_SIZE);
+	let size = data_offset + areas.iter().map(|(start, end, _)| end - start).sum::<usize>();
+
+	let mut e_ident = [0u8; 16];
+	e_ident[..4].copy_from_slice(b"\x7fELF");
+	e_ident[4] = ELFCLASS64 as u8;
+	e_ident[5] = ELFDATA2LSB as u8;
+	e_ident[6] = EV_CURRENT as u8;
+	let ehdr = Elf64Ehdr {
+		e_ident,
+		e_type: ET_CORE as u16,
+		e_machine: EM_RISCV as u16,
+		e_version: EV_CURRENT as u32,
+		e_entry: 0,
+		e_phoff: EHDR_SIZE as u64,
+		e_shoff: 0,
+		e_flags: 0,
+		e_ehsize: EHDR_SIZE as u16,
+		e_phentsize: PHDR_SIZE as u16,
+		e_phnum: phnum as u16,
+		e_shentsize: 0,
+		e_shnum: 0,
+		e_shstrndx: 0,
+	};
+
+	println!(
+		"{} app {} task {} size {}-----",
+		BEGIN_MARKER, tcb.process.app_id, tcb.id(), size
+	);
+	let mut out = HexWriter::new();
+	out.write(as_bytes(&ehdr));
+	out.write(as_bytes(&Elf64Phdr {
+		p_type: PT_NOTE as u32,
+		p_flags: 0,
+		p_offset: notes_offset as u64,
+		p_vaddr: 0,
+		p_paddr: 0,
+		p_filesz: notes.len() as u64,
+		p_memsz: 0,
+		p_align: 4,
+	}));
+	let mut offset = data_offset;
+	for &(start, end, perm) in areas.iter() {
+		out.write(as_bytes(&Elf64Phdr {
+			p_type: PT_LOAD as u32,
+			p_flags: elf_flags(perm),
+			p_offset: offset as u64,
+			p_vaddr: start as u64,
+			p_paddr: 0,
+			p_filesz: (end - start) as u64,
+			p_memsz: (end - start) as u64,
+			p_align: PAGE_SIZE as u64,
+		}));
+		offset += end - start;
+	}
+	out.write(&notes);
+	out.zeros(data_offset - notes_offset - notes.len());
+	for &(start, end, _) in areas.iter() {
+		write_area(&mut out, addr_space, start, end);
+	}
+	out.flush();
+	println!("{}", END_MARKER);
+}
+
+/// prstatus and prpsinfo, and the fp registers if every hart has F and D
+fn notes(tcb: &TaskControlBlock, fault: &Fault) -> Vec<u8> {
+	let ctx = tcb.flow_context();
+	let r = ctx.user_regs().map(|reg| reg as u64);
+	let app_info = tcb.app_info();
+	let mut notes = Vec::new();
+
+	let prstatus = PrStatus {
+		si_signo: fault.signo as i32,
+		si_code: 0,
+		si_errno: 0,
+		pr_cursig: fault.signo as i16,
+		_pad0: 0,
+		pr_sigpend: 0,
+		pr_sighold: tcb.signals.blocked() as u64,
+		pr_pid: tcb.id() as i32,
+		pr_ppid: 0,
+		pr_pgrp: tcb.process.app_id as i32,
+		pr_sid: 0,
+		pr_utime: timeval(app_info.user_time.elapsed()),
+		pr_stime: timeval(app_info.kernel_time.elapsed()),
+		pr_cutime: [0; 2],
+		pr_cstime: [0; 2],
+		// user_regs is ra, t0-t6, a0-a7, s0-s11, gp, tp, sp, pc
+		pr_reg: [
+			r[31], r[0], r[30], r[28], r[29], r[1], r[2], r[3],
+			r[16], r[17], r[8], r[9], r[10], r[11], r[12], r[13],
+			r[14], r[15], r[18], r[19], r[20], r[21], r[22], r[23],
+			r[24], r[25], r[26], r[27], r[4], r[5], r[6], r[7],
+		],
+		pr_fpvalid: cpu::has_fp() as i32,
+		_pad1: 0,
+	};
+	push_note(&mut notes, NT_PRSTATUS as u32, as_bytes(&prstatus));
+
+	let name = format!("app{}", tcb.process.app_id);
+	let len = name.len().min(15);
+	let mut pr_fname = [0u8; 16];
+	pr_fname[..len].copy_from_slice(&name.as_bytes()[..len]);
+	let prpsinfo = PrPsInfo {
+		pr_state: 0,
+		pr_sname: b'R',
+		pr_zomb: 0,
+		pr_nice: 0,
+		_pad0: 0,
+		pr_flag: 0,
+		pr_uid: 0,
+		pr_gid: 0,
+		pr_pid: tcb.process.app_id as i32,
+		pr_ppid: 0,
+		pr_pgrp: tcb.process.app_id as i32,
+		pr_sid: 0,
+		pr_fname,
+		pr_psargs: [0; 80],
+	};
+	push_note(&mut notes, NT_PRPSINFO as u32, as_bytes(&prpsinfo));
+
+	if cpu::has_fp() {
+		// the FP registers may be newer than the context
+		unsafe { ctx.flush_fp() };
+		let fpregs = FpRegs {
+			f: ctx.f.map(|reg| reg as u64),
+			fcsr: ctx.fcsr as u32,
+			_pad0: 0,
+		};
+		push_note(&mut notes, elf::abi::NT_FPREGSET as u32, as_bytes(&fpregs));
+	}
+
+	notes
+}
+
+fn push_note(notes: &mut Vec<u8>, n_type: u32, desc: &[u8]) {
+	notes.extend_from_slice(&NOTE_NAME_SIZE.to_le_bytes());
+	notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+	notes.extend_from_slice(&n_type.to_le_bytes());
+	notes.extend_from_slice(NOTE_NAME);
+	notes.extend_from_slice(desc);
+	notes.resize(notes.len().next_multiple_of(4), 0);
+}
+
+/// ns to (sec, usec)
+fn timeval(ns: u64) -> [u64; 2] {
+	[ns / 1_000_000_000, ns % 1_000_000_000 / 1_000]
+}
+
+fn elf_flags(perm: MapPermission) -> u32 {
+	let mut flags = 0;
+	if perm.contains(MapPermission::R) {
+		flags |= PF_R;
+	}
+	if perm.contains(MapPermission::W) {
+		flags |= PF_W;
+	}
+	if perm.contains(MapPermission::X) {
+		flags |= PF_X;
+	}
+	flags as u32
+}
+
+/// The pages of `start..end`, a page which is not mapped is written as zeros.
+fn write_area(out: &mut HexWriter, addr_space: &AddrSpace, start: usize, end: usize) {
+	for page in (start..end).step_by(PAGE_SIZE) {
+		match addr_space.translated_byte_buffer(page as *const u8, PAGE_SIZE) {
+			Some(bufs) => bufs.iter().for_each(|buf| out.write(buf)),
+			None => out.zeros(PAGE_SIZE),
+		}
+	}
+}
+
+/// Writes bytes to the console as lines of hex.
+struct HexWriter {
+	line: [u8; LINE_BYTES],
+	len: usize,
+}
+
+impl HexWriter {
+	fn new() -> Self {
+		Self { line: [0; LINE_BYTES], len: 0 }
+	}
+
+	fn write(&mut self, mut bytes: &[u8]) {
+		while !bytes.is_empty() {
+			let count = bytes.len().min(LINE_BYTES - self.len);
+			self.line[self.len..self.len + count].copy_from_slice(&bytes[..count]);
+			self.len += count;
+			bytes = &bytes[count..];
+			if self.len == LINE_BYTES {
+				self.flush();
+			}
+		}
+	}
+
+	fn zeros(&mut self, mut count: usize) {
+		const ZEROS: [u8; LINE_BYTES] = [0; LINE_BYTES];
+		while count > 0 {
+			let n = count.min(LINE_BYTES);
+			self.write(&ZEROS[..n]);
+			count -= n;
+		}
+	}
+
+	fn flush(&mut self) {
+		if self.len > 0 {
+			println!("{}", Hex(&self.line[..self.len]));
+			self.len = 0;
+		}
+	}
+}
+
+struct Hex<'a>(&'a [u8]);
+
+impl Display for Hex<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+	}
+}
@@ -0,0 +1,147 @@
+use core::fmt::{self, Display, Formatter};
+
+use elf::abi::{ET_DYN, STT_FUNC};
+use elf::endian::AnyEndian;
+use elf::string_table::StringTable;
+use elf::symbol::SymbolTable;
+use elf::ElfBytes;
+use log::warn;
+use riscv::interrupt::supervisor::Exception;
+
+use crate::config::APP_VIRT_ADDR;
+use crate::global::ELFS_INFO;
+use crate::mm::addr_space::AddrSpace;
+use crate::mm::user::UserPtr;
+use crate::task::block::TaskControlBlock;
+
+/// frames printed at most by the user backtrace
+const MAX_USER_FRAMES: usize = 32;
+
+/// names of `FlowContext::user_regs`
+const REG_NAMES: [&str; 32] = [
+	"ra", "t0", "t1", "t2", "t3", "t4", "t5", "t6",
+	"a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7",
+	"s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7",
+	"s8", "s9", "s10", "s11", "gp", "tp", "sp", "pc",
+];
+const S0: usize = 16;
+
+/// The trap which raised a fault signal, kept until the signal is delivered.
+#[derive(Clone, Copy)]
+pub struct Fault {
+	pub signo: usize,
+	pub cause: Exception,
+	/// stval
+	pub addr: usize,
+	/// sepc
+	pub pc: usize,
+}
+
+/// Print why `tcb` is killed by `fault`: the trap, all user registers, the
+/// areas of pc and addr and the user call chain.
+///
+/// The flow context must still hold the registers of the faulting instruction.
+pub fn report(tcb: &TaskControlBlock, fault: &Fault) {
+	let process = &tcb.process;
+	warn!(
+		"Task {} (app {}, tid {}) killed by signal {}: {:?} at pc {:#x}, addr {:#x}",
+		tcb.id(), process.app_id, tcb.tid, fault.signo, fault.cause, fault.pc, fault.addr
+	);
+
+	let regs = tcb.flow_context().user_regs();
+	for (names, vals) in REG_NAMES.chunks(4).zip(regs.chunks(4)) {
+		warn!(
+			"  {:>3}: {:#018x}  {:>3}: {:#018x}  {:>3}: {:#018x}  {:>3}: {:#018x}",
+			names[0], vals[0], names[1], vals[1], names[2], vals[2], names[3], vals[3]
+		);
+	}
+
+	// other threads may still change the address space
+	let _vm = process.vm_lock.lock();
+	let addr_space = tcb.addr_space();
+	warn!("  pc   in {}", Area(addr_space, fault.pc));
+	warn!("  addr in {}", Area(addr_space, fault.addr));
+
+	let symbols = UserSymbols::new(process.app_id);
+	warn!("User backtrace:");
+	warn!("  #0  {:#018x} {}", fault.pc, Symbolized(symbols.as_ref(), fault.pc));
+	let mut fp = regs[S0];
+	for depth in 1..MAX_USER_FRAMES {
+		let Some((prev_fp, ra)) = read_frame(addr_space, fp) else {
+			break;
+		};
+		if ra == 0 {
+			break;
+		}
+		warn!("  #{:<2} {:#018x} {}", depth, ra, Symbolized(symbols.as_ref(), ra));
+		// the stack grows down, so callers have higher frames
+		if prev_fp <= fp {
+			break;
+		}
+		fp = prev_fp;
+	}
+}
+
+/// The saved (fp, ra) of the frame at `fp`, which sit just below it.
+fn read_frame(addr_space: &AddrSpace, fp: usize) -> Option<(usize, usize)> {
+	if fp % size_of::<usize>() != 0 {
+		return None;
+	}
+	let va = fp.checked_sub(2 * size_of::<usize>())?;
+	let [prev_fp, ra] = UserPtr::<[usize; 2]>::new(va).read(addr_space).ok()?;
+	Some((prev_fp, ra))
+}
+
+struct Area<'a>(&'a AddrSpace, usize);
+
+impl Display for Area<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self.0.find_vma(self.1) {
+			Some(vma) => write!(f, "{}", vma),
+			None => write!(f, "no area"),
+		}
+	}
+}
+
+/// Function symbols of an app ELF, if it was not stripped.
+struct UserSymbols<'a> {
+	symtab: SymbolTable<'a, AnyEndian>,
+	strtab: StringTable<'a>,
+	/// where the ELF is loaded
+	bias: usize,
+}
+
+impl UserSymbols<'static> {
+	fn new(app_id: usize) -> Option<Self> {
+		let elf_data = ELFS_INFO.get().unwrap().elf_info(app_id);
+		let file = ElfBytes::<AnyEndian>::minimal_parse(elf_data).ok()?;
+		let bias = if file.ehdr.e_type == ET_DYN {APP_VIRT_ADDR} else {0};
+		let (symtab, strtab) = file.symbol_table().ok()??;
+		Some(UserSymbols { symtab, strtab, bias })
+	}
+}
+
+impl<'a> UserSymbols<'a> {
+	/// name of the function containing `addr` and the offset into it
+	fn lookup(&self, addr: usize) -> Option<(&'a str, usize)> {
+		let addr = addr.checked_sub(self.bias)? as u64;
+		let sym = self.symtab.iter().find(|sym| {
+			sym.st_symtype() == STT_FUNC
+				&& sym.st_value <= addr
+				&& addr < sym.st_value + sym.st_size
+		})?;
+		let name = self.strtab.get(sym.st_name as usize).ok()?;
+		Some((name, (addr - sym.st_value) as usize))
+	}
+}
+
+struct Symbolized<'a>(Option<&'a UserSymbols<'a>>, usize);
+
+impl Display for Symbolized<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self.0.and_then(|symbols| symbols.lookup(self.1)) {
+			Some((name, offset)) => write!(f, "<{:#}+{:#x}>", rustc_demangle::demangle(name), offset),
+			None => write!(f, "<unknown>"),
+		}
+	}
+}
@@ -11,6 +11,8 @@ pub struct AppHartInfo {
 	pub app_range: Range<*const u8>,
 	pub kernel_time: StopWatch,
 	pub user_time: StopWatch,
+	/// when the task first ran, in ms
+	pub start_time: u64,
 }
 
 impl AppHartInfo {
@@ -20,6 +22,7 @@ impl AppHartInfo {
 		app_range: 0 as *const u8..0 as *const u8,
 		kernel_time: StopWatch::new(),
 		user_time: StopWatch::new(),
+		start_time: 0,
 	};
 
 	pub fn new(app_id: usize, app_range: Range<*const u8>) -> Self {
@@ -33,6 +36,7 @@ impl AppHartInfo {
 			app_range,
 			kernel_time: StopWatch::new(),
 			user_time: StopWatch::new(),
+			start_time: 0,
 		}
 	}
 
@@ -83,6 +87,15 @@ impl StopWatch {
 		self.total_time
 	}
 
+	/// total time including the running part, in ns
+	pub fn elapsed(&self) -> u64 {
+		if self.timing {
+			self.total_time + (ARCH.time_ns() - self.start_time)
+		} else {
+			self.total_time
+		}
+	}
+
 	pub fn start(&mut self) {
 		assert_eq!(self.timing, false, "Stop Watch already start");
 		self.timing = true;
@@ -0,0 +1,141 @@
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use log::{error, info, warn};
+use spin::Mutex;
+
+use crate::arch::common::{entry, ArchHarts};
+use crate::cpu::{self, PerCpu};
+use crate::error::Errno;
+use crate::global::ARCH;
+use crate::harts::{online_harts, set_hart_online, HartState};
+use crate::ipi;
+use crate::mm::stack::alloc_hart_stack;
+use crate::task::preempt_point;
+
+/// asked to go offline, the idle flow of the hart stops it
+static STOP_REQUESTED: PerCpu<AtomicBool> = PerCpu::new(|| AtomicBool::new(false));
+/// orders the checks of `offline`, so the last online hart is never taken
+static HOTPLUG_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HotplugError {
+	/// no such hart, or the kernel does not run on it
+	Invalid,
+	/// the last online hart, or the hart is changing its state
+	Busy,
+	/// the SBI refused
+	Failed,
+}
+
+impl From<HotplugError> for Errno {
+	fn from(value: HotplugError) -> Self {
+		match value {
+			HotplugError::Invalid => Self::EINVAL,
+			HotplugError::Busy => Self::EBUSY,
+			HotplugError::Failed => Self::EIO,
+		}
+	}
+}
+
+fn check_hartid(hartid: usize) -> Result<(), HotplugError> {
+	if cpu::cpu_id(hartid).is_some() {
+		Ok(())
+	} else {
+		Err(HotplugError::Invalid)
+	}
+}
+
+fn is_online(hartid: usize) -> bool {
+	online_harts().any(|online| online == hartid)
+}
+
+fn start(hartid: usize) -> bool {
+	// two online calls must not both give the hart a stack
+	let stack_top = {
+		let _guard = HOTPLUG_LOCK.lock();
+		alloc_hart_stack(hartid)
+	};
+	let Some(stack_top) = stack_top else {
+		warn!("no frames for the kernel stack of hart {}", hartid);
+		return false;
+	};
+	ARCH.hart_start(hartid, entry::hart_start as *const () as usize, stack_top)
+}
+
+/// Start every other hart of the kernel which the SBI reports stopped, at boot.
+pub fn start_harts() {
+	for hartid in cpu::hart_ids() {
+		match ARCH.hart_status(hartid) {
+			HartState::Stoped => {
+				if !start(hartid) {
+					warn!("hart {} can not be started", hartid);
+				}
+			}
+			// e.g. the boot hart
+			state => info!("hart {} is not started: {}", hartid, state),
+		}
+	}
+}
+
+pub fn hart_status(hartid: usize) -> Result<HartState, HotplugError> {
+	check_hartid(hartid)?;
+	Ok(ARCH.hart_status(hartid))
+}
+
+/// Take `hartid` offline and return once it stopped.
+///
+/// The flow running there leaves it at its next trap or `preempt_point` and
+/// goes on on another hart, the caller as well if it runs there.
+pub fn offline(hartid: usize) -> Result<(), HotplugError> {
+	check_hartid(hartid)?;
+	{
+		let _guard = HOTPLUG_LOCK.lock();
+		if !is_online(hartid) {
+			return match ARCH.hart_status(hartid) {
+				HartState::Stoped => Ok(()),
+				_ => Err(HotplugError::Busy),
+			};
+		}
+		let staying = online_harts()
+			.filter(|&online| !STOP_REQUESTED[online].load(Ordering::Acquire))
+			.count();
+		if staying <= 1 || STOP_REQUESTED[hartid].swap(true, Ordering::AcqRel) {
+			return Err(HotplugError::Busy);
+		}
+	}
+	// also wakes it up if it is idle
+	ipi::resched(hartid);
+	while ARCH.hart_status(hartid) != HartState::Stoped {
+		preempt_point();
+		spin_loop();
+	}
+	Ok(())
+}
+
+/// Start `hartid` again after `offline`, it runs tasks soon after.
+pub fn online(hartid: usize) -> Result<(), HotplugError> {
+	check_hartid(hartid)?;
+	match ARCH.hart_status(hartid) {
+		HartState::Stoped if start(hartid) => Ok(()),
+		HartState::Stoped => Err(HotplugError::Failed),
+		HartState::Started if is_online(hartid) => Ok(()),
+		_ => Err(HotplugError::Busy),
+	}
+}
+
+/// Stop this hart if `offline` asked, called by the idle flow, which holds no task.
+///
+/// It only returns if the SBI refused, the hart stays online then.
+pub fn poll_offline(hartid: usize) {
+	if !STOP_REQUESTED[hartid].load(Ordering::Acquire) {
+		return;
+	}
+	ipi::close_mailbox(hartid);
+	info!("hart {} goes offline", hartid);
+	STOP_REQUESTED[hartid].store(false, Ordering::Release);
+	// a later hart_start enters hart_main again from the top of its kernel stack
+	ARCH.hart_stop();
+	error!("hart {} can not be stopped", hartid);
+	set_hart_online(hartid);
+}
@@ -0,0 +1,112 @@
+use core::cell::SyncUnsafeCell;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use alloc::boxed::Box;
+use spin::Mutex;
+
+use crate::arch::common::KernelContext;
+use crate::global::TASK_MANAGER;
+use crate::mm::stack::KThreadStack;
+use crate::task::processor::current_processor;
+use crate::task::status::{ReadyLevel, TaskStatus};
+
+pub type KThreadEntry = Box<dyn FnOnce() + Send + 'static>;
+
+/// A control flow which only lives in the kernel.
+///
+/// It owns a stack and a `KernelContext`, runs in S-mode with the kernel
+/// address space and is switched with `ArchSwitch`, never through the
+/// trampoline. Used for deferred work like flushing, page zeroing or log
+/// draining, which should not run inside the trap of some user task.
+pub struct KernelThread {
+	pub id: usize,
+	task_status: AtomicU8,
+	context: SyncUnsafeCell<KernelContext>,
+	entry: Mutex<Option<KThreadEntry>>,
+	_stack: KThreadStack,
+}
+
+impl KernelThread {
+	pub fn new(id: usize, entry: KThreadEntry) -> Self {
+		let stack = KThreadStack::new();
+		Self {
+			id,
+			task_status: AtomicU8::new(u8::from(TaskStatus::UnInit)),
+			context: SyncUnsafeCell::new(KernelContext::new(
+				kthread_entry as *const () as usize,
+				stack.top()
+			)),
+			entry: Mutex::new(Some(entry)),
+			_stack: stack,
+		}
+	}
+
+	pub fn status(&self) -> TaskStatus {
+		TaskStatus::try_from(self.task_status.load(Ordering::SeqCst))
+			.unwrap()
+	}
+
+	pub fn context_ptr(&self) -> *mut KernelContext {
+		self.context.get()
+	}
+
+	pub fn mark_ready(&self) {
+		self.task_status.store(u8::from(TaskStatus::Ready(ReadyLevel::Low)), Ordering::Release);
+	}
+
+	pub fn mark_runing(&self) {
+		self.task_status.store(u8::from(TaskStatus::Running), Ordering::Release);
+	}
+
+	pub fn mark_exit(&self) {
+		self.task_status.store(u8::from(TaskStatus::Exited), Ordering::Release);
+	}
+}
+
+/// The first code every kernel thread runs.
+extern "C" fn kthread_entry() -> ! {
+	let entry = current_processor()
+		.current_kthread()
+		.expect("kernel thread entry without a kernel thread")
+		.entry
+		.lock()
+		.take()
+		.expect("kernel thread has been started before");
+	entry();
+	kthread_exit();
+}
+
+/// Spawn a kernel thread running `f`, return its id.
+///
+/// The thread is picked up by `TaskManager` the next time a hart schedules.
+pub fn kthread_spawn<F>(f: F) -> usize
+where
+	F: FnOnce() + Send + 'static
+{
+	TASK_MANAGER.get().unwrap().spawn_kthread(Box::new(f))
+}
+
+/// Give the hart back, the current kernel thread will be run again later.
+pub fn kthread_yield() {
+	let processor = current_processor();
+	let context = {
+		let kthread = processor.current_kthread()
+			.expect("kthread_yield outside of a kernel thread");
+		kthread.mark_ready();
+		kthread.context_ptr()
+	};
+	processor.switch_to_sched(context);
+}
+
+/// End the current kernel thread, its stack is freed by the scheduler.
+pub fn kthread_exit() -> ! {
+	let processor = current_processor();
+	let context = {
+		let kthread = processor.current_kthread()
+			.expect("kthread_exit outside of a kernel thread");
+		kthread.mark_exit();
+		kthread.context_ptr()
+	};
+	processor.switch_to_sched(context);
+	unreachable!("exited kernel thread is scheduled again");
+}
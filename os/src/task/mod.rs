@@ -1,29 +1,58 @@
-use core::cmp::Reverse;
-use core::ptr::NonNull;
 use core::sync::atomic::Ordering;
-use core::intrinsics::forget;
-use core::array;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::fence;
 
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use log::info;
 use spin::mutex::Mutex;
+use spin::{RwLock, RwLockReadGuard};
 
 use crate::arch::loongarch64::trap;
-use crate::global::{ARCH, ELFS_INFO, KERNEL_ADDRSPACE, KERNEL_STACK, TASK_MANAGER};
-use crate::arch::common::{Arch, ArchPower, ArchTime, ArchTrap, FlowContext};
-use crate::config::{FLOW_CONTEXT_VADDR, HART_CONTEXT_VADDR, MAX_APP_NUM, PAGE_SIZE, PAGE_SIZE_BITS, TICK_MS, TRAMPOLINE_VADDR, TRAP_HANDLER_VADDR};
-use crate::harts::{HartContext, task_context_in_trap_stage, trap_handler_in_trap_stage};
-use crate::mm::stack::KernelStack;
+use crate::global::{ARCH, ELFS_INFO, KERNEL_ADDRSPACE, PROCESSORS, TASK_MANAGER};
+use crate::arch::common::{Arch, ArchHarts, ArchPower, ArchTime, ArchTrap};
+use crate::config::{thread_slot_vaddr, HART_CONTEXT_VADDR, MAX_THREAD_NUM, TICK_MS, TRAMPOLINE_VADDR, USER_STACK_SIZE};
+use crate::crash;
+use crate::driver::irq;
+use crate::error::{Errno, SysResult};
+use crate::harts::{hart_id_in_trap_stage, set_hart_online, set_trap_handler, task_context_in_trap_stage};
+use crate::ipi;
+use crate::mm::addr_space::AddrSpace;
+use crate::mm::stack::hart_stack;
+use crate::sync::{IrqMutex, IrqOff};
 use crate::task::block::TaskControlBlock;
-use crate::task::status::{ReadyLevel, TaskStatus};
+use crate::task::kthread::{KThreadEntry, KernelThread, kthread_yield};
+use crate::task::process::ProcessControlBlock;
+use crate::task::processor::{Processor, current_processor};
+use crate::task::status::TaskStatus;
+use crate::trap::TrapHandler;
 
 pub mod harts;
 pub mod block;
 pub mod status;
+pub mod kthread;
+pub mod processor;
+pub mod process;
+pub mod wait_queue;
+pub mod signal;
+pub mod fault;
+#[cfg(feature = "coredump")]
+pub mod coredump;
+pub mod hotplug;
 
 pub struct TaskManager {
 	pub num_app: usize,
 	finished: Mutex<bool>,
-	tasks: [TaskControlBlock; MAX_APP_NUM]
+	/// every task, indexed by task id, None once a thread is reaped and
+	/// until its id is used again
+	tasks: RwLock<Vec<Option<Arc<TaskControlBlock>>>>,
+	/// ready tasks in FIFO order, also guards the handoff of `on_cpu`, irq
+	/// handlers take it to wake tasks
+	ready: IrqMutex<VecDeque<usize>>,
+	/// ready kernel threads, the running ones are held by their `Processor`
+	kthreads: Mutex<VecDeque<Arc<KernelThread>>>,
+	next_kthread_id: AtomicUsize,
 }
 
 unsafe impl Send for TaskManager {}
@@ -32,215 +61,373 @@ unsafe impl Sync for TaskManager {}
 impl TaskManager {
 	pub fn new() -> Self {
 		let num_app = ELFS_INFO.get().unwrap().num_app;
-		let tasks: [TaskControlBlock; MAX_APP_NUM] =
-			array::from_fn(|i| {
-				if i < num_app {
-					TaskControlBlock::new(
-						i,
-						TaskStatus::UnInit,
-						Some(ELFS_INFO.get().unwrap().elf_info(i))
-					)
-				} else {
-					TaskControlBlock::new(
-						i,
-						TaskStatus::Exited,
-						None
-					)
-				}
-			});
+		// the main thread of app i is task i
+		let tasks: Vec<Option<Arc<TaskControlBlock>>> = (0..num_app).map(|app_id| {
+			let (u_addr_space, u_sp, u_entry) =
+				AddrSpace::from_elf(ELFS_INFO.get().unwrap().elf_info(app_id));
+			let process = Arc::new(ProcessControlBlock::new(
+				app_id,
+				u_addr_space,
+				u_sp - USER_STACK_SIZE
+			));
+			let tid = process.alloc_tid(app_id, MAX_THREAD_NUM).unwrap();
+			let tcb = TaskControlBlock::new(app_id, process, tid, u_sp, u_entry, 0)
+				.expect("no memory for the kernel stack of an app");
+			let tcb = Arc::new(tcb);
+			tcb.link();
+			Some(tcb)
+		}).collect();
 		TaskManager {
 			num_app: num_app,
 			finished: Mutex::new(false),
-			tasks
+			tasks: RwLock::new(tasks),
+			ready: IrqMutex::new((0..num_app).collect()),
+			kthreads: Mutex::new(VecDeque::new()),
+			next_kthread_id: AtomicUsize::new(0),
 		}
 	}
 
-	pub fn map_flow_context(&self) {
-		self.tasks.iter().for_each(|tcb| {
-			// self ref
-			tcb.addr_space().insert_uflow_context(
-				(&tcb.flow_context as *const _ as usize).into()
-			);
-		});
+	/// Create a thread of `process` which enters `entry` with `arg`, return its tid.
+	///
+	/// Return EAGAIN if the app has too many threads, or ENOMEM if there is
+	/// no memory for the kernel stack.
+	pub fn create_thread(&self, process: &Arc<ProcessControlBlock>, entry: usize, arg: usize) -> SysResult {
+		let tcb = {
+			// an irq handler on this hart may look up a task to wake it
+			let _irq = IrqOff::new();
+			let mut tasks = self.tasks.write();
+			// the id of a reaped thread is free again
+			let task_id = tasks.iter().position(Option::is_none).unwrap_or(tasks.len());
+			let tid = process.alloc_tid(task_id, MAX_THREAD_NUM).ok_or(Errno::EAGAIN)?;
+			let u_sp = AddrSpace::thread_stack(process.ustack_base(), tid).end;
+			let Some(tcb) = TaskControlBlock::new(task_id, process.clone(), tid, u_sp, entry, arg) else {
+				process.free_tid(tid, task_id);
+				return Err(Errno::ENOMEM);
+			};
+			{
+				let _vm = process.vm_lock.lock();
+				process.addr_space().insert_thread_stack(process.ustack_base(), tid);
+			}
+			let tcb = Arc::new(tcb);
+			tcb.link();
+			if task_id == tasks.len() {
+				tasks.push(Some(tcb.clone()));
+			} else {
+				tasks[task_id] = Some(tcb.clone());
+			}
+			tcb
+		};
+		info!("Create thread {} of app {} as task {}", tcb.tid, process.app_id, tcb.id());
+		// its stack and slots are new in a page table which may be live
+		ipi::flush_tlb(&self.harts_running(process, tcb.id()), None);
+		self.ready.lock().push_back(tcb.id());
+		ipi::wake_idle_hart();
+		Ok(tcb.tid)
+	}
+
+	/// Free thread `tcb` once `waittid` saw it exit, its tid and task id can
+	/// be used again.
+	///
+	/// The kernel stack and the block go with the last reference, which
+	/// `dispatch` holds until the thread left its hart. The main thread
+	/// stays, it stands for the app. Threads of an ending app stay as well,
+	/// `exit_process` may have left them in wait queues.
+	pub fn reap_thread(&self, tcb: &TaskControlBlock) {
+		assert!(tcb.status() == TaskStatus::Exited);
+		if tcb.tid == 0 || tcb.process.is_exiting() {
+			return;
+		}
+		{
+			// a new thread which gets the tid maps it only after this
+			let _vm = tcb.process.vm_lock.lock();
+			if !tcb.process.free_tid(tcb.tid, tcb.id()) {
+				return;
+			}
+			tcb.addr_space().remove_thread(tcb.process.ustack_base(), tcb.tid);
+		}
+		ipi::flush_tlb(&self.harts_running(&tcb.process, tcb.id()), None);
+		let _irq = IrqOff::new();
+		self.tasks.write()[tcb.id()] = None;
 	}
 
 	pub fn app_size(&self, app_id: usize) -> usize {
-		self.tasks[app_id].base_size
+		self.task(app_id).base_size
 	}
 
 	pub fn check_end(&self) {
 		let all_finished =
-			self.tasks.iter().take(self.num_app)
+			self.tasks.read().iter().flatten()
 				.all(|f| f.status() == TaskStatus::Exited);
 		if all_finished {
 			let mut lock = self.finished.lock();
 			info!("All applications completed! Kennel shutdown");
 			*lock = true;
+			crate::console::flush();
 			ARCH.shutdown(false);
 		}
 	}
 
-	/// this will find next ready app and set running(use CAS)
-	/// return (prev_status, app_id)
-	/// TODO: use bitmap or queue(linux kernel does) to opt it
-	fn find_next_ready_and_set_run(&self) -> (TaskStatus, usize) {
-		loop {
-			let best_candidate = self.tasks.iter().enumerate()
-				.filter_map(|(id, task)| {
-					let raw = task.task_status.load(Ordering::Relaxed);
-					let status = TaskStatus::try_from(raw).ok()?;
-					let prio = status.get_priority()?;
-					Some((id, raw, status, prio))
-				}).max_by_key(|(id, _, _, prio)| (*prio, Reverse(*id)));
-
-			if let Some((id, raw, status, _)) = best_candidate {
-				if self.tasks[id].task_status.compare_exchange(
-					raw,
-					u8::from(TaskStatus::Running),
-					Ordering::Acquire,
-					Ordering::Relaxed).is_ok() {
-						return (status, id);
-					}
-				else {
-				    self.check_end();
-				}
+	pub fn spawn_kthread(&self, entry: KThreadEntry) -> usize {
+		let id = self.next_kthread_id.fetch_add(1, Ordering::Relaxed);
+		self.kthreads.lock().push_back(Arc::new(KernelThread::new(id, entry)));
+		ipi::wake_idle_hart();
+		id
+	}
+
+	/// Run every kernel thread which is ready now once, on the current hart.
+	///
+	/// Threads which yield are queued again after they have left the hart,
+	/// so another hart can never pick a thread whose context is not saved yet.
+	pub fn run_kthreads(&self) {
+		let processor = current_processor();
+		let round = self.kthreads.lock().len();
+		for _ in 0..round {
+			let Some(kthread) = self.kthreads.lock().pop_front() else {
+				break;
+			};
+			kthread.mark_runing();
+			let kthread = processor.run_kthread(kthread);
+			if kthread.status() != TaskStatus::Exited {
+				self.kthreads.lock().push_back(kthread);
 			}
 		}
 	}
 
-
-	pub fn prepare_next_at_boot(&self, hartid: usize) -> usize {
-		let (prev_status, next_app) = self.find_next_ready_and_set_run();
-		let next_tcb = &self.tasks[next_app];
-		//TODO: use next_flow_context translated result
-		let next_flow_context_va = unsafe {
-			NonNull::new_unchecked(FLOW_CONTEXT_VADDR as *mut _)
-		};
-		let hart_context_va = unsafe {
-			NonNull::new_unchecked(HART_CONTEXT_VADDR as *mut _)
-		};
-
-		// kernel: link kernel stack to user app
-		let mut hart_context = HartContext::new();
-		#[allow(static_mut_refs)]
-		hart_context.init(
+	/// The idle flow of `hartid`, never returns.
+	///
+	/// It runs on the kernel stack of the hart and picks kernel threads and
+	/// tasks in turn, every task runs on its own kernel stack and comes back
+	/// here when it leaves the hart.
+	pub fn run_tasks(&self, hartid: usize) -> ! {
+		let processor = &PROCESSORS[hartid];
+		let hart_stack = hart_stack(hartid);
+		// ksp is set for each task in dispatch
+		hart_stack.hart_context_mut().init(
 			hartid,
 			KERNEL_ADDRSPACE.get().unwrap().token(),
-			unsafe { KERNEL_STACK.get(hartid).unwrap().traph() as *const _ as usize }
+			0
 		);
-		let kstack = unsafe {
-			#[allow(static_mut_refs)]
-			KERNEL_STACK.get_mut(hartid).unwrap()
-				.init_trap_stack(
-					next_flow_context_va,
-					hart_context_va,
-					hart_context,
-					<Arch as ArchTrap>::fast_handler_user,
-					|_| {}
-				)
-		};
+		// outside of tasks tp points to the trap handler of the boot stack
+		let idle_traph = hart_stack.traph_mut();
+		idle_traph.hart_id = hartid;
+		let idle_traph = idle_traph as *mut TrapHandler;
+		unsafe { set_trap_handler(idle_traph) };
+		ARCH.hart_init();
+		irq::init_hart(hartid);
+		ARCH.set_next_timer_intr(TICK_MS);
+		set_hart_online(hartid);
 
-		// user: link user app to kernel stack(traph), i.e. map some kernel staff
-		//map traph
-		assert!(prev_status == TaskStatus::UnInit);
-		next_tcb
-			.addr_space()
-			.insert_utrap_handler((*&kstack).kstack_ptr().into(), false);
-		//map kernel context(hart context)
-		#[allow(static_mut_refs)]
-		next_tcb
-			.addr_space()
-			.insert_uhart_context((unsafe{
-				KERNEL_STACK.get_mut(hartid).unwrap().as_ptr_range().start as usize
-			}).into(), false);
-		// traph not align to 4k, so we should find the offset of traph
-		let offset = (*&kstack).kstack_ptr() & (PAGE_SIZE - 1);
-		next_tcb.flow_context().utrap_handler = TRAP_HANDLER_VADDR + offset;
+		loop {
+			crash::poll_stop();
+			// interrupts are off in the kernel, so an idle hart polls
+			ipi::handle_messages(hartid);
+			// no flow is on the hart here, so it can go offline
+			hotplug::poll_offline(hartid);
+			irq::handle_irq(hartid);
+			// the idle flow picks the next one anyway
+			processor.take_need_resched();
+			// deferred kernel work goes first
+			self.run_kthreads();
+			let Some((prev_status, task_id)) = self.fetch_ready() else {
+				self.check_end();
+				self.wait_for_work(processor);
+				continue;
+			};
+			self.dispatch(processor, hartid, prev_status, task_id);
+			unsafe { set_trap_handler(idle_traph) };
+		}
+	}
 
-		// init sepc, sstatus, stvec, stie, sscratch
-		<Arch as ArchTrap>::boot_handler(
-			next_tcb.flow_context().pc,
-			TRAMPOLINE_VADDR,
-			TRAP_HANDLER_VADDR + offset,
-		);
-		forget(kstack);
-		next_app
+	/// Sleep until an interrupt, e.g. the IPI of `ipi::wake_idle_hart` once
+	/// something is queued.
+	fn wait_for_work(&self, processor: &Processor) {
+		processor.set_idle();
+		// what is queued before the flag is set is seen here, what is queued
+		// after it comes with an IPI
+		if self.ready.lock().is_empty() && self.kthreads.lock().is_empty() {
+			// the tick goes on while idle, a pending timer would end wfi at once
+			if ARCH.timer_pending() {
+				ARCH.set_next_timer_intr(TICK_MS);
+			}
+			ARCH.wait_for_interrupt();
+		}
+		processor.take_idle();
 	}
 
-	pub fn run_next_at_boot(&self, next_app: usize) -> !{
-		ARCH.set_next_timer_intr(TICK_MS);
-		self.tasks.get(next_app).unwrap().app_info().user_time.start();
-		let sp = TASK_MANAGER.get().unwrap().tasks[next_app].flow_context().sp;
-		let addr_space = TASK_MANAGER.get().unwrap().tasks[next_app].addr_space().token();
-		// init user stack and sret
-		unsafe {
-			<Arch as ArchTrap>::boot_entry(sp, addr_space)
+	/// take the first ready task and set running, return (prev_status, task_id)
+	fn fetch_ready(&self) -> Option<(TaskStatus, usize)> {
+		let mut ready = self.ready.lock();
+		loop {
+			let task_id = ready.pop_front()?;
+			let tcb = self.task(task_id);
+			let prev_status = tcb.status();
+			// ended by exit_process while waiting in the queue
+			if prev_status == TaskStatus::Exited {
+				continue;
+			}
+			assert!(matches!(prev_status, TaskStatus::UnInit | TaskStatus::Ready(_)));
+			tcb.mark_runing();
+			tcb.on_cpu.store(true, Ordering::Relaxed);
+			return Some((prev_status, task_id));
 		}
 	}
 
-	pub fn run_next_at_trap(&self) -> usize{
-		let (prev_status, next_app) = self.find_next_ready_and_set_run();
-		assert!(self.tasks[next_app].status() == TaskStatus::Running);
-		let next_tcb = &self.tasks[next_app];
-		let next_flow_context = (&self.tasks[next_app]).flow_context.get() as *mut FlowContext;
-		let trap_handler = trap_handler_in_trap_stage();
-		let hartid = trap_handler.hart_id;
-
-		// kernel: switch task context
-		trap_handler.transed_context = unsafe { NonNull::new_unchecked(next_flow_context) };
-		trap_handler.app_id = next_app;
-
-		// user: modify the map and flow_context
-		assert!(matches!(prev_status, TaskStatus::UnInit | TaskStatus::Ready(_)));
-		let is_uninit = prev_status == TaskStatus::UnInit;
-		//map traph
-		next_tcb
-			.addr_space()
-			.insert_utrap_handler((trap_handler as *const _ as usize).into(), !is_uninit);
-		//map kernel context(hart context)
-		#[allow(static_mut_refs)]
-		next_tcb
-			.addr_space()
-			.insert_uhart_context((unsafe{
-				KERNEL_STACK.get_mut(hartid).unwrap().as_ptr_range().start as usize
-			}).into(), !is_uninit);
-		// offset and utraph va is always the same
-		let offset = (trap_handler as *const _ as usize) & (PAGE_SIZE - 1);
-		if is_uninit {
-			next_tcb.flow_context().utrap_handler = TRAP_HANDLER_VADDR + offset;
+	/// Run `task_id` on this hart until it leaves, then queue it again if it is ready.
+	fn dispatch(&self, processor: &Processor, hartid: usize, prev_status: TaskStatus, task_id: usize) {
+		let tcb = self.task(task_id);
+		// kernel: link hart to the kernel stack of the task
+		let hart_stack = hart_stack(hartid);
+		hart_stack.hart_context_mut().set_ksp(tcb.trap_handler() as *const _ as usize);
+		let moved = prev_status != TaskStatus::UnInit && tcb.trap_handler().hart_id != hartid;
+		tcb.trap_handler().hart_id = hartid;
+		// user: link task to hart context, the task may come from another hart
+		{
+			let _vm = tcb.process.vm_lock.lock();
+			tcb.addr_space().insert_uhart_context(
+				(hart_stack.as_ptr_range().start as usize).into(),
+				tcb.tid,
+				prev_status != TaskStatus::UnInit
+			);
+		}
+		// the other threads may still see the hart context of the old hart
+		if moved {
+			let uhc_va = thread_slot_vaddr(HART_CONTEXT_VADDR, tcb.tid);
+			ipi::flush_tlb(&self.harts_running(&tcb.process, task_id), Some(uhc_va));
 		}
 
-		// switch sscratch and sepc
-		unsafe {
-			(*next_flow_context).load_others();
+		processor.run_task(task_id, tcb.kernel_context_ptr());
+
+		// the task left the hart, its kernel context is saved
+		let killed = {
+			let mut ready = self.ready.lock();
+			tcb.on_cpu.store(false, Ordering::Relaxed);
+			// missed by exit_process while it was still on the hart
+			let killed = tcb.process.is_exiting() && tcb.status() != TaskStatus::Exited;
+			if killed {
+				tcb.mark_exit();
+			} else if matches!(tcb.status(), TaskStatus::Ready(_)) {
+				ready.push_back(task_id);
+			}
+			killed
+		};
+		if killed {
+			tcb.exit_wait.wake_all();
 		}
+	}
 
-		assert!(self.tasks[next_app].status() == TaskStatus::Running);
-		next_app
+	/// Harts which run threads of `process` other than `task_id`, their TLBs
+	/// may hold translations of its address space.
+	///
+	/// A thread picked just now may still report its last hart, it flushes
+	/// everything before it enters user mode anyway.
+	fn harts_running(&self, process: &ProcessControlBlock, task_id: usize) -> Vec<usize> {
+		let mut harts: Vec<usize> = self.threads_of(process).into_iter()
+			.filter(|tcb| tcb.id() != task_id && tcb.on_cpu.load(Ordering::Relaxed))
+			.map(|tcb| tcb.trap_handler().hart_id)
+			.collect();
+		harts.sort_unstable();
+		harts.dedup();
+		harts
 	}
 
-	pub fn exit_cur_and_run_next(&self) {
-		let app_id = task_context_in_trap_stage().app_info().app_id;
-		let old_task_block = self.tasks.get(app_id).unwrap();
-		assert!(old_task_block.status() == TaskStatus::Running, "this task is not Running, something may be wrong");
-		old_task_block.app_info().kernel_time.end();
-		old_task_block.app_info().end();
-		old_task_block.mark_exit();
+	/// Every thread of `process` which is not reaped yet.
+	///
+	/// A thread may be reaped and its task id used again after the ids are
+	/// read, so the tasks are checked to still belong to `process`.
+	fn threads_of(&self, process: &ProcessControlBlock) -> Vec<Arc<TaskControlBlock>> {
+		let tasks = self.tasks.read();
+		process.threads().into_iter()
+			.filter_map(|task_id| tasks.get(task_id).cloned().flatten())
+			.filter(|tcb| core::ptr::eq(Arc::as_ptr(&tcb.process), process))
+			.collect()
+	}
+
+	/// Save the current task and go back to the idle flow of this hart.
+	///
+	/// It returns when the task is picked again, maybe on another hart, with
+	/// the user registers which are still in the hart restored.
+	fn leave_hart(&self, tcb: &TaskControlBlock) {
+		let processor = current_processor();
+		unsafe { tcb.flow_context().save_live() };
+		processor.switch_to_sched(tcb.kernel_context_ptr());
+		unsafe {
+			set_trap_handler(tcb.trap_handler());
+			tcb.flow_context().load_live();
+			tcb.flow_context().load_others();
+		}
+	}
+
+	pub fn exit_cur_and_run_next(&self) -> ! {
+		let tcb = task_context_in_trap_stage();
+		let task_id = tcb.id();
+		assert!(tcb.status() == TaskStatus::Running, "this task is not Running, something may be wrong");
+		tcb.app_info().kernel_time.end();
+		tcb.app_info().end();
+		tcb.stdout.flush(task_id);
+		tcb.mark_exit();
+		// waittid checks the status under the queue lock
+		tcb.exit_wait.wake_all();
+		if tcb.tid == 0 {
+			self.exit_process(&tcb.process, task_id);
+		}
+		info!("Kernel end {}", task_id);
+
+		current_processor().switch_to_sched(tcb.kernel_context_ptr());
+		unreachable!("exited task {} is scheduled again", task_id);
+	}
 
-		let next_app = self.run_next_at_trap();
+	/// End the whole app of the current task, e.g. on a fatal signal.
+	pub fn exit_cur_process_and_run_next(&self, exit_code: i32) -> ! {
+		let tcb = task_context_in_trap_stage();
+		tcb.set_exit_code(exit_code);
+		// the main thread ends the others in exit_cur_and_run_next
+		if tcb.tid != 0 {
+			self.exit_process(&tcb.process, tcb.id());
+		}
+		self.exit_cur_and_run_next()
+	}
 
-		let new_task_block = &self.tasks[next_app];
-		assert!(new_task_block.status() == TaskStatus::Running);
-		info!("Kernel end {} and switch to app {}", app_id, next_app);
+	/// The task `cur` of `process` ends the app, end the other threads as well.
+	///
+	/// Threads off the hart end here, the running ones at their next trap
+	/// or in `dispatch` once they leave the hart.
+	fn exit_process(&self, process: &ProcessControlBlock, cur: usize) {
+		process.mark_exiting();
+		let killed: Vec<Arc<TaskControlBlock>> = {
+			let mut ready = self.ready.lock();
+			let killed: Vec<Arc<TaskControlBlock>> = self.threads_of(process).into_iter()
+				.filter(|tcb| {
+					tcb.id() != cur &&
+						!tcb.on_cpu.load(Ordering::Relaxed) &&
+						tcb.status() != TaskStatus::Exited
+				})
+				.inspect(|tcb| {
+					tcb.stdout.flush(tcb.id());
+					tcb.mark_exit();
+				})
+				.collect();
+			// exited tasks leave the queue, their ids may be used again
+			ready.retain(|task_id| killed.iter().all(|tcb| tcb.id() != *task_id));
+			killed
+		};
+		killed.iter().for_each(|tcb| {
+			tcb.exit_wait.wake_all();
+		});
+		// make the running ones trap now rather than at their next tick
+		for tcb in self.threads_of(process).into_iter().filter(|tcb| tcb.id() != cur) {
+			if tcb.on_cpu.load(Ordering::Relaxed) {
+				ipi::resched(tcb.trap_handler().hart_id);
+			}
+		}
 	}
 
 	pub fn suspend_cur_and_run_next(&self, sp: Option<usize>, pc: Option<usize>) {
-		let app_id = task_context_in_trap_stage().app_info().app_id;
-		let old_task_block = self.tasks.get(app_id).unwrap();
+		let old_task_block = task_context_in_trap_stage();
+		let task_id = old_task_block.id();
 		assert!(old_task_block.status() == TaskStatus::Running, "this task is not Running, something may be wrong");
 		old_task_block.app_info().kernel_time.end();
 
-		info!("Release {}, and save the sp and pc", app_id);
+		info!("Release {}, and save the sp and pc", task_id);
 		if let Some(sp) = sp {
 			old_task_block.flow_context().set_sp(sp);
 		}
@@ -249,18 +436,119 @@ impl TaskManager {
 		}
 
 		old_task_block.mark_suspend_low();
-		let next_app = self.run_next_at_trap();
-		if next_app != app_id {
-			old_task_block.mark_suspend_high_cas(TaskStatus::Ready(ReadyLevel::Low));
+		self.leave_hart(old_task_block);
+		info!("Kernel resume {}", task_id);
+	}
+
+	/// Give the hart away in the middle of a syscall, see `preempt_point`.
+	pub fn preempt_cur_in_kernel(&self) {
+		let tcb = task_context_in_trap_stage();
+		tcb.app_info().kernel_time.end();
+		unsafe { tcb.flow_context().save_others() };
+		tcb.mark_suspend_low();
+		self.leave_hart(tcb);
+		tcb.app_info().kernel_time.start();
+	}
+
+	/// Block the current task in the middle of a syscall until `wakeup`.
+	///
+	/// `guard` is the lock its waker takes to find the task, e.g. of a wait
+	/// queue. It is released only after the task is marked blocked, so the
+	/// wakeup can not be lost. A signal which the task does not block wakes
+	/// it as well, or keeps it from blocking if it is already pending.
+	pub fn block_cur_and_run_next<G>(&self, guard: G) {
+		let tcb = task_context_in_trap_stage();
+		tcb.mark_blocked();
+		drop(guard);
+		// pairs with `send_signal`, which may have found the task running
+		fence(Ordering::SeqCst);
+		if tcb.signals.has_deliverable() {
+			// a waker which came meanwhile did not queue it, it is on the hart
+			tcb.mark_runing();
+			return;
+		}
+		tcb.app_info().kernel_time.end();
+		unsafe { tcb.flow_context().save_others() };
+		self.leave_hart(tcb);
+		tcb.app_info().kernel_time.start();
+	}
+
+	/// Make a blocked task ready again, return false if it was not blocked.
+	///
+	/// If it has not left its hart yet, `dispatch` queues it after it left.
+	pub fn wakeup(&self, task_id: usize) -> bool {
+		let tcb = self.task(task_id);
+		let (woken, queued) = {
+			let mut ready = self.ready.lock();
+			let woken = tcb.wakeup_cas();
+			let queued = woken && !tcb.on_cpu.load(Ordering::Relaxed);
+			if queued {
+				ready.push_back(task_id);
+			}
+			(woken, queued)
+		};
+		if queued {
+			ipi::wake_idle_hart();
 		}
+		woken
+	}
 
-		let new_task_block = &self.tasks[next_app];
-		assert!(new_task_block.status() == TaskStatus::Running);
-		info!("Kernel suspend {} switch to app {}", app_id, next_app);
+	/// Task `task_id`, which must not be reaped.
+	pub fn task(&self, task_id: usize) -> Arc<TaskControlBlock> {
+		self.tasks.read()[task_id].clone().expect("task is reaped")
 	}
 
-	pub fn task(&self, app_id: usize) -> &TaskControlBlock {
-		// check the app_id?
-		&self.tasks[app_id]
+	/// Thread `tid` of `process`, None if there is none or it is reaped.
+	pub fn thread(&self, process: &ProcessControlBlock, tid: usize) -> Option<Arc<TaskControlBlock>> {
+		let task_id = process.thread(tid)?;
+		self.tasks.read().get(task_id).cloned().flatten()
+			.filter(|tcb| core::ptr::eq(Arc::as_ptr(&tcb.process), process) && tcb.tid == tid)
+	}
+
+	/// Every task, unless a hart which stopped for a crash dump holds the table.
+	pub fn try_tasks(&self) -> Option<RwLockReadGuard<'_, Vec<Option<Arc<TaskControlBlock>>>>> {
+		self.tasks.try_read()
+	}
+}
+/// The first code every task runs, on its own kernel stack.
+extern "C" fn task_entry() -> ! {
+	let task_id = current_processor()
+		.current_task()
+		.expect("task entry without a task");
+	let (sp, token, arg) = {
+		let tcb = TASK_MANAGER.get().unwrap().task(task_id);
+		tcb.app_info().start_time = ARCH.time_ms();
+		tcb.app_info().user_time.start();
+		// init sepc, sstatus, stvec, stie, sscratch
+		<Arch as ArchTrap>::boot_handler(
+			tcb.flow_context().pc,
+			TRAMPOLINE_VADDR,
+			tcb.flow_context().utrap_handler,
+		);
+		(tcb.flow_context().sp, tcb.addr_space().token(), tcb.flow_context().a[0])
+	};
+	// init user stack and sret, the argument of a thread goes in a0
+	unsafe {
+		<Arch as ArchTrap>::boot_entry(sp, token, arg)
+	}
+}
+
+/// A point where long running kernel work gives the hart away once the
+/// tick of the running flow is over.
+///
+/// Kernel code is never switched by the timer interrupt itself, only here
+/// or when it blocks, so callers must not hold a spin lock.
+pub fn preempt_point() {
+	crash::poll_stop();
+	ipi::handle_messages(hart_id_in_trap_stage());
+	let processor = current_processor();
+	if !(processor.take_need_resched() || ARCH.timer_pending()) {
+		return;
+	}
+	ARCH.set_next_timer_intr(TICK_MS);
+	if processor.current_kthread().is_some() {
+		kthread_yield();
+	} else if processor.current_task().is_some() {
+		TASK_MANAGER.get().unwrap().preempt_cur_in_kernel();
 	}
 }
@@ -0,0 +1,159 @@
+use core::cell::SyncUnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::mm::addr_space::AddrSpace;
+use crate::task::signal::{SigAction, NSIG};
+use crate::task::wait_queue::WaitQueue;
+
+/// What the threads of one app share.
+pub struct ProcessControlBlock {
+	pub app_id: usize,
+	addr_space: SyncUnsafeCell<AddrSpace>,
+	/// serializes changes of the page table, threads of the app run on
+	/// several harts at the same time
+	pub vm_lock: Mutex<()>,
+	/// task id of each thread, indexed by tid, None once it is reaped
+	threads: Mutex<Vec<Option<usize>>>,
+	/// bottom of the user stack of the main thread
+	ustack_base: usize,
+	/// the main thread exited, the other threads exit at their next trap
+	exiting: AtomicBool,
+	/// futex wait queues, keyed by user virtual address, only while some
+	/// task waits on or wakes the futex
+	futexes: Mutex<BTreeMap<usize, Arc<WaitQueue>>>,
+	/// signal actions, indexed by signal number
+	sigactions: Mutex<[SigAction; NSIG]>,
+}
+
+unsafe impl Send for ProcessControlBlock {}
+unsafe impl Sync for ProcessControlBlock {}
+
+impl ProcessControlBlock {
+	pub fn new(app_id: usize, addr_space: AddrSpace, ustack_base: usize) -> Self {
+		Self {
+			app_id,
+			addr_space: SyncUnsafeCell::new(addr_space),
+			vm_lock: Mutex::new(()),
+			threads: Mutex::new(Vec::new()),
+			ustack_base,
+			exiting: AtomicBool::new(false),
+			futexes: Mutex::new(BTreeMap::new()),
+			sigactions: Mutex::new([SigAction::DEFAULT; NSIG]),
+		}
+	}
+
+	pub fn addr_space(&self) -> &mut AddrSpace {
+		unsafe {
+			&mut (*self.addr_space.get())
+		}
+	}
+
+	pub fn ustack_base(&self) -> usize {
+		self.ustack_base
+	}
+
+	/// Reserve the lowest free tid for `task_id`, None if the app has too many threads.
+	pub fn alloc_tid(&self, task_id: usize, max: usize) -> Option<usize> {
+		let mut threads = self.threads.lock();
+		let tid = threads.iter().position(Option::is_none).unwrap_or(threads.len());
+		if tid >= max {
+			return None;
+		}
+		if tid == threads.len() {
+			threads.push(None);
+		}
+		threads[tid] = Some(task_id);
+		Some(tid)
+	}
+
+	/// Give `tid` back, false if it is not thread `task_id` anymore, e.g.
+	/// another waiter reaped it first.
+	pub fn free_tid(&self, tid: usize, task_id: usize) -> bool {
+		match self.threads.lock().get_mut(tid) {
+			Some(slot) if *slot == Some(task_id) => {
+				*slot = None;
+				true
+			}
+			_ => false,
+		}
+	}
+
+	/// task id of thread `tid`
+	pub fn thread(&self, tid: usize) -> Option<usize> {
+		self.threads.lock().get(tid).copied().flatten()
+	}
+
+	/// task id of every thread which is not reaped yet
+	pub fn threads(&self) -> Vec<usize> {
+		self.threads.lock().iter().flatten().copied().collect()
+	}
+
+	/// wait queue of the futex at `uaddr` to wait on, created if there is none
+	pub fn futex_queue(&self, uaddr: usize) -> Arc<WaitQueue> {
+		self.futexes.lock()
+			.entry(uaddr)
+			.or_insert_with(|| Arc::new(WaitQueue::new()))
+			.clone()
+	}
+
+	/// wait queue of the futex at `uaddr` to wake, None if nobody waits
+	pub fn find_futex_queue(&self, uaddr: usize) -> Option<Arc<WaitQueue>> {
+		self.futexes.lock().get(&uaddr).cloned()
+	}
+
+	/// Give back a queue of `futex_queue` or `find_futex_queue`, it is
+	/// removed once nobody else holds it.
+	///
+	/// Queues are only cloned under the map lock, and a waiter holds its
+	/// queue while it is queued, so the last holder sees no waiter.
+	pub fn put_futex_queue(&self, uaddr: usize, queue: Arc<WaitQueue>) {
+		let mut futexes = self.futexes.lock();
+		drop(queue);
+		if futexes.get(&uaddr).is_some_and(|queue| Arc::strong_count(queue) == 1) {
+			futexes.remove(&uaddr);
+		}
+	}
+
+	pub fn sigaction(&self, signo: usize) -> SigAction {
+		self.sigactions.lock()[signo]
+	}
+
+	/// Set the action of `signo` and return the old one.
+	pub fn set_sigaction(&self, signo: usize, action: SigAction) -> SigAction {
+		core::mem::replace(&mut self.sigactions.lock()[signo], action)
+	}
+
+	pub fn is_exiting(&self) -> bool {
+		self.exiting.load(Ordering::Acquire)
+	}
+
+	pub fn mark_exiting(&self) {
+		self.exiting.store(true, Ordering::Release);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test_case]
+	fn futex_queue_test() {
+		let process = ProcessControlBlock::new(0, AddrSpace::new_bare(), 0);
+		assert!(process.find_futex_queue(0x1000).is_none());
+		let queue = process.futex_queue(0x1000);
+		let found = process.find_futex_queue(0x1000).unwrap();
+		assert!(Arc::ptr_eq(&queue, &found));
+		process.put_futex_queue(0x1000, found);
+		// still held by the waiter
+		assert!(process.find_futex_queue(0x1000).is_some());
+		process.put_futex_queue(0x1000, queue);
+		assert!(process.find_futex_queue(0x1000).is_none());
+
+		crate::println!("futex_queue_test passed!");
+	}
+}
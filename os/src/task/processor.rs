@@ -0,0 +1,92 @@
+use core::cell::SyncUnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::sync::Arc;
+
+use crate::arch::common::{ArchSwitch, KernelContext};
+use crate::global::{ARCH, PROCESSORS};
+use crate::harts::hart_id_in_trap_stage;
+use crate::task::kthread::KernelThread;
+
+/// What a hart is running on the kernel side.
+pub struct Processor {
+	/// the idle flow of this hart, which picks tasks and kernel threads
+	sched_context: SyncUnsafeCell<KernelContext>,
+	current_kthread: SyncUnsafeCell<Option<Arc<KernelThread>>>,
+	current_task: SyncUnsafeCell<Option<usize>>,
+	/// a tick passed while the kernel could not switch, see `preempt_point`
+	need_resched: AtomicBool,
+	/// the idle flow sleeps until an IPI, see `ipi::wake_idle_hart`
+	idle: AtomicBool,
+}
+
+// SAFETY: a processor is only touched by its own hart
+unsafe impl Sync for Processor {}
+
+impl Processor {
+	pub const fn new() -> Self {
+		Self {
+			sched_context: SyncUnsafeCell::new(KernelContext::ZERO),
+			current_kthread: SyncUnsafeCell::new(None),
+			current_task: SyncUnsafeCell::new(None),
+			need_resched: AtomicBool::new(false),
+			idle: AtomicBool::new(false),
+		}
+	}
+
+	pub fn current_kthread(&self) -> Option<Arc<KernelThread>> {
+		unsafe { (*self.current_kthread.get()).clone() }
+	}
+
+	/// app id of the user task running on this hart
+	pub fn current_task(&self) -> Option<usize> {
+		unsafe { *self.current_task.get() }
+	}
+
+	/// Run `kthread` on this hart until it yields or exits, then give it back.
+	pub fn run_kthread(&self, kthread: Arc<KernelThread>) -> Arc<KernelThread> {
+		let next = kthread.context_ptr();
+		unsafe {
+			*self.current_kthread.get() = Some(kthread);
+			ARCH.switch(self.sched_context.get(), next);
+			(*self.current_kthread.get()).take().unwrap()
+		}
+	}
+
+	/// Run the task `app_id` on this hart until it leaves the hart.
+	pub fn run_task(&self, app_id: usize, next: *const KernelContext) {
+		unsafe {
+			*self.current_task.get() = Some(app_id);
+			ARCH.switch(self.sched_context.get(), next);
+			*self.current_task.get() = None;
+		}
+	}
+
+	/// Save the running kernel flow into `cur` and go back to the scheduler.
+	pub fn switch_to_sched(&self, cur: *mut KernelContext) {
+		unsafe {
+			ARCH.switch(cur, self.sched_context.get());
+		}
+	}
+
+	pub fn set_need_resched(&self) {
+		self.need_resched.store(true, Ordering::Release);
+	}
+
+	pub fn take_need_resched(&self) -> bool {
+		self.need_resched.swap(false, Ordering::AcqRel)
+	}
+
+	pub fn set_idle(&self) {
+		self.idle.store(true, Ordering::Release);
+	}
+
+	/// Return true if the hart was idle, only one waker sees it.
+	pub fn take_idle(&self) -> bool {
+		self.idle.swap(false, Ordering::AcqRel)
+	}
+}
+
+pub fn current_processor() -> &'static Processor {
+	&PROCESSORS[hart_id_in_trap_stage()]
+}
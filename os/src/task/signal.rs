@@ -0,0 +1,323 @@
+use core::sync::atomic::{fence, AtomicU32, Ordering};
+
+use log::warn;
+use spin::Mutex;
+
+use crate::global::TASK_MANAGER;
+use crate::mm::user::{Pod, UserPtr};
+use crate::task::block::TaskControlBlock;
+#[cfg(feature = "coredump")]
+use crate::task::coredump;
+use crate::task::fault::{self, Fault};
+
+/// signals are 1..NSIG
+pub const NSIG: usize = 32;
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGURG: usize = 23;
+pub const SIGWINCH: usize = 28;
+
+/// `SigAction::handler` values which are not handlers
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+/// `how` of sigprocmask
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// do not block the signal while its handler runs
+pub const SA_NODEFER: u32 = 0x4000_0000;
+/// back to `SIG_DFL` once the handler is entered
+pub const SA_RESETHAND: u32 = 0x8000_0000;
+
+const fn sigbit(signo: usize) -> u32 {
+	1 << signo
+}
+
+/// can be neither caught, ignored nor blocked
+const UNBLOCKABLE: u32 = sigbit(SIGKILL);
+
+pub fn valid_signal(signo: usize) -> bool {
+	signo > 0 && signo < NSIG
+}
+
+/// What an app does on a signal, shared by its threads.
+///
+/// The handler is entered as `handler(signo, frame)` and returns to
+/// `restorer`, which must call sigreturn with the sp the handler got.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SigAction {
+	pub handler: usize,
+	pub restorer: usize,
+	/// blocked in addition while the handler runs
+	pub mask: u32,
+	pub flags: u32,
+}
+
+// SAFETY: integers only, without padding
+unsafe impl Pod for SigAction {}
+
+impl SigAction {
+	pub const DEFAULT: Self = Self {
+		handler: SIG_DFL,
+		restorer: 0,
+		mask: 0,
+		flags: 0,
+	};
+}
+
+#[derive(PartialEq)]
+enum DefaultAction {
+	Terminate,
+	Ignore,
+}
+
+fn default_action(signo: usize) -> DefaultAction {
+	match signo {
+		SIGCHLD | SIGCONT | SIGURG | SIGWINCH => DefaultAction::Ignore,
+		_ => DefaultAction::Terminate,
+	}
+}
+
+/// Pending and blocked signals of one task.
+pub struct SignalState {
+	/// raised by any hart, taken by the task itself
+	pending: AtomicU32,
+	/// only changed by the task itself
+	blocked: AtomicU32,
+	/// the trap behind a pending fault signal
+	fault: Mutex<Option<Fault>>,
+}
+
+impl SignalState {
+	pub const fn new() -> Self {
+		Self {
+			pending: AtomicU32::new(0),
+			blocked: AtomicU32::new(0),
+			fault: Mutex::new(None),
+		}
+	}
+
+	/// Mark `signo` pending, it is delivered the next time the task returns
+	/// to user mode. See `send_signal` to also end a wait of the task.
+	pub fn raise(&self, signo: usize) {
+		self.pending.fetch_or(sigbit(signo), Ordering::AcqRel);
+	}
+
+	pub fn blocked(&self) -> u32 {
+		self.blocked.load(Ordering::Acquire)
+	}
+
+	pub fn set_blocked(&self, mask: u32) {
+		self.blocked.store(mask & !UNBLOCKABLE, Ordering::Release);
+	}
+
+	pub fn is_blocked(&self, signo: usize) -> bool {
+		self.blocked() & sigbit(signo) != 0
+	}
+
+	/// some pending signal is not blocked
+	pub fn has_deliverable(&self) -> bool {
+		self.pending.load(Ordering::Acquire) & !self.blocked() != 0
+	}
+
+	/// Take the fault recorded for `signo`, if it was raised by a trap.
+	fn take_fault(&self, signo: usize) -> Option<Fault> {
+		let mut fault = self.fault.lock();
+		if fault.is_some_and(|fault| fault.signo == signo) {
+			fault.take()
+		} else {
+			None
+		}
+	}
+
+	/// Take the lowest pending signal which is not blocked.
+	fn take_deliverable(&self) -> Option<usize> {
+		let blocked = self.blocked();
+		let mut pending = self.pending.load(Ordering::Acquire);
+		loop {
+			let deliverable = pending & !blocked;
+			if deliverable == 0 {
+				return None;
+			}
+			let signo = deliverable.trailing_zeros() as usize;
+			match self.pending.compare_exchange(
+				pending,
+				pending & !sigbit(signo),
+				Ordering::AcqRel,
+				Ordering::Acquire
+			) {
+				Ok(_) => return Some(signo),
+				Err(now) => pending = now,
+			}
+		}
+	}
+}
+
+/// What a handler finds on the user stack, sigreturn restores the task from it.
+///
+/// The handler may change it, e.g. move `pc` past a faulting instruction.
+#[repr(C)]
+pub struct SignalFrame {
+	/// ra, t0-t6, a0-a7, s0-s11, gp, tp, sp, pc
+	pub regs: [usize; 32],
+	/// zero unless every hart has F and D, see `cpu::has_fp`
+	pub f: [usize; 32],
+	/// blocked signals before the handler
+	pub blocked: u32,
+	pub signo: u32,
+}
+
+// SAFETY: integers only, without padding
+unsafe impl Pod for SignalFrame {}
+
+/// Raise `signo` on `tcb` from another task, and wake `tcb` if it waits in
+/// the kernel and does not block `signo`, its wait ends with EINTR.
+pub fn send_signal(tcb: &TaskControlBlock, signo: usize) {
+	tcb.signals.raise(signo);
+	if !tcb.signals.is_blocked(signo) {
+		// a task about to block either sees the signal or is seen blocked,
+		// see `TaskManager::block_cur_and_run_next`
+		fence(Ordering::SeqCst);
+		TASK_MANAGER.get().unwrap().wakeup(tcb.id());
+	}
+}
+
+/// Raise the fault signal `signo` on `tcb`.
+///
+/// A fault can not be ignored or blocked, the task would run into it again at
+/// once, so then the default action is taken, as on a fault in its own handler.
+pub fn force_signal(tcb: &TaskControlBlock, signo: usize) {
+	if tcb.signals.is_blocked(signo) || tcb.process.sigaction(signo).handler == SIG_IGN {
+		tcb.process.set_sigaction(signo, SigAction::DEFAULT);
+		tcb.signals.set_blocked(tcb.signals.blocked() & !sigbit(signo));
+	}
+	tcb.signals.raise(signo);
+}
+
+/// Raise the signal of `fault` on `tcb` and keep the trap for the report if it is fatal.
+pub fn raise_fault(tcb: &TaskControlBlock, fault: Fault) {
+	*tcb.signals.fault.lock() = Some(fault);
+	force_signal(tcb, fault.signo);
+}
+
+/// Deliver the pending signals of the current task before it returns to user mode.
+///
+/// The flow context must hold all user registers. The first signal with a
+/// handler rewrites it to enter the handler, a fatal one ends the app.
+pub fn deliver_signals(tcb: &TaskControlBlock) {
+	while let Some(signo) = tcb.signals.take_deliverable() {
+		let action = tcb.process.sigaction(signo);
+		let fault = tcb.signals.take_fault(signo);
+		match action.handler {
+			SIG_IGN => continue,
+			SIG_DFL => {
+				if default_action(signo) == DefaultAction::Terminate {
+					terminate(tcb, signo, fault);
+				}
+			}
+			_ => {
+				if action.flags & SA_RESETHAND != 0 {
+					tcb.process.set_sigaction(signo, SigAction::DEFAULT);
+				}
+				if push_frame(tcb, signo, &action).is_none() {
+					warn!("Task {} can not take signal {} on its stack", tcb.id(), signo);
+					terminate(tcb, SIGSEGV, None);
+				}
+				return;
+			}
+		}
+	}
+}
+
+fn terminate(tcb: &TaskControlBlock, signo: usize, fault: Option<Fault>) -> ! {
+	match fault {
+		Some(fault) => {
+			fault::report(tcb, &fault);
+			#[cfg(feature = "coredump")]
+			coredump::dump(tcb, &fault);
+		}
+		None => warn!("Task {} killed by signal {}", tcb.id(), signo),
+	}
+	TASK_MANAGER.get().unwrap().exit_cur_process_and_run_next(-(signo as i32))
+}
+
+/// Save the user registers below the user sp and enter the handler of `signo`.
+fn push_frame(tcb: &TaskControlBlock, signo: usize, action: &SigAction) -> Option<()> {
+	let ctx = tcb.flow_context();
+	// the FP registers may be newer than the context
+	unsafe { ctx.flush_fp() };
+	let frame = SignalFrame {
+		regs: ctx.user_regs(),
+		f: ctx.f,
+		blocked: tcb.signals.blocked(),
+		signo: signo as u32,
+	};
+	let sp = ctx.sp.checked_sub(size_of::<SignalFrame>())? & !0xf;
+	UserPtr::<SignalFrame>::new(sp).write(tcb.addr_space(), &frame).ok()?;
+
+	let mut blocked = frame.blocked | action.mask;
+	if action.flags & SA_NODEFER == 0 {
+		blocked |= sigbit(signo);
+	}
+	tcb.signals.set_blocked(blocked);
+	ctx.sp = sp;
+	ctx.pc = action.handler;
+	ctx.ra = action.restorer;
+	ctx.a[0] = signo;
+	ctx.a[1] = sp;
+	Some(())
+}
+
+/// Restore the current task from the frame at its user sp, None if it is not readable.
+pub fn restore_frame(tcb: &TaskControlBlock) -> Option<()> {
+	let ctx = tcb.flow_context();
+	let frame = UserPtr::<SignalFrame>::new(ctx.sp).read(tcb.addr_space()).ok()?;
+	ctx.set_user_regs(&frame.regs);
+	// the task loads the FP registers from the context again
+	unsafe { ctx.flush_fp() };
+	ctx.f = frame.f;
+	tcb.signals.set_blocked(frame.blocked);
+	Some(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test_case]
+	fn test_signal_state() {
+		let signals = SignalState::new();
+		assert!(!signals.has_deliverable());
+		signals.raise(SIGUSR2);
+		signals.raise(SIGUSR1);
+		signals.set_blocked(sigbit(SIGUSR1) | sigbit(SIGKILL));
+		// SIGKILL can not be blocked
+		assert!(!signals.is_blocked(SIGKILL));
+		assert_eq!(signals.take_deliverable(), Some(SIGUSR2));
+		assert_eq!(signals.take_deliverable(), None);
+		signals.set_blocked(0);
+		assert_eq!(signals.take_deliverable(), Some(SIGUSR1));
+		assert!(!signals.has_deliverable());
+
+		crate::println!("test_signal_state passed!");
+	}
+}
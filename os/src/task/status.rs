@@ -1,27 +1,18 @@
-#[derive(Copy, Clone, PartialEq, PartialOrd)]
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
 pub enum ReadyLevel {
 	Low,
 	High
 }
 
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TaskStatus {
 	UnInit,
 	Ready(ReadyLevel),
 	Running,
 	Exited,
-}
-
-impl TaskStatus {
-	pub fn get_priority(&self) -> Option<u8> {
-		match self {
-		    TaskStatus::UnInit => Some(255),
-		    TaskStatus::Ready(ReadyLevel::High) => Some(2),
-		    TaskStatus::Ready(ReadyLevel::Low) => Some(3),
-		    _ => None
-		}
-	}
+	/// waiting in the kernel, only `TaskManager::wakeup` makes it ready
+	Blocked,
 }
 
 impl From<TaskStatus> for u8 {
@@ -31,7 +22,8 @@ impl From<TaskStatus> for u8 {
 		    TaskStatus::Running => 1,
 		    TaskStatus::Exited => 2,
 		    TaskStatus::Ready(ReadyLevel::Low) => 3,
-		    TaskStatus::Ready(ReadyLevel::High) => 4
+		    TaskStatus::Ready(ReadyLevel::High) => 4,
+		    TaskStatus::Blocked => 5
 		}
 	}
 }
@@ -45,6 +37,7 @@ impl core::convert::TryFrom<u8> for TaskStatus {
 			2 => TaskStatus::Exited,
 			3 => TaskStatus::Ready(ReadyLevel::Low),
 			4 => TaskStatus::Ready(ReadyLevel::High),
+			5 => TaskStatus::Blocked,
 			_ => return Err(()),
 		})
 	}
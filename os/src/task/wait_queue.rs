@@ -0,0 +1,70 @@
+use alloc::collections::VecDeque;
+
+use crate::error::{Errno, SysResult};
+use crate::global::TASK_MANAGER;
+use crate::harts::task_context_in_trap_stage;
+use crate::sync::IrqMutex;
+
+/// Tasks blocked in the kernel until some event, woken in FIFO order.
+///
+/// Irq handlers wake tasks as well, so the queue keeps interrupts off while locked.
+pub struct WaitQueue(IrqMutex<VecDeque<usize>>);
+
+impl WaitQueue {
+	pub const fn new() -> Self {
+		Self(IrqMutex::new(VecDeque::new()))
+	}
+
+	/// Block the current task unless `ready` returns true.
+	///
+	/// `ready` runs under the queue lock, so an event which is published
+	/// before `wake_*` can not be missed. Return false if it did not block.
+	/// The task may also be woken for other reasons, callers check again.
+	///
+	/// A signal which the task does not block ends the wait with EINTR,
+	/// unless `ready` holds by then.
+	pub fn wait_unless<F: Fn() -> bool>(&self, ready: F) -> SysResult<bool> {
+		let tcb = task_context_in_trap_stage();
+		let mut queue = self.0.lock();
+		if ready() {
+			return Ok(false);
+		}
+		if tcb.signals.has_deliverable() {
+			return Err(Errno::EINTR);
+		}
+		queue.push_back(tcb.id());
+		TASK_MANAGER.get().unwrap().block_cur_and_run_next(queue);
+		let mut queue = self.0.lock();
+		// still queued if a signal woke it
+		queue.retain(|&task_id| task_id != tcb.id());
+		if !ready() && tcb.signals.has_deliverable() {
+			return Err(Errno::EINTR);
+		}
+		Ok(true)
+	}
+
+	/// Wake the task which waited first, return false if there is none.
+	///
+	/// A task which a signal woke already is passed over, it leaves the queue
+	/// by itself.
+	pub fn wake_one(&self) -> bool {
+		loop {
+			let Some(task_id) = self.0.lock().pop_front() else {
+				return false;
+			};
+			if TASK_MANAGER.get().unwrap().wakeup(task_id) {
+				return true;
+			}
+		}
+	}
+
+	/// Wake every waiting task, return how many were woken.
+	pub fn wake_all(&self) -> usize {
+		let waiters: VecDeque<usize> = core::mem::take(&mut *self.0.lock());
+		let num = waiters.len();
+		waiters.into_iter().for_each(|task_id| {
+			TASK_MANAGER.get().unwrap().wakeup(task_id);
+		});
+		num
+	}
+}
@@ -51,6 +51,7 @@ impl EntireContextSeparated {
 	/// 从完整路径恢复。
 	#[inline]
 	pub fn restore(self) -> EntireResult {
+		trap_end(false);
 		EntireResult::Restore
 	}
 	
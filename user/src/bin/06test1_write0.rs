@@ -7,7 +7,7 @@ use core::arch::asm;
 extern crate user_lib;
 extern crate core;
 use core::slice;
-use user_lib::{write, console::STDOUT};
+use user_lib::{write, console::STDOUT, errno::EFAULT};
 
 /// 正确输出：
 /// Test write0 OK!
@@ -34,7 +34,7 @@ pub fn main() -> i32 {
             #[allow(invalid_null_arguments)]
             slice::from_raw_parts(0x0 as *const _, 10)
         }),
-        -1
+        -EFAULT
     );
     let (bottom, top) = unsafe { stack_range() };
     println!("top 0x{:x}, bottom 0x{:x}", top, bottom);
@@ -42,13 +42,13 @@ pub fn main() -> i32 {
         write(STDOUT, unsafe {
             slice::from_raw_parts((top - 5) as *const _, 10)
         }),
-        -1
+        -EFAULT
     );
     assert_eq!(
         write(STDOUT, unsafe {
             slice::from_raw_parts((bottom - 5) as *const _, 10)
         }),
-        -1
+        -EFAULT
     );
     // TODO: test string located in .data section
     println!("Test write0 OK!");
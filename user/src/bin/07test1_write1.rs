@@ -3,7 +3,7 @@
 
 #[macro_use]
 extern crate user_lib;
-use user_lib::{write, console::STDOUT};
+use user_lib::{write, console::STDOUT, errno::EBADF};
 const DATA_STRING: &str = "string from data section\n";
 
 /// 正确输出：
@@ -14,7 +14,7 @@ const DATA_STRING: &str = "string from data section\n";
 
 #[unsafe(no_mangle)]
 pub fn main() -> i32 {
-    assert_eq!(write(1234, DATA_STRING.as_bytes()), -1);
+    assert_eq!(write(1234, DATA_STRING.as_bytes()), -EBADF);
     assert_eq!(
         write(STDOUT, DATA_STRING.as_bytes()),
         DATA_STRING.len() as isize
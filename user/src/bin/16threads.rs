@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use user_lib::errno::ESRCH;
+use user_lib::{exit, gettid, thread_create, waittid};
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 1000;
+/// more threads one after another than an app may have at once
+const REUSED: usize = 48;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn worker(arg: usize) -> ! {
+    for _ in 0..ROUNDS {
+        COUNTER.fetch_add(1, Ordering::Relaxed);
+    }
+    println!("[threads] thread {} (arg {}) done", gettid(), arg);
+    exit(arg as i32);
+    unreachable!();
+}
+
+extern "C" fn short(arg: usize) -> ! {
+    exit(arg as i32);
+    unreachable!();
+}
+
+#[unsafe(no_mangle)]
+fn main() -> i32 {
+    println!("[threads] main thread {}", gettid());
+    let mut tids = [0isize; THREADS];
+    for (i, tid) in tids.iter_mut().enumerate() {
+        *tid = thread_create(worker, i + 100);
+        assert!(*tid > 0);
+    }
+    for (i, tid) in tids.iter().enumerate() {
+        assert_eq!(waittid(*tid as usize), (i + 100) as isize);
+    }
+    assert_eq!(COUNTER.load(Ordering::Relaxed), THREADS * ROUNDS);
+    // a reaped thread is gone, its tid is free for the next one
+    assert_eq!(waittid(tids[0] as usize), -ESRCH);
+    for i in 0..REUSED {
+        let tid = thread_create(short, i);
+        assert_eq!(tid, tids[0]);
+        assert_eq!(waittid(tid as usize), i as isize);
+    }
+    println!("[threads] test OK!");
+    0
+}
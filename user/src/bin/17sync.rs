@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use user_lib::sync::{Barrier, Condvar, Mutex, Semaphore};
+use user_lib::{exit, thread_create, waittid};
+
+const PRODUCERS: usize = 2;
+const CONSUMERS: usize = 2;
+const ITEMS: usize = 200;
+const BUF_SIZE: usize = 8;
+
+struct Ring {
+    buf: [usize; BUF_SIZE],
+    head: usize,
+    len: usize,
+}
+
+static RING: Mutex<Ring> = Mutex::new(Ring { buf: [0; BUF_SIZE], head: 0, len: 0 });
+static NOT_FULL: Condvar = Condvar::new();
+static NOT_EMPTY: Condvar = Condvar::new();
+/// every consumer takes the same number of items
+static TICKETS: Semaphore = Semaphore::new(0);
+static BARRIER: Barrier = Barrier::new(PRODUCERS + CONSUMERS);
+static SUM: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn producer(id: usize) -> ! {
+    BARRIER.wait();
+    for i in 0..ITEMS {
+        let mut ring = NOT_FULL.wait_while(RING.lock(), |ring| ring.len == BUF_SIZE);
+        let tail = (ring.head + ring.len) % BUF_SIZE;
+        ring.buf[tail] = id * ITEMS + i + 1;
+        ring.len += 1;
+        drop(ring);
+        NOT_EMPTY.notify_one();
+        TICKETS.up();
+    }
+    exit(0);
+    unreachable!();
+}
+
+extern "C" fn consumer(_: usize) -> ! {
+    BARRIER.wait();
+    for _ in 0..PRODUCERS * ITEMS / CONSUMERS {
+        TICKETS.down();
+        let mut ring = NOT_EMPTY.wait_while(RING.lock(), |ring| ring.len == 0);
+        let item = ring.buf[ring.head];
+        ring.head = (ring.head + 1) % BUF_SIZE;
+        ring.len -= 1;
+        drop(ring);
+        NOT_FULL.notify_one();
+        SUM.fetch_add(item, Ordering::Relaxed);
+    }
+    exit(0);
+    unreachable!();
+}
+
+#[unsafe(no_mangle)]
+fn main() -> i32 {
+    let mut tids = [0isize; PRODUCERS + CONSUMERS];
+    for (i, tid) in tids.iter_mut().enumerate() {
+        *tid = if i < PRODUCERS {
+            thread_create(producer, i)
+        } else {
+            thread_create(consumer, i)
+        };
+        assert!(*tid > 0);
+    }
+    for tid in tids {
+        assert_eq!(waittid(tid as usize), 0);
+    }
+    let total = PRODUCERS * ITEMS;
+    assert_eq!(SUM.load(Ordering::Relaxed), total * (total + 1) / 2);
+    println!("[sync] test OK!");
+    0
+}
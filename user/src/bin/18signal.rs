@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use user_lib::errno::EINTR;
+use user_lib::signal::{
+    kill, sigmask, sigprocmask, signal, SignalFrame, SIGSEGV, SIGUSR1, SIG_BLOCK, SIG_UNBLOCK,
+};
+use user_lib::{exit, futex_wait, get_taskid, thread_create, waittid, yield_};
+
+static USR1: AtomicUsize = AtomicUsize::new(0);
+static SEGV: AtomicUsize = AtomicUsize::new(0);
+/// never changes, so only a signal ends a wait on it
+static NEVER: AtomicU32 = AtomicU32::new(0);
+static WOKEN: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_usr1(signo: usize, _frame: &mut SignalFrame) {
+    assert_eq!(signo, SIGUSR1);
+    USR1.fetch_add(1, Ordering::Relaxed);
+}
+
+extern "C" fn on_segv(signo: usize, frame: &mut SignalFrame) {
+    assert_eq!(signo, SIGSEGV);
+    SEGV.fetch_add(1, Ordering::Relaxed);
+    // go on after the faulting store, it may be a compressed one
+    let inst = unsafe { (frame.pc() as *const u16).read() };
+    let len = if inst & 0b11 == 0b11 { 4 } else { 2 };
+    frame.set_pc(frame.pc() + len);
+}
+
+/// Signal the main thread until its wait ended.
+extern "C" fn killer(pid: usize) -> ! {
+    while !WOKEN.load(Ordering::Relaxed) {
+        assert_eq!(kill(pid, SIGUSR1), 0);
+        yield_();
+    }
+    exit(0);
+    unreachable!();
+}
+
+#[unsafe(no_mangle)]
+fn main() -> i32 {
+    let pid = get_taskid() as usize;
+    assert_eq!(signal(SIGUSR1, on_usr1), 0);
+    assert_eq!(signal(SIGSEGV, on_segv), 0);
+
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(USR1.load(Ordering::Relaxed), 1);
+
+    // a blocked signal waits until it is unblocked
+    sigprocmask(SIG_BLOCK, sigmask(SIGUSR1));
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(USR1.load(Ordering::Relaxed), 1);
+    sigprocmask(SIG_UNBLOCK, sigmask(SIGUSR1));
+    assert_eq!(USR1.load(Ordering::Relaxed), 2);
+
+    // a store to the unmapped page 0
+    unsafe {
+        core::arch::asm!("sd zero, 0(zero)");
+    }
+    assert_eq!(SEGV.load(Ordering::Relaxed), 1);
+
+    // a signal ends a wait in the kernel and its handler runs first
+    let before = USR1.load(Ordering::Relaxed);
+    let tid = thread_create(killer, pid);
+    assert!(tid > 0);
+    assert_eq!(futex_wait(&NEVER, 0), -EINTR);
+    WOKEN.store(true, Ordering::Relaxed);
+    assert!(USR1.load(Ordering::Relaxed) > before);
+    // the killer may send one more before it sees the flag
+    let mut code = waittid(tid as usize);
+    while code == -EINTR {
+        code = waittid(tid as usize);
+    }
+    assert_eq!(code, 0);
+    println!("[signal] test OK!");
+    0
+}
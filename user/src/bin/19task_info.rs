@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::EFAULT;
+use user_lib::syscall::sys_task_info;
+use user_lib::{get_time, task_info, yield_, TaskInfo, TASK_RUNNING};
+
+const SYSCALL_WRITE: usize = 64;
+const SYSCALL_YIELD: usize = 124;
+const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_TASK_INFO: usize = 410;
+
+static mut INFO: TaskInfo = TaskInfo::new();
+
+#[unsafe(no_mangle)]
+fn main() -> i32 {
+    let start = get_time();
+    println!("[task_info] start");
+    yield_();
+    yield_();
+
+    #[allow(static_mut_refs)]
+    let info = unsafe { &mut INFO };
+    assert_eq!(task_info(info), 0);
+    assert_eq!(info.status, TASK_RUNNING);
+    assert_eq!(info.syscall_times[SYSCALL_GET_TIME], 1);
+    assert!(info.syscall_times[SYSCALL_WRITE] >= 1);
+    assert_eq!(info.syscall_times[SYSCALL_YIELD], 2);
+    assert_eq!(info.syscall_times[SYSCALL_TASK_INFO], 1);
+    assert!(info.time as isize >= get_time() - start - 1);
+
+    // a pointer to read only memory is refused
+    assert_eq!(sys_task_info(main as usize), -EFAULT);
+    println!(
+        "[task_info] time {}ms, user {}us, kernel {}us",
+        info.time, info.user_time, info.kernel_time
+    );
+    println!("[task_info] test OK!");
+    0
+}
@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{write, console::STDOUT};
+
+const PAGE_SIZE: usize = 0x1000;
+
+#[repr(C, align(4096))]
+struct Pages([u8; 2 * PAGE_SIZE]);
+
+static mut PAGES: Pages = Pages([b'-'; 2 * PAGE_SIZE]);
+
+/// 正确输出：
+/// 一行 "é" 与若干无效字节，然后
+/// Test raw write OK!
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    // bytes which are not UTF-8 at all
+    let binary = [0xff, 0xfe, 0x80, 0x00, 0xc3, b'\n'];
+    assert_eq!(write(STDOUT, &binary), binary.len() as isize);
+
+    // a two byte character split by a page boundary
+    let pages = unsafe { &mut *(&raw mut PAGES) };
+    let at = PAGE_SIZE - 1;
+    pages.0[at..at + 3].copy_from_slice("é\n".as_bytes());
+    assert_eq!(write(STDOUT, &pages.0[at..at + 3]), 3);
+
+    println!("Test raw write OK!");
+    0
+}
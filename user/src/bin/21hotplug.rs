@@ -0,0 +1,74 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use user_lib::errno::EBUSY;
+use user_lib::{
+    exit, hart_offline, hart_online, hart_status, thread_create, waittid, yield_,
+    HART_STARTED, HART_STOPPED,
+};
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 200;
+const MAX_HARTS: usize = 64;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn worker(_arg: usize) -> ! {
+    for _ in 0..ROUNDS {
+        COUNTER.fetch_add(1, Ordering::Relaxed);
+        yield_();
+    }
+    exit(0);
+    unreachable!();
+}
+
+/// 正确输出：
+/// [hotplug] N harts
+/// [hotplug] test OK!
+
+#[unsafe(no_mangle)]
+fn main() -> i32 {
+    // hart ids may have holes, e.g. a disabled hart 0
+    let harts = (0..MAX_HARTS).filter(|&hartid| hart_status(hartid) >= 0).count();
+    println!("[hotplug] {} harts", harts);
+    let mut tids = [0isize; THREADS];
+    for tid in tids.iter_mut() {
+        *tid = thread_create(worker, 0);
+        assert!(*tid > 0);
+    }
+
+    // the threads move around while harts go away, the last one stays
+    let mut stopped = [false; MAX_HARTS];
+    for hartid in 0..MAX_HARTS {
+        if hart_status(hartid) != HART_STARTED {
+            continue;
+        }
+        match hart_offline(hartid) {
+            0 => {
+                assert_eq!(hart_status(hartid), HART_STOPPED);
+                stopped[hartid] = true;
+            }
+            ret => assert_eq!(ret, -EBUSY),
+        }
+    }
+    assert!(stopped.iter().filter(|&&stopped| stopped).count() < harts);
+
+    for hartid in (0..MAX_HARTS).filter(|&hartid| stopped[hartid]) {
+        assert_eq!(hart_online(hartid), 0);
+        while hart_status(hartid) != HART_STARTED {
+            yield_();
+        }
+    }
+
+    for tid in tids.iter() {
+        assert_eq!(waittid(*tid as usize), 0);
+    }
+    assert_eq!(COUNTER.load(Ordering::Relaxed), THREADS * ROUNDS);
+    println!("[hotplug] test OK!");
+    0
+}
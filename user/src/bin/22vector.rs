@@ -0,0 +1,170 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use user_lib::signal::{sigaction, signal, SigAction, SignalFrame, SIGILL};
+use user_lib::{exit, thread_create, waittid, yield_};
+
+const ROUNDS: usize = 64;
+/// 每个寄存器用两个 u64 元素，VLEN 至少 128 位
+const VL: usize = 2;
+/// v1、v9、v17 和 v31，分别在 vs8r.v 保存的四组寄存器里
+const REGS: usize = 4;
+
+static NO_VECTOR: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigill(signo: usize, frame: &mut SignalFrame) {
+    assert_eq!(signo, SIGILL);
+    NO_VECTOR.store(true, Ordering::Relaxed);
+    // 跳过探测用的 csrr
+    frame.set_pc(frame.pc() + 4);
+}
+
+extern "C" fn worker(seed: usize) -> ! {
+    test_vector(seed as u64);
+    exit(0);
+    unreachable!();
+}
+
+// f64 的位模式，写成整数以免编译器先用到浮点寄存器
+const F1_5: u64 = 0x3ff8_0000_0000_0000;
+const F2_25: u64 = 0x4002_0000_0000_0000;
+const F0_5: u64 = 0x3fe0_0000_0000_0000;
+const F0_75: u64 = 0x3fe8_0000_0000_0000;
+const F2: u64 = 0x4000_0000_0000_0000;
+const F3: u64 = 0x4008_0000_0000_0000;
+const F3_5: u64 = 0x400c_0000_0000_0000;
+const F4_25: u64 = 0x4011_0000_0000_0000;
+
+/// 向量浮点指令也要浮点状态，这个新线程之前没有用过浮点寄存器，
+/// 第一条 vfadd.vv 同时需要加载浮点和向量状态。
+extern "C" fn fp_worker(_arg: usize) -> ! {
+    let a = [F1_5, F2_25];
+    let b = [F0_5, F0_75];
+    let mut sum = [0u64; VL];
+    let mut shifted = [0u64; VL];
+    let first: u64;
+    unsafe {
+        asm!(
+            ".option push",
+            ".option arch, +v",
+            "vsetivli zero, 2, e64, m1, ta, ma",
+            "vle64.v v1, ({a})",
+            "vle64.v v2, ({b})",
+            "vfadd.vv v3, v1, v2",
+            "vse64.v v3, ({sum})",
+            "vfmv.f.s ft0, v3",
+            "fmv.x.d {first}, ft0",
+            "vfadd.vf v4, v1, ft0",
+            "vse64.v v4, ({shifted})",
+            ".option pop",
+            a = in(reg) a.as_ptr(),
+            b = in(reg) b.as_ptr(),
+            sum = in(reg) sum.as_mut_ptr(),
+            shifted = in(reg) shifted.as_mut_ptr(),
+            first = out(reg) first,
+            out("ft0") _,
+        );
+    }
+    assert_eq!(sum, [F2, F3]);
+    assert_eq!(first, F2);
+    assert_eq!(shifted, [F3_5, F4_25]);
+    exit(0);
+    unreachable!();
+}
+
+/// 正确输出：
+/// [vector] vlenb N
+/// [vector] float ok
+/// [vector] test OK!
+/// 或者 hart 没有向量扩展时：
+/// [vector] no vector extension, skipped
+
+#[unsafe(no_mangle)]
+fn main() -> i32 {
+    assert_eq!(signal(SIGILL, on_sigill), 0);
+    let vlenb: usize;
+    unsafe {
+        asm!("li {0}, 0", "csrr {0}, 0xc22", out(reg) vlenb);
+    }
+    if NO_VECTOR.load(Ordering::Relaxed) {
+        println!("[vector] no vector extension, skipped");
+        return 0;
+    }
+    println!("[vector] vlenb {}", vlenb);
+    // 从现在起的 SIGILL 都是错误
+    assert_eq!(sigaction(SIGILL, Some(&SigAction::DEFAULT), None), 0);
+
+    let tid = thread_create(fp_worker, 0);
+    assert!(tid > 0);
+    assert_eq!(waittid(tid as usize), 0);
+    println!("[vector] float ok");
+
+    // 两个线程交替使用向量寄存器，各自的值不能被对方改写
+    let tid = thread_create(worker, 1000);
+    assert!(tid > 0);
+    test_vector(0);
+    assert_eq!(waittid(tid as usize), 0);
+    println!("[vector] test OK!");
+    0
+}
+
+/// 每轮把一组数装进向量寄存器，yield_ 之后再取出来比较，vl 也应保持不变。
+fn test_vector(seed: u64) {
+    for round in 0..ROUNDS as u64 {
+        let mut input = [0u64; VL * REGS];
+        for (i, x) in input.iter_mut().enumerate() {
+            *x = (seed + round) * 0x9e37_79b9 + i as u64;
+        }
+        unsafe {
+            asm!(
+                ".option push",
+                ".option arch, +v",
+                "vsetivli zero, 2, e64, m1, ta, ma",
+                "vle64.v v1, ({ptr})",
+                "addi {ptr}, {ptr}, 16",
+                "vle64.v v9, ({ptr})",
+                "addi {ptr}, {ptr}, 16",
+                "vle64.v v17, ({ptr})",
+                "addi {ptr}, {ptr}, 16",
+                "vle64.v v31, ({ptr})",
+                ".option pop",
+                ptr = inout(reg) input.as_ptr() => _,
+            );
+        }
+
+        // 向量寄存器还活着时进入内核，另一个线程可能在这期间用了它们
+        yield_();
+
+        let mut output = [0u64; VL * REGS];
+        let vl: usize;
+        unsafe {
+            asm!(
+                ".option push",
+                ".option arch, +v",
+                "csrr {vl}, vl",
+                "vse64.v v1, ({ptr})",
+                "addi {ptr}, {ptr}, 16",
+                "vse64.v v9, ({ptr})",
+                "addi {ptr}, {ptr}, 16",
+                "vse64.v v17, ({ptr})",
+                "addi {ptr}, {ptr}, 16",
+                "vse64.v v31, ({ptr})",
+                ".option pop",
+                ptr = inout(reg) output.as_mut_ptr() => _,
+                vl = out(reg) vl,
+            );
+        }
+        assert_eq!(vl, VL);
+        assert_eq!(input, output);
+
+        if round % 16 == 0 {
+            println!("[vector] seed {} round {}/{}", seed, round, ROUNDS);
+        }
+    }
+}
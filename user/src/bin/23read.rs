@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use user_lib::errno::{EBADF, EFAULT, EINTR};
+use user_lib::signal::{kill, signal, SignalFrame, SIGUSR1};
+use user_lib::{exit, get_taskid, read, thread_create, waittid, yield_};
+
+const STDIN: usize = 0;
+
+static DONE: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_usr1(signo: usize, _frame: &mut SignalFrame) {
+    assert_eq!(signo, SIGUSR1);
+}
+
+/// Signal the main thread until its read returned.
+extern "C" fn killer(pid: usize) -> ! {
+    while !DONE.load(Ordering::Relaxed) {
+        assert_eq!(kill(pid, SIGUSR1), 0);
+        yield_();
+    }
+    exit(0);
+    unreachable!();
+}
+
+/// 正确输出：
+/// [read] test OK!
+/// 有输入时前面还有一行 [read] got N bytes
+
+#[unsafe(no_mangle)]
+fn main() -> i32 {
+    let mut buf = [0u8; 16];
+    assert_eq!(read(STDIN, &mut buf[..0]), 0);
+    assert_eq!(read(42, &mut buf), -EBADF);
+    // the text of the app is mapped but not writable
+    let text = unsafe { core::slice::from_raw_parts_mut(main as usize as *mut u8, buf.len()) };
+    assert_eq!(read(STDIN, text), -EFAULT);
+
+    // nobody types, so the read blocks until a signal ends it
+    assert_eq!(signal(SIGUSR1, on_usr1), 0);
+    let tid = thread_create(killer, get_taskid() as usize);
+    assert!(tid > 0);
+    let got = read(STDIN, &mut buf);
+    DONE.store(true, Ordering::Relaxed);
+    if got > 0 {
+        println!("[read] got {} bytes", got);
+    } else {
+        assert_eq!(got, -EINTR);
+    }
+    // the killer may send one more before it sees the flag
+    let mut code = waittid(tid as usize);
+    while code == -EINTR {
+        code = waittid(tid as usize);
+    }
+    assert_eq!(code, 0);
+    println!("[read] test OK!");
+    0
+}
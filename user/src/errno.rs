@@ -0,0 +1,15 @@
+//! Errors returned by syscalls as `-errno`, the numbers follow linux.
+
+pub const EPERM: isize = 1;
+pub const ESRCH: isize = 3;
+pub const EINTR: isize = 4;
+pub const EIO: isize = 5;
+pub const EBADF: isize = 9;
+pub const EAGAIN: isize = 11;
+pub const ENOMEM: isize = 12;
+pub const EFAULT: isize = 14;
+pub const EBUSY: isize = 16;
+pub const ENODEV: isize = 19;
+pub const EINVAL: isize = 22;
+pub const EDEADLK: isize = 35;
+pub const ENOSYS: isize = 38;
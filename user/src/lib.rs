@@ -5,6 +5,9 @@
 pub mod console;
 mod lang_items;
 pub mod syscall;
+pub mod sync;
+pub mod signal;
+pub mod errno;
 
 #[unsafe(no_mangle)]
 #[unsafe(link_section = ".text.entry")]
@@ -30,8 +33,14 @@ fn clear_bss() {
                                               });
 }
 
+use core::sync::atomic::AtomicU32;
 use syscall::*;
 
+/// Block until some input came in, return how many bytes were read, or
+/// -EINTR if a signal came first.
+pub fn read(fd: usize, buf: &mut [u8]) -> isize {
+    sys_read(fd, buf)
+}
 pub fn write(fd: usize, buf: &[u8]) -> isize {
         sys_write(fd, buf)
 }
@@ -50,3 +59,106 @@ pub fn yield_() -> isize {
 pub fn get_time() -> isize {
     sys_get_time()
 }
+
+/// Start a thread running `entry(arg)` in this app, return its tid, -EAGAIN
+/// if the app has too many threads or -ENOMEM.
+///
+/// The thread shares the memory of the app but has its own stack. It must
+/// end with `exit`, whose code is then returned by `waittid`, which also
+/// frees its tid for a later thread.
+pub fn thread_create(entry: extern "C" fn(usize) -> !, arg: usize) -> isize {
+    sys_thread_create(entry as usize, arg)
+}
+
+pub fn gettid() -> isize {
+    sys_gettid()
+}
+
+/// Block until thread `tid` exits, return its exit code, -ESRCH if there is
+/// no such thread or -EINTR if a signal came first.
+pub fn waittid(tid: usize) -> isize {
+    sys_waittid(tid)
+}
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+
+/// Sleep while the u32 at `uaddr` is `val`, return 0 once woken, -EAGAIN if
+/// it differs or -EINTR if a signal came first.
+pub fn futex_wait(uaddr: &AtomicU32, val: u32) -> isize {
+    sys_futex(uaddr.as_ptr(), FUTEX_WAIT, val)
+}
+
+/// Wake at most `num` threads sleeping on `uaddr`, return how many were woken.
+pub fn futex_wake(uaddr: &AtomicU32, num: u32) -> isize {
+    sys_futex(uaddr.as_ptr(), FUTEX_WAKE, num)
+}
+
+pub const MAX_SYSCALL_NUM: usize = 1024;
+
+/// `TaskInfo::status` values
+pub const TASK_UNINIT: u32 = 0;
+pub const TASK_RUNNING: u32 = 1;
+pub const TASK_EXITED: u32 = 2;
+
+/// What `task_info` reports about the calling thread.
+#[repr(C)]
+pub struct TaskInfo {
+    pub status: u32,
+    /// tid inside the app
+    pub tid: u32,
+    /// calls of each syscall, indexed by syscall id
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    /// ms since the thread first ran
+    pub time: u64,
+    /// time spent in user mode, in us
+    pub user_time: u64,
+    /// time spent in the kernel, in us
+    pub kernel_time: u64,
+}
+
+impl TaskInfo {
+    pub const fn new() -> Self {
+        Self {
+            status: TASK_UNINIT,
+            tid: 0,
+            syscall_times: [0; MAX_SYSCALL_NUM],
+            time: 0,
+            user_time: 0,
+            kernel_time: 0,
+        }
+    }
+}
+
+/// Fill `ti` with the status, syscall counts and run time of this thread, return 0 or -EFAULT.
+pub fn task_info(ti: &mut TaskInfo) -> isize {
+    sys_task_info(ti as *mut TaskInfo as usize)
+}
+
+/// `hart_status` values, the SBI hart states
+pub const HART_STARTED: isize = 0;
+pub const HART_STOPPED: isize = 1;
+
+const HART_STATUS: usize = 0;
+const HART_OFFLINE: usize = 1;
+const HART_ONLINE: usize = 2;
+
+/// State of hart `hartid`, e.g. `HART_STARTED`, -EINVAL if the kernel does
+/// not run on it or -ENODEV if the SBI does not know it.
+pub fn hart_status(hartid: usize) -> isize {
+    sys_hart_ctl(hartid, HART_STATUS)
+}
+
+/// Stop hart `hartid`, return 0 once it stopped or -EBUSY if it is the last one.
+///
+/// Threads running there go on on other harts. Only the apps the kernel
+/// lists in `HOTPLUG_APPS` may do this, the others get -EPERM, as for
+/// `hart_online`.
+pub fn hart_offline(hartid: usize) -> isize {
+    sys_hart_ctl(hartid, HART_OFFLINE)
+}
+
+/// Start hart `hartid` again, it takes threads soon after.
+pub fn hart_online(hartid: usize) -> isize {
+    sys_hart_ctl(hartid, HART_ONLINE)
+}
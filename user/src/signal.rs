@@ -0,0 +1,116 @@
+//! Signals, see `sigaction`.
+
+use crate::syscall::{sys_kill, sys_sigaction, sys_sigprocmask, sys_sigreturn};
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// do not block the signal while its handler runs
+pub const SA_NODEFER: u32 = 0x4000_0000;
+/// back to `SIG_DFL` once the handler is entered
+pub const SA_RESETHAND: u32 = 0x8000_0000;
+
+pub const fn sigmask(signo: usize) -> u32 {
+    1 << signo
+}
+
+/// What the handler gets, the thread goes on from it once the handler returns.
+#[repr(C)]
+pub struct SignalFrame {
+    /// ra, t0-t6, a0-a7, s0-s11, gp, tp, sp, pc
+    pub regs: [usize; 32],
+    /// zero unless every hart has F and D
+    pub f: [usize; 32],
+    /// blocked signals before the handler
+    pub blocked: u32,
+    pub signo: u32,
+}
+
+impl SignalFrame {
+    pub const PC: usize = 31;
+    pub const SP: usize = 30;
+
+    pub fn pc(&self) -> usize {
+        self.regs[Self::PC]
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.regs[Self::PC] = pc;
+    }
+}
+
+pub type SignalHandler = extern "C" fn(signo: usize, frame: &mut SignalFrame);
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SigAction {
+    pub handler: usize,
+    pub restorer: usize,
+    /// blocked in addition while the handler runs
+    pub mask: u32,
+    pub flags: u32,
+}
+
+impl SigAction {
+    pub const DEFAULT: Self = Self::with(SIG_DFL);
+    pub const IGNORE: Self = Self::with(SIG_IGN);
+
+    const fn with(handler: usize) -> Self {
+        Self { handler, restorer: 0, mask: 0, flags: 0 }
+    }
+
+    pub fn new(handler: SignalHandler, mask: u32, flags: u32) -> Self {
+        Self {
+            handler: handler as usize,
+            restorer: sys_sigreturn as usize,
+            mask,
+            flags,
+        }
+    }
+}
+
+/// Set the action of `signo` and return 0, or -EINVAL for a bad signal.
+pub fn sigaction(signo: usize, act: Option<&SigAction>, oldact: Option<&mut SigAction>) -> isize {
+    sys_sigaction(
+        signo,
+        act.map_or(0, |act| act as *const _ as usize),
+        oldact.map_or(0, |oldact| oldact as *mut _ as usize),
+    )
+}
+
+/// Install `handler` for `signo`, shorthand of `sigaction`.
+pub fn signal(signo: usize, handler: SignalHandler) -> isize {
+    sigaction(signo, Some(&SigAction::new(handler, 0, 0)), None)
+}
+
+/// Change the blocked signals of this thread, return the old mask or -EINVAL.
+pub fn sigprocmask(how: usize, set: u32) -> isize {
+    sys_sigprocmask(how, set)
+}
+
+/// Send `signo` to the app `pid`, as returned by `get_taskid`.
+pub fn kill(pid: usize, signo: usize) -> isize {
+    sys_kill(pid, signo)
+}
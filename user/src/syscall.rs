@@ -1,10 +1,21 @@
 use core::arch::asm;
 
+const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_FUTEX: usize = 98;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGRETURN: usize = 139;
 const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_GETTID: usize = 178;
+const SYSCALL_TASK_INFO: usize = 410;
+const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GET_TASKID: usize = 1001;
+const SYSCALL_WAITTID: usize = 1002;
+const SYSCALL_HART_CTL: usize = 1003;
 
 fn syscall(id: usize, args: [usize; 3]) -> isize {
         let mut ret: isize;
@@ -20,6 +31,10 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
         ret
 }
 
+pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
+    syscall(SYSCALL_READ, [fd, buffer.as_mut_ptr() as usize, buffer.len()])
+}
+
 pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
         syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
@@ -39,3 +54,51 @@ pub fn sys_yield() -> isize {
 pub fn sys_get_time() -> isize {
     syscall(SYSCALL_GET_TIME, [0, 0, 0])
 }
+
+pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+    syscall(SYSCALL_THREAD_CREATE, [entry, arg, 0])
+}
+
+pub fn sys_gettid() -> isize {
+    syscall(SYSCALL_GETTID, [0, 0, 0])
+}
+
+pub fn sys_waittid(tid: usize) -> isize {
+    syscall(SYSCALL_WAITTID, [tid, 0, 0])
+}
+
+pub fn sys_futex(uaddr: *const u32, op: usize, val: u32) -> isize {
+    syscall(SYSCALL_FUTEX, [uaddr as usize, op, val as usize])
+}
+
+pub fn sys_kill(pid: usize, signo: usize) -> isize {
+    syscall(SYSCALL_KILL, [pid, signo, 0])
+}
+
+pub fn sys_sigaction(signo: usize, act: usize, oldact: usize) -> isize {
+    syscall(SYSCALL_SIGACTION, [signo, act, oldact])
+}
+
+pub fn sys_sigprocmask(how: usize, set: u32) -> isize {
+    syscall(SYSCALL_SIGPROCMASK, [how, set as usize, 0])
+}
+
+pub fn sys_task_info(ti: usize) -> isize {
+    syscall(SYSCALL_TASK_INFO, [ti, 0, 0])
+}
+
+/// Return from a signal handler, only used as its return address.
+///
+/// sp must be where the handler was entered, i.e. at the signal frame.
+#[unsafe(naked)]
+pub unsafe extern "C" fn sys_sigreturn() -> ! {
+    core::arch::naked_asm!(
+        "li a7, {id}",
+        "ecall",
+        id = const SYSCALL_SIGRETURN,
+    )
+}
+
+pub fn sys_hart_ctl(hartid: usize, op: usize) -> isize {
+    syscall(SYSCALL_HART_CTL, [hartid, op, 0])
+}
@@ -31,6 +31,14 @@ pub struct AllArg {
 	#[arg(long, default_value = "virt")]
 	pub machine: String,
 
+	/// Use the APLIC and IMSIC interrupt controllers instead of the PLIC
+	#[arg(long)]
+	pub aia: bool,
+
+	/// QEMU CPU model, e.g. "rv64,v=true" to give the harts the vector extension
+	#[arg(long)]
+	pub cpu: Option<String>,
+
 	#[arg(long, default_value = "./bootloader/rustsbi-qemu.bin")]
 	pub bios: PathBuf,
 
@@ -86,6 +94,8 @@ pub fn run(arg: &AllArg) -> Option<ExitStatus> {
 		release,
 		smp: arg.smp,
 		machine: arg.machine.clone(),
+		aia: arg.aia,
+		cpu: arg.cpu.clone(),
 		bios: arg.bios.clone(),
 		gui: arg.gui,
 		base_addr: arg.base_addr.clone(),
@@ -0,0 +1,107 @@
+use clap::Args;
+use log::{error, info, warn};
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+/// see `os/src/task/coredump.rs`
+const BEGIN_MARKER: &str = "-----BEGIN PIANOOS CORE";
+const END_MARKER: &str = "-----END PIANOOS CORE-----";
+
+#[derive(Debug, Args, Clone)]
+pub struct CoreArg {
+	/// Console output of the kernel, e.g. saved by `cargo qemu | tee qemu.log`
+	pub log: PathBuf,
+
+	/// Where to write the `.core` files
+	#[arg(short, long, default_value = ".")]
+	pub out_dir: PathBuf,
+}
+
+/// A core cut out of the log.
+struct Core {
+	app: usize,
+	task: usize,
+	size: usize,
+	data: Vec<u8>,
+}
+
+pub fn run(arg: &CoreArg) -> Option<ExitStatus> {
+	let log = match fs::read(&arg.log) {
+		Ok(log) => log,
+		Err(e) => {
+			error!("Cannot read {}: {}", arg.log.display(), e);
+			return None;
+		}
+	};
+	let cores = parse(&String::from_utf8_lossy(&log));
+	if cores.is_empty() {
+		warn!("No core dump found in {}", arg.log.display());
+	}
+	fs::create_dir_all(&arg.out_dir).ok()?;
+	for core in cores {
+		if core.data.len() != core.size {
+			warn!(
+				"Core of app {} task {} has {} bytes but should have {}, the log may be cut",
+				core.app,
+				core.task,
+				core.data.len(),
+				core.size
+			);
+		}
+		let path = arg.out_dir.join(format!("app{}-task{}.core", core.app, core.task));
+		fs::write(&path, &core.data).ok()?;
+		info!("Write {}", path.display());
+	}
+	Some(ExitStatus::default())
+}
+
+fn parse(log: &str) -> Vec<Core> {
+	let mut cores = Vec::new();
+	let mut current: Option<Core> = None;
+	for line in log.lines().map(str::trim) {
+		if let Some(header) = line.strip_prefix(BEGIN_MARKER) {
+			if current.is_some() {
+				warn!("Core dump without end marker, skipped");
+			}
+			current = parse_header(header);
+		} else if line == END_MARKER {
+			cores.extend(current.take());
+		} else if let Some(core) = current.as_mut() {
+			// other harts may print in between
+			match decode_hex(line) {
+				Some(bytes) => core.data.extend(bytes),
+				None => warn!("Skip line in core dump: {}", line),
+			}
+		}
+	}
+	if current.is_some() {
+		warn!("Core dump without end marker, skipped");
+	}
+	cores
+}
+
+/// ` app <id> task <id> size <bytes>-----`
+fn parse_header(header: &str) -> Option<Core> {
+	let fields: Vec<&str> = header.trim_end_matches('-').split_whitespace().collect();
+	let ["app", app, "task", task, "size", size] = fields[..] else {
+		warn!("Bad core dump header: {}", header);
+		return None;
+	};
+	Some(Core {
+		app: app.parse().ok()?,
+		task: task.parse().ok()?,
+		size: size.parse().ok()?,
+		data: Vec::new(),
+	})
+}
+
+fn decode_hex(line: &str) -> Option<Vec<u8>> {
+	if line.is_empty() || line.len() % 2 != 0 {
+		return None;
+	}
+	(0..line.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(line.get(i..i + 2)?, 16).ok())
+		.collect()
+}
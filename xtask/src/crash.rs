@@ -0,0 +1,209 @@
+use clap::Args;
+use log::{error, warn};
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+/// see `os/src/crash.rs`
+const BEGIN_MARKER: &str = "-----BEGIN PIANOOS CRASH-----";
+const END_MARKER: &str = "-----END PIANOOS CRASH-----";
+
+/// names of `FlowContext::user_regs`, the order of the `regs` line
+const REG_NAMES: [&str; 32] = [
+	"ra", "t0", "t1", "t2", "t3", "t4", "t5", "t6",
+	"a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7",
+	"s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7",
+	"s8", "s9", "s10", "s11", "gp", "tp", "sp", "pc",
+];
+/// the `regs` line of a hart stopped in the kernel
+const KERNEL_REG_NAMES: [&str; 5] = ["sepc", "scause", "stval", "ra", "sp"];
+
+#[derive(Debug, Args, Clone)]
+pub struct CrashArg {
+	/// Console output of the kernel, e.g. saved by `cargo qemu | tee qemu.log`
+	pub log: PathBuf,
+}
+
+enum HartState {
+	Panic,
+	Silent,
+	Kernel { task: String, at: String, regs: Vec<String> },
+	User { task: String, app: String, regs: Vec<String> },
+}
+
+struct Hart {
+	id: String,
+	state: HartState,
+}
+
+struct Task {
+	id: String,
+	app: String,
+	tid: String,
+	status: String,
+	pc: String,
+	sp: String,
+}
+
+#[derive(Default)]
+struct Crash {
+	harts: Vec<Hart>,
+	tasks: Vec<Task>,
+	/// the task table was locked
+	tasks_busy: bool,
+	/// (total, free), None if the allocator was locked
+	frames: Option<Option<(usize, usize)>>,
+}
+
+pub fn run(arg: &CrashArg) -> Option<ExitStatus> {
+	let log = match fs::read(&arg.log) {
+		Ok(log) => log,
+		Err(e) => {
+			error!("Cannot read {}: {}", arg.log.display(), e);
+			return None;
+		}
+	};
+	let Some(crash) = parse(&String::from_utf8_lossy(&log)) else {
+		warn!("No crash dump found in {}", arg.log.display());
+		return None;
+	};
+	report(&crash);
+	Some(ExitStatus::default())
+}
+
+/// The last crash dump in `log`, a kernel panics once.
+fn parse(log: &str) -> Option<Crash> {
+	let mut found = None;
+	let mut current: Option<Crash> = None;
+	for line in log.lines().map(str::trim) {
+		if line == BEGIN_MARKER {
+			current = Some(Crash::default());
+		} else if line == END_MARKER {
+			found = current.take().or(found);
+		} else if let Some(crash) = current.as_mut() {
+			let fields: Vec<&str> = line.split_whitespace().collect();
+			if parse_line(crash, &fields).is_none() {
+				warn!("Skip line in crash dump: {}", line);
+			}
+		}
+	}
+	if let Some(crash) = current {
+		warn!("Crash dump without end marker, the log may be cut");
+		found = Some(crash);
+	}
+	found
+}
+
+fn parse_line(crash: &mut Crash, fields: &[&str]) -> Option<()> {
+	match fields {
+		["hart", id, "panic"] => crash.harts.push(hart(id, HartState::Panic)),
+		["hart", id, "silent"] => crash.harts.push(hart(id, HartState::Silent)),
+		["hart", id, "kernel", "task", task, "at", at] => crash.harts.push(hart(
+			id,
+			HartState::Kernel { task: task.to_string(), at: at.to_string(), regs: Vec::new() },
+		)),
+		["hart", id, "user", "task", task, "app", app] => crash.harts.push(hart(
+			id,
+			HartState::User { task: task.to_string(), app: app.to_string(), regs: Vec::new() },
+		)),
+		["hart", id, "regs", regs @ ..] => {
+			let hart = crash.harts.iter_mut().rev().find(|hart| hart.id == *id)?;
+			let (saved, names) = match &mut hart.state {
+				HartState::Kernel { regs, .. } => (regs, KERNEL_REG_NAMES.len()),
+				HartState::User { regs, .. } => (regs, REG_NAMES.len()),
+				_ => return None,
+			};
+			if regs.len() != names {
+				return None;
+			}
+			*saved = regs.iter().map(|reg| reg.to_string()).collect();
+		}
+		["task", id, "app", app, "tid", tid, "status", status, "pc", pc, "sp", sp] => {
+			crash.tasks.push(Task {
+				id: id.to_string(),
+				app: app.to_string(),
+				tid: tid.to_string(),
+				status: status.to_string(),
+				pc: pc.to_string(),
+				sp: sp.to_string(),
+			});
+		}
+		["tasks", "busy"] => crash.tasks_busy = true,
+		["frames", "total", total, "free", free] => {
+			crash.frames = Some(Some((total.parse().ok()?, free.parse().ok()?)));
+		}
+		["frames", "busy"] => crash.frames = Some(None),
+		_ => return None,
+	}
+	Some(())
+}
+
+fn hart(id: &str, state: HartState) -> Hart {
+	Hart { id: id.to_string(), state }
+}
+
+fn report(crash: &Crash) {
+	println!("Harts:");
+	for hart in crash.harts.iter() {
+		match &hart.state {
+			HartState::Panic => println!("  hart {:<2} panicked", hart.id),
+			HartState::Silent => {
+				println!("  hart {:<2} did not stop in time, e.g. it spins on a lock", hart.id)
+			}
+			HartState::Kernel { task, at, regs } => {
+				println!("  hart {:<2} in the kernel, task {}, at {}", hart.id, task, at);
+				print_regs(&KERNEL_REG_NAMES, regs);
+			}
+			HartState::User { task, app, regs } => {
+				println!("  hart {:<2} in user mode, task {} of app {}", hart.id, task, app);
+				print_regs(&REG_NAMES, regs);
+			}
+		}
+	}
+
+	println!("Tasks:");
+	if crash.tasks_busy {
+		println!("  the task table was locked by a stopped hart");
+	} else if crash.tasks.is_empty() {
+		println!("  none");
+	} else {
+		println!(
+			"  {:>4} {:>4} {:>4}  {:<12} {:>18} {:>18}",
+			"task", "app", "tid", "status", "pc", "sp"
+		);
+		for task in crash.tasks.iter() {
+			println!(
+				"  {:>4} {:>4} {:>4}  {:<12} {:>18} {:>18}",
+				task.id, task.app, task.tid, task.status, task.pc, task.sp
+			);
+		}
+	}
+
+	println!("Frames:");
+	match crash.frames {
+		Some(Some((total, free))) => {
+			let used = total - free;
+			println!(
+				"  {} of {} used ({:.1}%), {} free",
+				used,
+				total,
+				used as f64 * 100.0 / total.max(1) as f64,
+				free
+			);
+		}
+		Some(None) => println!("  the allocator was locked by a stopped hart"),
+		None => println!("  not set up yet"),
+	}
+}
+
+/// four registers a row, nothing if the `regs` line was missing
+fn print_regs(names: &[&str], regs: &[String]) {
+	for (names, vals) in names.chunks(4).zip(regs.chunks(4)) {
+		let line: Vec<String> = names
+			.iter()
+			.zip(vals)
+			.map(|(name, val)| format!("{:>6}: {:>18}", name, val))
+			.collect();
+		println!("      {}", line.join("  "));
+	}
+}
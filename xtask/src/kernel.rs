@@ -1,8 +1,9 @@
 use clap::Args;
 use crate::utils::{CmdOptional, cargo};
 use crate::KERNEL_PACKAGE_NAME;
+use crate::ksyms;
 use std::process::ExitStatus;
-use log::{error, info};
+use log::{error, info, warn};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::env;
@@ -16,7 +17,7 @@ pub struct KernelArg {
 	#[arg(long, default_value_t = false)]
     	pub release: bool,
 
-	#[arg(long, short = 'f', default_value = "float")]
+	#[arg(long, short = 'f')]
     	pub features: Vec<String>,
 
 	#[arg(long, default_value_t = false)]
@@ -98,6 +99,11 @@ fn build_kernel(arg: &KernelArg) -> Option<ExitStatus> {
 
 	let bin_path = target_dir.join(format!("{}.bin", KERNEL_PACKAGE_NAME));
 
+	// before the binary is made, it holds the symbols too
+	if ksyms::embed(&elf_path).is_none() {
+		warn!("Kernel symbols are not embedded, backtraces show raw addresses");
+	}
+
 	// Create binary from ELF
 	info!("Converting ELF to binary with rust-objcopy");
 	let result = Command::new("rust-objcopy")
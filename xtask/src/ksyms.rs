@@ -0,0 +1,110 @@
+use log::{info, warn};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const KSYMS_SECTION: &str = ".ksyms";
+const KSYMS_MAGIC: &[u8; 4] = b"KSYM";
+
+/// Fill the `.ksyms` section of the kernel ELF with its function symbols.
+///
+/// The kernel prints backtraces as `function+offset` with them, the format
+/// is described in `os/src/ksyms.rs`.
+pub fn embed(elf: &Path) -> Option<()> {
+	let size = section_size(elf, KSYMS_SECTION)?;
+	let (syms, link_base) = text_symbols(elf)?;
+	let mut blob = encode(&syms, link_base);
+	if blob.len() > size {
+		warn!(
+			"Symbol table needs {} bytes but {} has {}, raise KSYMS_SIZE",
+			blob.len(),
+			KSYMS_SECTION,
+			size
+		);
+		return None;
+	}
+	info!("Embed {} kernel symbols ({} bytes)", syms.len(), blob.len());
+	// the section keeps its size, so no address in the image moves
+	blob.resize(size, 0);
+	let blob_path = elf.with_extension("ksyms");
+	fs::write(&blob_path, &blob).ok()?;
+	let status = Command::new("rust-objcopy")
+		.arg("--update-section")
+		.arg(format!("{}={}", KSYMS_SECTION, blob_path.display()))
+		.arg(elf)
+		.status()
+		.ok()?;
+	status.success().then_some(())
+}
+
+/// size of `section` from `rust-size -A`
+fn section_size(elf: &Path, section: &str) -> Option<usize> {
+	let output = Command::new("rust-size").arg("-A").arg(elf).output().ok()?;
+	String::from_utf8_lossy(&output.stdout)
+		.lines()
+		.find_map(|line| {
+			let mut fields = line.split_whitespace();
+			if fields.next()? != section {
+				return None;
+			}
+			fields.next()?.parse().ok()
+		})
+}
+
+/// Demangled function symbols sorted by address, and the address of `skernel`.
+fn text_symbols(elf: &Path) -> Option<(Vec<(u64, String)>, u64)> {
+	let output = Command::new("rust-nm")
+		.args(["--defined-only", "--numeric-sort"])
+		.arg(elf)
+		.output()
+		.ok()?;
+	if !output.status.success() {
+		return None;
+	}
+	let mut syms: Vec<(u64, String)> = Vec::new();
+	let mut link_base = 0;
+	for line in String::from_utf8_lossy(&output.stdout).lines() {
+		// "<addr> <type> <name>"
+		let mut fields = line.splitn(3, ' ');
+		let (Some(addr), Some(kind), Some(name)) = (fields.next(), fields.next(), fields.next()) else {
+			continue;
+		};
+		let Ok(addr) = u64::from_str_radix(addr, 16) else {
+			continue;
+		};
+		if name == "skernel" {
+			link_base = addr;
+		}
+		// local labels and mapping symbols are no functions
+		if !matches!(kind, "t" | "T" | "W") || name.starts_with(".L") || name.starts_with('$') {
+			continue;
+		}
+		// several names for one address, keep the first
+		if syms.last().is_some_and(|(last, _)| *last == addr) {
+			continue;
+		}
+		// without the hash suffix
+		syms.push((addr, format!("{:#}", rustc_demangle::demangle(name))));
+	}
+	Some((syms, link_base))
+}
+
+fn encode(syms: &[(u64, String)], link_base: u64) -> Vec<u8> {
+	let header_size = 16;
+	let entry_size = 16;
+	let mut blob = Vec::new();
+	blob.extend_from_slice(KSYMS_MAGIC);
+	blob.extend_from_slice(&(syms.len() as u32).to_le_bytes());
+	blob.extend_from_slice(&link_base.to_le_bytes());
+	let mut name_off = header_size + syms.len() * entry_size;
+	for (addr, name) in syms {
+		blob.extend_from_slice(&addr.to_le_bytes());
+		blob.extend_from_slice(&(name_off as u32).to_le_bytes());
+		blob.extend_from_slice(&(name.len() as u32).to_le_bytes());
+		name_off += name.len();
+	}
+	for (_, name) in syms {
+		blob.extend_from_slice(name.as_bytes());
+	}
+	blob
+}
@@ -6,12 +6,17 @@ use crate::kernel::KernelArg;
 use crate::user::UserArg;
 use crate::qemu::QemuArg;
 use crate::all::AllArg;
+use crate::coredump::CoreArg;
+use crate::crash::CrashArg;
 
 mod utils;
 mod kernel;
 mod user;
 mod qemu;
 mod all;
+mod ksyms;
+mod coredump;
+mod crash;
 mod logger;
 
 const KERNEL_PACKAGE_NAME: &str = "PianoOS";
@@ -41,6 +46,10 @@ enum Cmd {
 	Qemu(QemuArg),
 	/// Run all above
 	All(AllArg),
+	/// Extract the core dumps of killed apps from a console log
+	Core(CoreArg),
+	/// Show the crash dump of a kernel panic from a console log
+	Crash(CrashArg),
 }
 
 fn main() -> ExitCode {
@@ -55,6 +64,8 @@ fn main() -> ExitCode {
 		Cmd::User(arg) => user::run(arg),
 		Cmd::Qemu(arg) => qemu::run(arg),
 		Cmd::All(arg) => all::run(arg),
+		Cmd::Core(arg) => coredump::run(arg),
+		Cmd::Crash(arg) => crash::run(arg),
 	};
 
 	ExitCode::SUCCESS
@@ -21,6 +21,14 @@ pub struct QemuArg {
 	#[arg(long, default_value = "virt")]
 	pub machine: String,
 
+	/// Use the APLIC and IMSIC interrupt controllers instead of the PLIC
+	#[arg(long)]
+	pub aia: bool,
+
+	/// QEMU CPU model, e.g. "rv64,v=true" to give the harts the vector extension
+	#[arg(long)]
+	pub cpu: Option<String>,
+
 	#[arg(long, default_value = "./bootloader/rustsbi-qemu.bin")]
 	pub bios: PathBuf,
 
@@ -83,8 +91,14 @@ pub fn run(arg: &QemuArg) -> Option<ExitStatus> {
 		return run_gdb_client(arg)
 	}
 
+	let machine = if arg.aia {
+		format!("{},aia=aplic-imsic", arg.machine)
+	} else {
+		arg.machine.clone()
+	};
+
 	let mut cmd = Command::new(&qemu_bin);
-	cmd.arg("-machine").arg(&arg.machine)
+	cmd.arg("-machine").arg(&machine)
 		.arg("-smp").arg(arg.smp.to_string())
 		.arg("-bios").arg(&arg.bios)
 		.args(["-d", "mmu,int"])
@@ -94,6 +108,9 @@ pub fn run(arg: &QemuArg) -> Option<ExitStatus> {
 			&format!("loader,file={},addr={}", bin_path.display(), base_addr),
 		]);
 
+	if let Some(cpu) = &arg.cpu {
+		cmd.arg("-cpu").arg(cpu);
+	}
 	if !arg.gui {
 		cmd.arg("-nographic");
 	}